@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{MagicConverter, MagicFetcher, PropValue};
+
+/// Natural breaks are computed on at most this many evenly sampled values.
+const NATURAL_BREAKS_SAMPLES: usize = 1000;
+
+/// How a numeric prop is split into classes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Classification {
+    #[default]
+    EqualInterval,
+    Quantile,
+    /// Jenks natural breaks.
+    NaturalBreaks,
+    /// Lower bounds of every class but the first, taken in ascending order whatever order they
+    /// are listed in. NaN bounds are ignored.
+    Manual(Vec<f64>),
+}
+
+/// Maps a prop to a value, classified over the layer for numbers or looked up for strings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PropMap<T> {
+    /// `values` is the ramp; with `classes` above its length the ramp is interpolated evenly.
+    Classify {
+        prop: String,
+        #[serde(default)]
+        classification: Classification,
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        classes: Option<usize>,
+        values: Vec<T>,
+        /// Used when the prop is missing or not a number.
+        #[serde(default)]
+        default: T,
+        #[serde(skip)]
+        breaks: Vec<f64>,
+    },
+    Categorical {
+        prop: String,
+        values: HashMap<String, T>,
+        /// Used when the prop is missing or not listed.
+        #[serde(default)]
+        default: T,
+    },
+}
+
+impl<T> PropMap<T>
+where
+    T: MagicConverter + Clone,
+{
+    /// Computes class breaks from the props of every geometry in the layer.
+    pub fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) {
+        if let PropMap::Classify {
+            prop,
+            classification,
+            classes,
+            values,
+            breaks,
+            ..
+        } = self
+        {
+            let mut numbers: Vec<f64> = layer
+                .iter()
+                .filter_map(|props| props.get(prop.as_str())?.as_number())
+                .filter(|number| number.is_finite())
+                .collect();
+            numbers.sort_by(f64::total_cmp);
+            let classes = classes.unwrap_or(values.len()).max(1);
+            *breaks = match classification {
+                Classification::EqualInterval => {
+                    distinct_breaks(equal_interval_breaks(&numbers, classes), &numbers)
+                }
+                Classification::Quantile => {
+                    distinct_breaks(quantile_breaks(&numbers, classes), &numbers)
+                }
+                Classification::NaturalBreaks => {
+                    distinct_breaks(natural_breaks(&numbers, classes), &numbers)
+                }
+                Classification::Manual(manual) => {
+                    let mut manual: Vec<f64> = manual
+                        .iter()
+                        .copied()
+                        .filter(|lower| !lower.is_nan())
+                        .collect();
+                    manual.sort_by(f64::total_cmp);
+                    manual
+                }
+            };
+        }
+    }
+    pub fn evaluate(&self, props: &HashMap<String, PropValue>) -> T {
+        match self {
+            PropMap::Classify {
+                prop,
+                values,
+                default,
+                breaks,
+                ..
+            } => {
+                let Some(number) = props.get(prop).and_then(|value| value.as_number()) else {
+                    return default.clone();
+                };
+                let class = breaks.partition_point(|lower| *lower <= number);
+                ramp_value(values, class, breaks.len() + 1).unwrap_or_else(|| default.clone())
+            }
+            PropMap::Categorical {
+                prop,
+                values,
+                default,
+            } => props
+                .get(prop)
+                .and_then(|value| values.get(&value.to_string()))
+                .unwrap_or(default)
+                .clone(),
+        }
+    }
+}
+
+impl<T> MagicFetcher for PropMap<T>
+where
+    T: MagicFetcher,
+{
+    fn fetch(&mut self) -> Result<(), String> {
+        match self {
+            PropMap::Classify {
+                values, default, ..
+            } => {
+                for value in values.iter_mut() {
+                    value.fetch()?;
+                }
+                default.fetch()?;
+            }
+            PropMap::Categorical {
+                values, default, ..
+            } => {
+                for value in values.values_mut() {
+                    value.fetch()?;
+                }
+                default.fetch()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Value of `class` out of `classes`, spread evenly over the ramp `values`.
+fn ramp_value<T>(values: &[T], class: usize, classes: usize) -> Option<T>
+where
+    T: MagicConverter + Clone,
+{
+    if values.len() < 2 || classes < 2 {
+        return values.first().cloned();
+    }
+    if values.len() == classes {
+        return values.get(class).cloned();
+    }
+    let position = class as f64 / (classes - 1) as f64 * (values.len() - 1) as f64;
+    let lower = (position.floor() as usize).min(values.len() - 2);
+    let t = position - lower as f64;
+    let (lower, upper) = (&values[lower], &values[lower + 1]);
+    Some(lower.interpolate(upper, t).unwrap_or_else(|| {
+        if t < 0.5f64 {
+            lower.clone()
+        } else {
+            upper.clone()
+        }
+    }))
+}
+
+/// Drops breaks that would leave a class empty, as tied values or a single distinct value
+/// give, so every value lands in the lowest class it can.
+fn distinct_breaks(mut breaks: Vec<f64>, sorted: &[f64]) -> Vec<f64> {
+    let Some(min) = sorted.first() else {
+        return Vec::new();
+    };
+    breaks.dedup();
+    breaks.retain(|lower| lower > min);
+    breaks
+}
+
+fn equal_interval_breaks(sorted: &[f64], classes: usize) -> Vec<f64> {
+    let (Some(min), Some(max)) = (sorted.first(), sorted.last()) else {
+        return Vec::new();
+    };
+    let step = (max - min) / classes as f64;
+    (1..classes)
+        .map(|index| min + step * index as f64)
+        .collect()
+}
+
+fn quantile_breaks(sorted: &[f64], classes: usize) -> Vec<f64> {
+    if sorted.is_empty() {
+        return Vec::new();
+    }
+    (1..classes)
+        .map(|index| sorted[sorted.len() * index / classes])
+        .collect()
+}
+
+/// Jenks natural breaks, minimising the variance inside classes.
+fn natural_breaks(sorted: &[f64], classes: usize) -> Vec<f64> {
+    let values: Vec<f64> = if sorted.len() > NATURAL_BREAKS_SAMPLES {
+        (0..NATURAL_BREAKS_SAMPLES)
+            .map(|index| sorted[index * sorted.len() / NATURAL_BREAKS_SAMPLES])
+            .collect()
+    } else {
+        sorted.to_vec()
+    };
+    let n = values.len();
+    if n <= classes {
+        return values.iter().skip(1).copied().collect();
+    }
+    // lower[l][j] is the 1-based first value of class j when the first l values form j classes
+    let mut lower = vec![vec![0usize; classes + 1]; n + 1];
+    let mut variance = vec![vec![f64::INFINITY; classes + 1]; n + 1];
+    for j in 1..=classes {
+        lower[1][j] = 1;
+        variance[1][j] = 0f64;
+    }
+    for l in 2..=n {
+        let (mut sum, mut sum_squares, mut count) = (0f64, 0f64, 0f64);
+        let mut class_variance = 0f64;
+        for m in 1..=l {
+            let first = l - m + 1;
+            let value = values[first - 1];
+            sum += value;
+            sum_squares += value * value;
+            count += 1f64;
+            class_variance = sum_squares - sum * sum / count;
+            if first > 1 {
+                for j in 2..=classes {
+                    let total = class_variance + variance[first - 1][j - 1];
+                    if variance[l][j] > total {
+                        lower[l][j] = first;
+                        variance[l][j] = total;
+                    }
+                }
+            }
+        }
+        lower[l][1] = 1;
+        variance[l][1] = class_variance;
+    }
+    let mut breaks = vec![0f64; classes - 1];
+    let mut l = n;
+    for j in (2..=classes).rev() {
+        let first = lower[l][j];
+        breaks[j - 2] = values[first - 1];
+        l = first - 1;
+    }
+    breaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RenderContext;
+
+    fn layer(numbers: &[f64]) -> Vec<HashMap<String, PropValue>> {
+        numbers
+            .iter()
+            .map(|number| HashMap::from([("v".to_string(), PropValue::Float64(*number))]))
+            .collect()
+    }
+
+    fn classify(classification: Classification, classes: usize, numbers: &[f64]) -> PropMap<f64> {
+        let mut prop_map = PropMap::Classify {
+            prop: "v".to_string(),
+            classification,
+            classes: Some(classes),
+            values: (0..classes).map(|class| class as f64).collect(),
+            default: -1f64,
+            breaks: Vec::new(),
+        };
+        let layer = layer(numbers);
+        prop_map.prepare(&layer.iter().collect::<Vec<_>>());
+        prop_map
+    }
+
+    fn breaks(prop_map: &PropMap<f64>) -> &[f64] {
+        match prop_map {
+            PropMap::Classify { breaks, .. } => breaks,
+            PropMap::Categorical { .. } => &[],
+        }
+    }
+
+    fn class_of(prop_map: &PropMap<f64>, number: f64) -> f64 {
+        prop_map.evaluate(&layer(&[number])[0])
+    }
+
+    impl MagicFetcher for f64 {
+        fn fetch(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl MagicConverter for f64 {
+        fn convert(
+            &mut self,
+            _: &HashMap<String, PropValue>,
+            _: &RenderContext,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+        fn interpolate(&self, other: &Self, t: f64) -> Option<Self> {
+            Some(self + (other - self) * t)
+        }
+    }
+
+    #[test]
+    fn equal_interval_splits_the_range_evenly() {
+        let prop_map = classify(Classification::EqualInterval, 5, &[0f64, 3f64, 10f64]);
+        assert_eq!(breaks(&prop_map), &[2f64, 4f64, 6f64, 8f64]);
+        assert_eq!(class_of(&prop_map, 0f64), 0f64);
+        // class edges belong to the class above
+        assert_eq!(class_of(&prop_map, 2f64), 1f64);
+        assert_eq!(class_of(&prop_map, 10f64), 4f64);
+    }
+
+    #[test]
+    fn quantile_puts_equal_counts_in_each_class() {
+        let numbers: Vec<f64> = (1..=8).map(f64::from).collect();
+        let prop_map = classify(Classification::Quantile, 4, &numbers);
+        assert_eq!(breaks(&prop_map), &[3f64, 5f64, 7f64]);
+        let classes: Vec<f64> = numbers
+            .iter()
+            .map(|number| class_of(&prop_map, *number))
+            .collect();
+        assert_eq!(classes, [0f64, 0f64, 1f64, 1f64, 2f64, 2f64, 3f64, 3f64]);
+    }
+
+    #[test]
+    fn natural_breaks_find_gaps() {
+        let numbers = [1f64, 2f64, 3f64, 10f64, 11f64, 12f64, 20f64, 21f64, 22f64];
+        let prop_map = classify(Classification::NaturalBreaks, 3, &numbers);
+        assert_eq!(breaks(&prop_map), &[10f64, 20f64]);
+    }
+
+    #[test]
+    fn natural_breaks_with_fewer_values_than_classes() {
+        let prop_map = classify(Classification::NaturalBreaks, 4, &[1f64, 5f64]);
+        assert_eq!(breaks(&prop_map), &[5f64]);
+    }
+
+    #[test]
+    fn natural_breaks_sample_large_layers() {
+        let numbers: Vec<f64> = (0..5000)
+            .map(|index| if index < 2500 { 0f64 } else { 100f64 })
+            .collect();
+        let prop_map = classify(Classification::NaturalBreaks, 2, &numbers);
+        assert_eq!(breaks(&prop_map), &[100f64]);
+    }
+
+    #[test]
+    fn ties_do_not_leave_empty_classes() {
+        let prop_map = classify(Classification::Quantile, 2, &[1f64, 1f64, 1f64, 1f64, 2f64]);
+        assert!(breaks(&prop_map).is_empty());
+        assert_eq!(class_of(&prop_map, 1f64), 0f64);
+    }
+
+    #[test]
+    fn single_distinct_value_is_one_class() {
+        for classification in [
+            Classification::EqualInterval,
+            Classification::Quantile,
+            Classification::NaturalBreaks,
+        ] {
+            let prop_map = classify(classification, 3, &[5f64, 5f64, 5f64]);
+            assert!(breaks(&prop_map).is_empty());
+            assert_eq!(class_of(&prop_map, 5f64), 0f64);
+        }
+    }
+
+    #[test]
+    fn empty_layer_has_no_breaks() {
+        for classification in [
+            Classification::EqualInterval,
+            Classification::Quantile,
+            Classification::NaturalBreaks,
+        ] {
+            let prop_map = classify(classification, 3, &[]);
+            assert!(breaks(&prop_map).is_empty());
+            assert_eq!(class_of(&prop_map, 7f64), 0f64);
+        }
+    }
+
+    #[test]
+    fn manual_breaks_are_kept() {
+        let prop_map = classify(Classification::Manual(vec![10f64, 20f64]), 3, &[15f64]);
+        assert_eq!(breaks(&prop_map), &[10f64, 20f64]);
+        assert_eq!(class_of(&prop_map, 9.9f64), 0f64);
+        assert_eq!(class_of(&prop_map, 20f64), 2f64);
+    }
+
+    #[test]
+    fn ramp_is_interpolated_over_more_classes() {
+        let mut prop_map = classify(Classification::EqualInterval, 5, &[0f64, 10f64]);
+        if let PropMap::Classify { values, .. } = &mut prop_map {
+            *values = vec![0f64, 100f64];
+        }
+        assert_eq!(class_of(&prop_map, 5f64), 50f64);
+        assert_eq!(class_of(&prop_map, 10f64), 100f64);
+    }
+
+    #[test]
+    fn missing_prop_takes_the_default() {
+        let prop_map = classify(Classification::EqualInterval, 2, &[0f64, 10f64]);
+        assert_eq!(prop_map.evaluate(&HashMap::new()), -1f64);
+    }
+
+    #[test]
+    fn manual_breaks_are_sorted_and_skip_nan() {
+        let prop_map = classify(Classification::Manual(vec![20f64, f64::NAN, 10f64]), 3, &[]);
+        assert_eq!(breaks(&prop_map), [10f64, 20f64]);
+        assert_eq!(class_of(&prop_map, 5f64), 0f64);
+        assert_eq!(class_of(&prop_map, 10f64), 1f64);
+        assert_eq!(class_of(&prop_map, 15f64), 1f64);
+        assert_eq!(class_of(&prop_map, 25f64), 2f64);
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use vello::{
+    Scene,
+    kurbo::{Affine, BezPath, Rect},
+    peniko::{BlendMode, Color, Compose, Fill, Mix},
+};
+
+use crate::{
+    AreaRenderer, GeometryRenderer, MagicConverter, MagicFetcher, MagicValue, PropValue,
+    RasterLayer, RenderContext, RenderedGeometry, RenderedGeometryFilter,
+};
+
+/// How a group is composited with what is drawn beneath it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LayerBlend {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl From<LayerBlend> for Mix {
+    fn from(value: LayerBlend) -> Self {
+        match value {
+            LayerBlend::Normal => Mix::Normal,
+            LayerBlend::Multiply => Mix::Multiply,
+            LayerBlend::Screen => Mix::Screen,
+            LayerBlend::Overlay => Mix::Overlay,
+            LayerBlend::Darken => Mix::Darken,
+            LayerBlend::Lighten => Mix::Lighten,
+            LayerBlend::ColorDodge => Mix::ColorDodge,
+            LayerBlend::ColorBurn => Mix::ColorBurn,
+            LayerBlend::HardLight => Mix::HardLight,
+            LayerBlend::SoftLight => Mix::SoftLight,
+            LayerBlend::Difference => Mix::Difference,
+            LayerBlend::Exclusion => Mix::Exclusion,
+            LayerBlend::Hue => Mix::Hue,
+            LayerBlend::Saturation => Mix::Saturation,
+            LayerBlend::Color => Mix::Color,
+            LayerBlend::Luminosity => Mix::Luminosity,
+        }
+    }
+}
+
+impl MagicFetcher for LayerBlend {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for LayerBlend {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Rasters and renderers composited together, drawn in place of a [`GeometryRenderer::Group`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct LayerGroup {
+    pub opacity: MagicValue<PropValue>,
+    pub blend: MagicValue<LayerBlend>,
+    /// Geometries whose areas the group is clipped to, nothing is clipped when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clip: Option<MagicValue<RenderedGeometryFilter>>,
+    /// Keeps the group outside the clip areas instead of inside them.
+    #[serde(skip_serializing_if = "crate::utils::is_default")]
+    pub invert_clip: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rasters: Vec<MagicValue<RasterLayer>>,
+    pub renderers: Vec<MagicValue<GeometryRenderer>>,
+}
+
+impl std::default::Default for LayerGroup {
+    fn default() -> Self {
+        LayerGroup {
+            opacity: MagicValue::wrap(1f64),
+            blend: LayerBlend::Normal.into(),
+            clip: None,
+            invert_clip: false,
+            rasters: Vec::new(),
+            renderers: Vec::new(),
+        }
+    }
+}
+
+impl MagicFetcher for LayerGroup {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.opacity.fetch()?;
+        self.blend.fetch()?;
+        if let Some(clip) = self.clip.as_mut() {
+            clip.fetch()?;
+        }
+        for raster in self.rasters.iter_mut() {
+            raster.fetch()?;
+        }
+        for renderer in self.renderers.iter_mut() {
+            renderer.fetch()?;
+        }
+        Ok(())
+    }
+}
+
+impl MagicConverter for LayerGroup {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        // rasters and renderers are converted while drawing
+        self.opacity.convert(props, context)?;
+        self.blend.convert(props, context)?;
+        if let Some(clip) = self.clip.as_mut() {
+            clip.convert(props, context)?;
+        }
+        Ok(())
+    }
+}
+
+impl LayerGroup {
+    /// Draws the rasters, then the renderers through `draw_renderers`, into the group layer.
+    pub fn draw_with(
+        &mut self,
+        scene: &mut Scene,
+        transform: Affine,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
+        draw_renderers: impl FnOnce(
+            &mut Scene,
+            &mut [MagicValue<GeometryRenderer>],
+            &mut [RenderedGeometry],
+        ) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.convert(&Default::default(), context)?;
+        let clip_path = self.clip_path(context, rendered_geometrys)?;
+        self.push_layer(scene, transform, context.pixel_size, clip_path.as_ref())?;
+        for raster in self.rasters.iter_mut() {
+            raster.convert(&Default::default(), context)?;
+            raster
+                .as_ref()
+                .draw(scene, transform, context.tile_proj, context.pixel_size)?;
+        }
+        draw_renderers(scene, &mut self.renderers, rendered_geometrys)?;
+        self.pop_layer(scene, transform, context.pixel_size, clip_path.as_ref());
+        Ok(())
+    }
+    /// Map-space outline of the clip areas, `None` when the group is not clipped.
+    pub fn clip_path(
+        &self,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
+    ) -> Result<Option<BezPath>, String> {
+        let Some(clip) = self.clip.as_ref() else {
+            return Ok(None);
+        };
+        let mut path = BezPath::new();
+        for rendered_geometry in rendered_geometrys {
+            if rendered_geometry.fit_filter(clip.as_ref(), context)
+                && let Some(areas) = rendered_geometry.areas()
+            {
+                // rings are oriented alike, so overlapping areas join under the non-zero rule
+                for polygon in areas {
+                    path.extend(AreaRenderer::to_shape(polygon)?);
+                }
+            }
+        }
+        Ok(Some(path))
+    }
+    /// Starts the group layer, ended with [`LayerGroup::pop_layer`].
+    pub fn push_layer(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        pixel_size: (u32, u32),
+        clip_path: Option<&BezPath>,
+    ) -> Result<(), String> {
+        let opacity: f64 = self.opacity.inner_try_into()?;
+        let mix = self.layer_mix(opacity, clip_path.is_some());
+        let viewport = Rect::new(0f64, 0f64, pixel_size.0 as f64, pixel_size.1 as f64);
+        match clip_path {
+            Some(clip_path) if !self.invert_clip => {
+                scene.push_layer(mix, opacity as f32, transform, clip_path);
+            }
+            _ => scene.push_layer(mix, opacity as f32, Affine::IDENTITY, &viewport),
+        }
+        Ok(())
+    }
+    /// Mix the group layer is pushed with, [`Mix::Clip`] when it needs no blending.
+    fn layer_mix(&self, opacity: f64, clipped: bool) -> Mix {
+        let mix = Mix::from(*self.blend.as_ref());
+        // pure clips skip blending in vello, but draw straight into the layers beneath, which
+        // an inverted clip would then erase too
+        let inverted = self.invert_clip && clipped;
+        if mix == Mix::Normal && opacity >= 1f64 && !inverted {
+            Mix::Clip
+        } else {
+            mix
+        }
+    }
+    /// Ends the group layer, erasing the clip areas first when the clip is inverted.
+    pub fn pop_layer(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        pixel_size: (u32, u32),
+        clip_path: Option<&BezPath>,
+    ) {
+        if self.invert_clip
+            && let Some(clip_path) = clip_path
+        {
+            let viewport = Rect::new(0f64, 0f64, pixel_size.0 as f64, pixel_size.1 as f64);
+            scene.push_layer(
+                BlendMode::new(Mix::Normal, Compose::DestOut),
+                1f32,
+                transform,
+                clip_path,
+            );
+            scene.fill(
+                Fill::NonZero,
+                Affine::IDENTITY,
+                Color::BLACK,
+                None,
+                &viewport,
+            );
+            scene.pop_layer();
+        }
+        scene.pop_layer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{Geometry, Point, Rect as GeoRect, coord};
+    use vello::kurbo::Shape;
+
+    use super::*;
+
+    #[test]
+    fn only_plain_opaque_groups_skip_blending() {
+        let group = LayerGroup::default();
+        assert_eq!(group.layer_mix(1f64, false), Mix::Clip);
+        assert_eq!(group.layer_mix(1f64, true), Mix::Clip);
+        assert_eq!(group.layer_mix(0.5, false), Mix::Normal);
+        let multiply = LayerGroup {
+            blend: LayerBlend::Multiply.into(),
+            ..Default::default()
+        };
+        assert_eq!(multiply.layer_mix(1f64, false), Mix::Multiply);
+        let inverted = LayerGroup {
+            invert_clip: true,
+            ..Default::default()
+        };
+        assert_eq!(inverted.layer_mix(1f64, true), Mix::Normal);
+        // nothing to erase without a clip path
+        assert_eq!(inverted.layer_mix(1f64, false), Mix::Clip);
+    }
+
+    #[test]
+    fn clip_paths_take_the_areas_passing_the_filter() {
+        let context = RenderContext::default();
+        let area = |kind: &str, min: f64| {
+            let rect = GeoRect::new(
+                coord! { x: min, y: min },
+                coord! { x: min + 1f64, y: min + 1f64 },
+            );
+            let props = HashMap::from([("kind".to_string(), PropValue::from(kind))]);
+            RenderedGeometry::new_temp(props, Geometry::Rect(rect))
+        };
+        let mut geometries = vec![
+            area("lake", 0f64),
+            area("park", 5f64),
+            area("lake", 10f64),
+            RenderedGeometry::new_temp(
+                HashMap::from([("kind".to_string(), PropValue::from("lake"))]),
+                Geometry::Point(Point::new(20f64, 20f64)),
+            ),
+        ];
+        assert!(
+            LayerGroup::default()
+                .clip_path(&context, &mut geometries)
+                .unwrap()
+                .is_none()
+        );
+        let group = LayerGroup {
+            clip: Some(RenderedGeometryFilter::Eq("kind".into(), "lake".into()).into()),
+            ..Default::default()
+        };
+        let path = group.clip_path(&context, &mut geometries).unwrap().unwrap();
+        assert!((path.area().abs() - 2f64).abs() < 1e-9);
+        assert_eq!(path.bounding_box(), Rect::new(0f64, 0f64, 11f64, 11f64));
+    }
+}
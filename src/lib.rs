@@ -2,10 +2,20 @@ pub mod render_option;
 pub mod rendered_geometry;
 pub mod utils;
 pub use rendered_geometry::*;
+pub mod rendered_geometry_filter;
+pub use rendered_geometry_filter::*;
 pub mod renderer;
 pub use render_option::*;
 pub mod magic_value;
 pub use magic_value::*;
+pub mod placement;
+pub use placement::*;
+pub mod classification;
+pub use classification::*;
+pub mod raster;
+pub use raster::*;
+pub mod layer_group;
+pub use layer_group::*;
 pub use renderer::*;
 use vello::{
     Renderer,
@@ -15,7 +25,7 @@ use vello::{
 };
 
 pub fn render_to_texture(
-    geoms: &mut Vec<RenderedGeometry>,
+    geoms: &mut [RenderedGeometry],
     device: &Device,
     queue: &Queue,
     renderer: &mut Renderer,
@@ -27,20 +37,206 @@ pub fn render_to_texture(
     let rect = option.get_region_rect();
     let g_transform = option.get_view_transform(&rect);
     let g_transform = option.get_scale_transform(&rect) * g_transform;
-    for renderer in option.renderers.iter_mut() {
-        let renderer = renderer.as_mut();
-        renderer.draw(&mut scene, transform * g_transform, geoms, rect)?;
+    let transform = transform * g_transform;
+    let context = option.get_render_context();
+    let pixel_size = option.get_pixel_size();
+    if let Some(time_props) = option.time_props.as_ref() {
+        for geom in geoms.iter_mut() {
+            geom.apply_time_props(time_props);
+        }
     }
+    for raster in option.rasters.iter_mut() {
+        raster.convert(&Default::default(), &context)?;
+        raster
+            .as_ref()
+            .draw(&mut scene, transform, option.tile_proj, pixel_size)?;
+    }
+    let mut candidates = Vec::new();
+    let mut has_candidates = Vec::new();
+    let mut placement_order = Vec::new();
+    collect_placement_order(&mut option.renderers, &context, true, &mut placement_order);
+    for (index, (renderer, renderer_context, visible)) in placement_order.into_iter().enumerate() {
+        if !visible {
+            has_candidates.push(false);
+            continue;
+        }
+        renderer.prepare_layer(&renderer_context, geoms)?;
+        let renderer_candidates =
+            renderer.placement_candidates(index, transform, &renderer_context, geoms, rect)?;
+        has_candidates.push(renderer_candidates.is_some());
+        candidates.extend(renderer_candidates.unwrap_or_default());
+    }
+    let placed = place_candidates(candidates, option.get_placement_bounds());
+    let placement = (has_candidates.as_slice(), &placed);
+    draw_in_order(
+        &mut scene,
+        &mut option.renderers,
+        &mut 0,
+        transform,
+        &context,
+        geoms,
+        rect,
+        placement,
+    )?;
     let render_params = option.get_render_params();
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
     renderer
         .render_to_texture(device, queue, &scene, &view, &render_params)
-        .map_err(|e| format!("render error: {}", e.to_string()))?;
+        .map_err(|e| format!("render error: {}", e))?;
     Ok(())
 }
 
+/// Renderers in drawing order with group members in place of their group, as placement
+/// numbers them, with the context they are drawn in and whether they are visible. Groups are
+/// also flattened through the `Visible`, `Animated` and `Effect` renderers wrapping them.
+fn collect_placement_order<'a>(
+    renderers: &'a mut [MagicValue<GeometryRenderer>],
+    context: &RenderContext,
+    visible: bool,
+    order: &mut Vec<(&'a mut GeometryRenderer, RenderContext, bool)>,
+) {
+    for renderer in renderers.iter_mut() {
+        collect_renderer(renderer.as_mut(), context, visible, order);
+    }
+}
+
+fn collect_renderer<'a>(
+    renderer: &'a mut GeometryRenderer,
+    context: &RenderContext,
+    visible: bool,
+    order: &mut Vec<(&'a mut GeometryRenderer, RenderContext, bool)>,
+) {
+    if !renderer.contains_group() {
+        let visible = visible && renderer.is_visible(context);
+        order.push((renderer, *context, visible));
+        return;
+    }
+    match renderer {
+        GeometryRenderer::Group(group) => {
+            collect_placement_order(&mut group.renderers, context, visible, order)
+        }
+        GeometryRenderer::Visible(range, inner) => {
+            let visible = visible && range.as_ref().contains(context);
+            collect_renderer((**inner).as_mut(), context, visible, order);
+        }
+        GeometryRenderer::Animated(timeline, inner) => {
+            let context = timeline.as_ref().context(context);
+            collect_renderer((**inner).as_mut(), &context, visible, order);
+        }
+        GeometryRenderer::Effect(_, inner) => {
+            collect_renderer((**inner).as_mut(), context, visible, order);
+        }
+        _ => {}
+    }
+}
+
+/// Draws renderers first to last, each group in place, numbering them like
+/// [`collect_placement_order`].
+#[allow(clippy::too_many_arguments)]
+fn draw_in_order(
+    scene: &mut vello::Scene,
+    renderers: &mut [MagicValue<GeometryRenderer>],
+    next_index: &mut usize,
+    transform: Affine,
+    context: &RenderContext,
+    geoms: &mut [RenderedGeometry],
+    rect: Option<geo::Rect>,
+    placement: (&[bool], &PlacedGeometries),
+) -> Result<(), String> {
+    for renderer in renderers.iter_mut() {
+        draw_renderer_in_order(
+            scene,
+            renderer.as_mut(),
+            next_index,
+            transform,
+            context,
+            geoms,
+            rect,
+            placement,
+        )?;
+    }
+    Ok(())
+}
+
+/// Draws one renderer for [`draw_in_order`], descending into groups and the renderers
+/// wrapping them.
+#[allow(clippy::too_many_arguments)]
+fn draw_renderer_in_order(
+    scene: &mut vello::Scene,
+    renderer: &mut GeometryRenderer,
+    next_index: &mut usize,
+    transform: Affine,
+    context: &RenderContext,
+    geoms: &mut [RenderedGeometry],
+    rect: Option<geo::Rect>,
+    (has_candidates, placed): (&[bool], &PlacedGeometries),
+) -> Result<(), String> {
+    let placement = (has_candidates, placed);
+    match renderer {
+        GeometryRenderer::Group(group) => {
+            return group.draw_with(
+                scene,
+                transform,
+                context,
+                geoms,
+                |scene, renderers, geoms| {
+                    draw_in_order(
+                        scene, renderers, next_index, transform, context, geoms, rect, placement,
+                    )
+                },
+            );
+        }
+        GeometryRenderer::Visible(range, inner) if (**inner).as_ref().contains_group() => {
+            if !range.as_ref().contains(context) {
+                *next_index += (**inner).as_ref().placement_slots();
+                return Ok(());
+            }
+            let inner = (**inner).as_mut();
+            return draw_renderer_in_order(
+                scene, inner, next_index, transform, context, geoms, rect, placement,
+            );
+        }
+        GeometryRenderer::Animated(timeline, inner) if (**inner).as_ref().contains_group() => {
+            let context = timeline.as_ref().context(context);
+            let inner = (**inner).as_mut();
+            return draw_renderer_in_order(
+                scene, inner, next_index, transform, &context, geoms, rect, placement,
+            );
+        }
+        GeometryRenderer::Effect(effect, inner) if (**inner).as_ref().contains_group() => {
+            effect.convert(&Default::default(), context)?;
+            // every copy is drawn with the numbers of the members it copies
+            let first_index = *next_index;
+            return effect.as_ref().draw_with(
+                scene,
+                transform,
+                (**inner).as_mut(),
+                |scene, transform, renderer| {
+                    *next_index = first_index;
+                    draw_renderer_in_order(
+                        scene, renderer, next_index, transform, context, geoms, rect, placement,
+                    )
+                },
+            );
+        }
+        _ => {}
+    }
+    let index = *next_index;
+    *next_index += 1;
+    if !renderer.is_visible(context) {
+        return Ok(());
+    }
+    let empty = Default::default();
+    let placed = if has_candidates[index] {
+        Some(placed.get(&index).unwrap_or(&empty))
+    } else {
+        None
+    };
+    renderer.draw_placed(scene, transform, context, geoms, rect, placed)
+}
+
 pub fn render_to_texture_with_new_texture(
-    geoms: &mut Vec<RenderedGeometry>,
+    geoms: &mut [RenderedGeometry],
     device: &Device,
     queue: &Queue,
     renderer: &mut Renderer,
@@ -54,7 +250,7 @@ pub fn render_to_texture_with_new_texture(
 }
 
 pub fn render_to_buffer(
-    geoms: &mut Vec<RenderedGeometry>,
+    geoms: &mut [RenderedGeometry],
     device: &Device,
     queue: &Queue,
     renderer: &mut Renderer,
@@ -105,28 +301,28 @@ pub fn render_to_buffer(
     let (sender, receiver) = tokio::sync::oneshot::channel();
     buf_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
     if let Ok(recv_result) = block_on_wgpu(device, receiver) {
-        recv_result.map_err(|e| format!("recv data from gpu error: {}", e.to_string()))?;
+        recv_result.map_err(|e| format!("recv data from gpu error: {}", e))?;
     } else {
-        return Err(format!("recv data from gpu error: channel was closed"));
+        return Err("recv data from gpu error: channel was closed".to_string());
     }
     let data = buf_slice.get_mapped_range();
     let (width, height) = option.get_pixel_size();
     let mut result_unpadded = Vec::<u8>::with_capacity(
         (width * height * 4)
             .try_into()
-            .map_err(|_| format!("invalid capacity"))?,
+            .map_err(|_| "invalid capacity".to_string())?,
     );
     for row in 0..height {
         let start = (row * padded_byte_width)
             .try_into()
-            .map_err(|_| format!("write buffer error, too large"))?;
+            .map_err(|_| "write buffer error, too large".to_string())?;
         result_unpadded.extend(&data[start..start + (width * 4) as usize]);
     }
     Ok(result_unpadded)
 }
 
 pub fn render_to_buffer_with_new_texture(
-    geoms: &mut Vec<RenderedGeometry>,
+    geoms: &mut [RenderedGeometry],
     device: &Device,
     queue: &Queue,
     renderer: &mut Renderer,
@@ -137,3 +333,121 @@ pub fn render_to_buffer_with_new_texture(
     let texture = device.create_texture(&texture_desc);
     render_to_buffer(geoms, device, queue, renderer, &texture, transform, option)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(renderers: Vec<GeometryRenderer>) -> GeometryRenderer {
+        GeometryRenderer::Group(Box::new(LayerGroup {
+            renderers: renderers.into_iter().map(MagicValue::from).collect(),
+            ..Default::default()
+        }))
+    }
+
+    fn wrapped(
+        wrap: impl Fn(Box<MagicValue<GeometryRenderer>>) -> GeometryRenderer,
+        renderer: GeometryRenderer,
+    ) -> GeometryRenderer {
+        wrap(Box::new(renderer.into()))
+    }
+
+    /// Kinds of the flattened renderers with their animation time and visibility.
+    fn placement_order(
+        renderers: &mut [MagicValue<GeometryRenderer>],
+        context: &RenderContext,
+    ) -> Vec<(&'static str, f64, bool)> {
+        let mut order = Vec::new();
+        collect_placement_order(renderers, context, true, &mut order);
+        order
+            .iter()
+            .map(|(renderer, context, visible)| {
+                let kind = match renderer {
+                    GeometryRenderer::Point(..) => "point",
+                    GeometryRenderer::Line(..) => "line",
+                    GeometryRenderer::Area(..) => "area",
+                    GeometryRenderer::Visible(..) => "visible",
+                    _ => "other",
+                };
+                (kind, context.animation_time, *visible)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn placement_order_flattens_groups() {
+        let point = || GeometryRenderer::Point(Default::default(), Default::default());
+        let line = || GeometryRenderer::Line(Default::default(), Default::default());
+        let area = || GeometryRenderer::Area(Default::default(), Default::default());
+        let mut renderers: Vec<MagicValue<GeometryRenderer>> = vec![
+            point().into(),
+            group(vec![line(), group(vec![area()]), group(Vec::new())]).into(),
+            line().into(),
+        ];
+        let context = RenderContext::default();
+        let order = placement_order(&mut renderers, &context);
+        let kinds: Vec<&str> = order.iter().map(|(kind, _, _)| *kind).collect();
+        assert_eq!(kinds, ["point", "line", "area", "line"]);
+        assert!(order.iter().all(|(_, _, visible)| *visible));
+        let slots: usize = renderers
+            .iter()
+            .map(|renderer| renderer.as_ref().placement_slots())
+            .sum();
+        assert_eq!(slots, order.len());
+    }
+
+    #[test]
+    fn placement_order_flattens_wrapped_groups() {
+        let point = || GeometryRenderer::Point(Default::default(), Default::default());
+        let line = || GeometryRenderer::Line(Default::default(), Default::default());
+        let hidden = ZoomRange {
+            min_zoom: Some(10f64),
+            ..Default::default()
+        };
+        let timeline = Timeline {
+            origin: 5f64,
+            duration: 100f64,
+            ..Default::default()
+        };
+        let mut renderers: Vec<MagicValue<GeometryRenderer>> = vec![
+            wrapped(
+                |inner| GeometryRenderer::Visible(hidden.clone().into(), inner),
+                group(vec![point(), line()]),
+            )
+            .into(),
+            wrapped(
+                |inner| GeometryRenderer::Animated(timeline.clone().into(), inner),
+                group(vec![point()]),
+            )
+            .into(),
+            wrapped(
+                |inner| GeometryRenderer::Effect(Default::default(), inner),
+                wrapped(
+                    |inner| GeometryRenderer::Visible(Default::default(), inner),
+                    group(vec![line()]),
+                ),
+            )
+            .into(),
+            // wrappers around single renderers keep them whole
+            wrapped(
+                |inner| GeometryRenderer::Visible(Default::default(), inner),
+                point(),
+            )
+            .into(),
+        ];
+        let context = RenderContext {
+            animation_time: 20f64,
+            ..Default::default()
+        };
+        assert_eq!(
+            placement_order(&mut renderers, &context),
+            [
+                ("point", 20f64, false),
+                ("line", 20f64, false),
+                ("point", 15f64, true),
+                ("line", 20f64, true),
+                ("visible", 20f64, true),
+            ]
+        );
+    }
+}
@@ -2,22 +2,70 @@ use std::{collections::HashMap, convert::From};
 
 use peniko::Brush;
 use serde::{Deserialize, Serialize};
-use vello::kurbo::Stroke;
+use vello::kurbo::{Affine, Stroke};
+
+use crate::{PropMap, RenderContext};
 
 pub trait MagicConverter {
-    fn convert(&mut self, props: &HashMap<String, PropValue>) -> Result<(), String>;
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String>;
+    /// Value at `t` between `self` (0) and `other` (1), `None` if it can not be interpolated.
+    fn interpolate(&self, _other: &Self, _t: f64) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+    /// Sees the props of the whole layer before any `convert`, for values derived from it.
+    fn prepare(&mut self, _layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        Ok(())
+    }
+    /// Value taken verbatim from a prop, `None` if it can not be.
+    fn from_prop(_value: &PropValue) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
 impl MagicConverter for Brush {
-    fn convert(&mut self, _: &HashMap<String, PropValue>) -> Result<(), String> {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
         Ok(())
     }
+    fn interpolate(&self, other: &Self, t: f64) -> Option<Self> {
+        match (self, other) {
+            (Brush::Solid(a), Brush::Solid(b)) => Some(Brush::Solid(a.lerp_rect(*b, t as f32))),
+            _ => None,
+        }
+    }
 }
 
 impl MagicConverter for Stroke {
-    fn convert(&mut self, _: &HashMap<String, PropValue>) -> Result<(), String> {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
         Ok(())
     }
+    /// Blends the width, and the dashes when both strokes have as many, keeping the rest of
+    /// `self`.
+    fn interpolate(&self, other: &Self, t: f64) -> Option<Self> {
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        let mut stroke = self.clone();
+        stroke.width = lerp(self.width, other.width);
+        if self.dash_pattern.len() == other.dash_pattern.len() {
+            stroke.dash_offset = lerp(self.dash_offset, other.dash_offset);
+            for (dash, other) in stroke
+                .dash_pattern
+                .iter_mut()
+                .zip(other.dash_pattern.iter())
+            {
+                *dash = lerp(*dash, *other);
+            }
+        }
+        Some(stroke)
+    }
 }
 
 pub trait MagicFetcher {
@@ -42,6 +90,9 @@ pub enum StrEncoding {
     Ron,
     #[cfg(feature = "from_json")]
     Json,
+    /// Takes a prop as it is, for `PropValue` fields fed by plain props like
+    /// `icon = "hospital.svg"`.
+    Raw,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
@@ -56,6 +107,161 @@ pub enum MagicValueKind {
     Http(String, StrEncoding),
 }
 
+/// What zoom stops are keyed on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ZoomInput {
+    #[default]
+    Zoom,
+    ScaleDenominator,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Takes the value of the stop below.
+    #[default]
+    Step,
+    Linear,
+    /// Grows faster towards the upper stop for bases above 1.
+    Exponential(f64),
+}
+
+/// Values keyed on zoom or scale denominator, stops given in ascending order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoomStops<T> {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "crate::utils::is_default")]
+    pub input: ZoomInput,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "crate::utils::is_default")]
+    pub interpolation: Interpolation,
+    pub stops: Vec<(f64, T)>,
+}
+
+impl<T> ZoomStops<T>
+where
+    T: MagicConverter + Clone,
+{
+    pub fn evaluate(&self, context: &RenderContext) -> Option<T> {
+        let input = match self.input {
+            ZoomInput::Zoom => context.zoom,
+            ZoomInput::ScaleDenominator => context.scale_denominator,
+        };
+        evaluate_stops(&self.stops, input, |lower_stop, upper_stop| {
+            match self.interpolation {
+                Interpolation::Step => None,
+                Interpolation::Exponential(base) if base != 1f64 => Some(
+                    (base.powf(input - lower_stop) - 1f64)
+                        / (base.powf(upper_stop - lower_stop) - 1f64),
+                ),
+                Interpolation::Linear | Interpolation::Exponential(_) => {
+                    Some((input - lower_stop) / (upper_stop - lower_stop))
+                }
+            }
+        })
+    }
+}
+
+/// Value of ascending `stops` at `input`, `ease` giving the fraction between the two stops
+/// around it, or `None` to keep the lower one.
+fn evaluate_stops<T>(
+    stops: &[(f64, T)],
+    input: f64,
+    ease: impl Fn(f64, f64) -> Option<f64>,
+) -> Option<T>
+where
+    T: MagicConverter + Clone,
+{
+    let (first, last) = (stops.first()?, stops.last()?);
+    let upper = match stops.iter().position(|(stop, _)| *stop > input) {
+        Some(0) => return Some(first.1.clone()),
+        Some(upper) => upper,
+        None => return Some(last.1.clone()),
+    };
+    let (lower_stop, lower_value) = &stops[upper - 1];
+    let (upper_stop, upper_value) = &stops[upper];
+    let Some(t) = ease(*lower_stop, *upper_stop) else {
+        return Some(lower_value.clone());
+    };
+    Some(
+        lower_value
+            .interpolate(upper_value, t)
+            .unwrap_or_else(|| lower_value.clone()),
+    )
+}
+
+impl<T> MagicFetcher for ZoomStops<T>
+where
+    T: MagicFetcher,
+{
+    fn fetch(&mut self) -> Result<(), String> {
+        for (_, value) in self.stops.iter_mut() {
+            value.fetch()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Easing {
+    /// Holds each keyframe until the next one.
+    Step,
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Eased progress for linear progress `t` in `0..1`.
+    pub fn apply(&self, t: f64) -> Option<f64> {
+        match self {
+            Easing::Step => None,
+            Easing::Linear => Some(t),
+            Easing::EaseIn => Some(t * t * t),
+            Easing::EaseOut => Some(1f64 - (1f64 - t).powi(3)),
+            Easing::EaseInOut => Some(if t < 0.5f64 {
+                4f64 * t * t * t
+            } else {
+                1f64 - (2f64 - 2f64 * t).powi(3) / 2f64
+            }),
+        }
+    }
+}
+
+/// Values keyed on the animation time in seconds, keyframes given in ascending order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyframes<T> {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "crate::utils::is_default")]
+    pub easing: Easing,
+    pub frames: Vec<(f64, T)>,
+}
+
+impl<T> Keyframes<T>
+where
+    T: MagicConverter + Clone,
+{
+    pub fn evaluate(&self, context: &RenderContext) -> Option<T> {
+        evaluate_stops(&self.frames, context.animation_time, |lower, upper| {
+            self.easing
+                .apply((context.animation_time - lower) / (upper - lower))
+        })
+    }
+}
+
+impl<T> MagicFetcher for Keyframes<T>
+where
+    T: MagicFetcher,
+{
+    fn fetch(&mut self) -> Result<(), String> {
+        for (_, value) in self.frames.iter_mut() {
+            value.fetch()?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MagicValue<T> {
     #[serde(flatten)]
@@ -64,9 +270,23 @@ pub struct MagicValue<T> {
     #[serde(default)]
     #[serde(skip_serializing_if = "crate::utils::is_default")]
     kind: MagicValueKind,
+    /// Marks a size given in pixels, renderers scale it into map units so it stays constant
+    /// across zoom levels. Sizes are in map units otherwise.
     #[serde(default)]
     #[serde(skip_serializing_if = "crate::utils::is_default")]
     need_scale: bool,
+    /// Replaces the value by zoom, taking precedence over a `Prop` kind, which is then skipped.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zoom_stops: Option<ZoomStops<T>>,
+    /// Replaces the value by time, taking precedence over zoom stops.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keyframes: Option<Keyframes<T>>,
+    /// Replaces the value from props, taking precedence over keyframes and zoom stops.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prop_map: Option<PropMap<T>>,
 }
 
 impl<T> MagicValue<T>
@@ -78,6 +298,9 @@ where
             inner: Default::default(),
             kind: MagicValueKind::Ron(path),
             need_scale: false,
+            zoom_stops: None,
+            keyframes: None,
+            prop_map: None,
         }
     }
 }
@@ -88,15 +311,51 @@ impl<T> MagicValue<T> {
             inner,
             kind: MagicValueKind::Fixed,
             need_scale: false,
+            zoom_stops: None,
+            keyframes: None,
+            prop_map: None,
         }
     }
     pub fn unwrap(self) -> T {
         self.inner
     }
-    pub fn as_ref(&self) -> &T {
+    pub fn need_scale(&self) -> bool {
+        self.need_scale
+    }
+    pub fn with_need_scale(mut self, need_scale: bool) -> Self {
+        self.need_scale = need_scale;
+        self
+    }
+    pub fn with_zoom_stops(mut self, zoom_stops: ZoomStops<T>) -> Self {
+        self.zoom_stops = Some(zoom_stops);
+        self
+    }
+    pub fn with_keyframes(mut self, keyframes: Keyframes<T>) -> Self {
+        self.keyframes = Some(keyframes);
+        self
+    }
+    pub fn with_prop_map(mut self, prop_map: PropMap<T>) -> Self {
+        self.prop_map = Some(prop_map);
+        self
+    }
+    /// Factor turning the value into map units under `transform`.
+    pub fn map_unit_factor(&self, transform: Affine) -> f64 {
+        if self.need_scale {
+            1f64 / crate::utils::transform_scale(transform)
+        } else {
+            1f64
+        }
+    }
+}
+
+impl<T> AsRef<T> for MagicValue<T> {
+    fn as_ref(&self) -> &T {
         &self.inner
     }
-    pub fn as_mut(&mut self) -> &mut T {
+}
+
+impl<T> AsMut<T> for MagicValue<T> {
+    fn as_mut(&mut self) -> &mut T {
         &mut self.inner
     }
 }
@@ -110,6 +369,9 @@ where
             inner: Default::default(),
             kind: MagicValueKind::Fixed,
             need_scale: false,
+            zoom_stops: None,
+            keyframes: None,
+            prop_map: None,
         }
     }
 }
@@ -126,50 +388,103 @@ impl MagicValue<PropValue> {
     ) -> Result<T, String> {
         self.inner.clone().try_into()
     }
-    pub fn to_string(&self) -> String {
-        self.inner.clone().to_string()
+    /// Reads a length in map units.
+    pub fn map_length(&self, transform: Affine) -> Result<f64, String> {
+        let value: f64 = self.inner_try_into()?;
+        Ok(value * self.map_unit_factor(transform))
+    }
+    /// Reads a list of lengths, like a dash array, in map units.
+    pub fn map_lengths(&self, transform: Affine) -> Result<Vec<f64>, String> {
+        let values: Vec<f64> = self.inner_try_into()?;
+        let factor = self.map_unit_factor(transform);
+        Ok(values.into_iter().map(|value| value * factor).collect())
+    }
+    /// Reads a length in pixels.
+    pub fn pixel_length(&self, transform: Affine) -> Result<f64, String> {
+        Ok(self.map_length(transform)? * crate::utils::transform_scale(transform))
     }
     pub fn wrap<D: Into<PropValue>>(value: D) -> Self {
         Self {
             inner: Into::<PropValue>::into(value),
             kind: MagicValueKind::Fixed,
             need_scale: false,
+            zoom_stops: None,
+            keyframes: None,
+            prop_map: None,
+        }
+    }
+}
+
+impl MagicValue<Stroke> {
+    /// The stroke with width and dashes in map units.
+    pub fn map_stroke(&self, transform: Affine) -> Stroke {
+        let factor = self.map_unit_factor(transform);
+        if factor == 1f64 {
+            return self.inner.clone();
         }
+        let mut stroke = self.inner.clone();
+        stroke.width *= factor;
+        stroke.dash_offset *= factor;
+        stroke
+            .dash_pattern
+            .iter_mut()
+            .for_each(|dash| *dash *= factor);
+        stroke
+    }
+}
+
+impl std::fmt::Display for MagicValue<PropValue> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
     }
 }
 
 impl<T> MagicValue<T>
 where
-    T: for<'de> Deserialize<'de> + Default + MagicFetcher + MagicConverter,
+    T: for<'de> Deserialize<'de> + Default + Clone + MagicFetcher + MagicConverter,
 {
-    pub fn convert(&mut self, props: &HashMap<String, PropValue>) -> Result<(), String> {
-        match &self.kind {
-            MagicValueKind::Prop(name, encoding) => {
-                if let Some(value) = props.get(name) {
-                    let mut v: MagicValue<T> = match encoding {
-                        StrEncoding::Ron => ron::from_str(&value.to_string()).map_err(|e| {
-                            format!("Deserializing value from Prop:{} error: {}", name, e)
-                        })?,
-                        #[cfg(feature = "from_json")]
-                        StrEncoding::Json => {
-                            serde_json::from_str(&value.to_string()).map_err(|e| {
-                                format!("Deserializing value from Prop:{} error: {}", name, e)
-                            })?
-                        }
-                    };
-                    v.fetch()?;
-                    v.convert(props)?;
-                    self.inner = v.unwrap();
-                } else {
-                    return Err(format!("No {} found in props", name));
-                }
+    pub fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        if let Some(prop_map) = &self.prop_map {
+            self.inner = prop_map.evaluate(props);
+        } else if let Some(value) = self.keyframes.as_ref().and_then(|k| k.evaluate(context)) {
+            self.inner = value;
+        } else if let Some(value) = self.zoom_stops.as_ref().and_then(|z| z.evaluate(context)) {
+            self.inner = value;
+        } else if let MagicValueKind::Prop(name, encoding) = &self.kind {
+            if let Some(value) = props.get(name) {
+                let mut v: MagicValue<T> = match encoding {
+                    StrEncoding::Ron => ron::from_str(&value.to_string()).map_err(|e| {
+                        format!("Deserializing value from Prop:{} error: {}", name, e)
+                    })?,
+                    #[cfg(feature = "from_json")]
+                    StrEncoding::Json => serde_json::from_str(&value.to_string()).map_err(|e| {
+                        format!("Deserializing value from Prop:{} error: {}", name, e)
+                    })?,
+                    StrEncoding::Raw => T::from_prop(value)
+                        .ok_or_else(|| format!("Prop:{} can not be taken as it is", name))?
+                        .into(),
+                };
+                v.fetch()?;
+                v.convert(props, context)?;
+                self.inner = v.unwrap();
+            } else {
+                return Err(format!("No {} found in props", name));
             }
-            _ => {}
         }
         self.inner.fetch()?;
-        self.inner.convert(props)?;
+        self.inner.convert(props, context)?;
         Ok(())
     }
+    pub fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        if let Some(prop_map) = self.prop_map.as_mut() {
+            prop_map.prepare(layer);
+        }
+        self.inner.prepare(layer)
+    }
 }
 
 impl<T> MagicValue<T>
@@ -210,14 +525,26 @@ where
                     StrEncoding::Json => serde_json::from_str(&text).map_err(|e| {
                         format!("Deserializing value from Url:{} error: {}", url, e)
                     })?,
+                    StrEncoding::Raw => {
+                        return Err(format!("Url:{} can not be taken as it is", url));
+                    }
                 };
                 value.fetch()?;
                 Some(value.unwrap())
             }
             _ => None,
         };
-        if inner.is_some() {
-            self.inner = inner.unwrap();
+        if let Some(keyframes) = self.keyframes.as_mut() {
+            keyframes.fetch()?;
+        }
+        if let Some(zoom_stops) = self.zoom_stops.as_mut() {
+            zoom_stops.fetch()?;
+        }
+        if let Some(prop_map) = self.prop_map.as_mut() {
+            prop_map.fetch()?;
+        }
+        if let Some(inner) = inner {
+            self.inner = inner;
         }
         self.inner.fetch()?;
         Ok(())
@@ -234,6 +561,8 @@ pub enum PropValue {
     Int32(i32),
     Int64(i64),
     Boolean(bool),
+    Array(Vec<PropValue>),
+    Object(HashMap<String, PropValue>),
 }
 
 impl MagicFetcher for PropValue {
@@ -243,9 +572,17 @@ impl MagicFetcher for PropValue {
 }
 
 impl MagicConverter for PropValue {
-    fn convert(&mut self, _: &HashMap<String, PropValue>) -> Result<(), String> {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
         Ok(())
     }
+    fn interpolate(&self, other: &Self, t: f64) -> Option<Self> {
+        let a = self.as_number()?;
+        let b = other.as_number()?;
+        Some(PropValue::Float64(a + (b - a) * t))
+    }
+    fn from_prop(value: &PropValue) -> Option<Self> {
+        Some(value.clone())
+    }
 }
 
 impl From<&str> for PropValue {
@@ -272,22 +609,143 @@ impl From<f64> for PropValue {
     }
 }
 
+impl From<i32> for PropValue {
+    fn from(value: i32) -> Self {
+        PropValue::Int32(value)
+    }
+}
+
+impl From<i64> for PropValue {
+    fn from(value: i64) -> Self {
+        PropValue::Int64(value)
+    }
+}
+
 impl From<bool> for PropValue {
     fn from(value: bool) -> Self {
         PropValue::Boolean(value)
     }
 }
 
-impl ToString for PropValue {
-    fn to_string(&self) -> String {
+impl From<Vec<PropValue>> for PropValue {
+    fn from(value: Vec<PropValue>) -> Self {
+        PropValue::Array(value)
+    }
+}
+
+impl From<HashMap<String, PropValue>> for PropValue {
+    fn from(value: HashMap<String, PropValue>) -> Self {
+        PropValue::Object(value)
+    }
+}
+
+impl PropValue {
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            PropValue::Int32(v) => Some(*v as i64),
+            PropValue::Int64(v) => Some(*v),
+            _ => None,
+        }
+    }
+    pub(crate) fn as_number(&self) -> Option<f64> {
+        match self {
+            PropValue::Float64(v) => Some(*v),
+            PropValue::Float32(v) => Some(*v as f64),
+            PropValue::Int32(v) => Some(*v as f64),
+            PropValue::Int64(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+    /// Seconds since the Unix epoch, from a number or an RFC 3339 string.
+    pub fn as_timestamp(&self) -> Option<f64> {
+        match self {
+            PropValue::String(v) => v.trim().parse().ok().or_else(|| {
+                let date_time = time::OffsetDateTime::parse(
+                    v.trim(),
+                    &time::format_description::well_known::Rfc3339,
+                )
+                .ok()?;
+                Some(date_time.unix_timestamp_nanos() as f64 / 1e9)
+            }),
+            _ => self.as_number(),
+        }
+        .filter(|v| v.is_finite())
+    }
+    /// Nested values quote their strings so arrays and objects stay readable as RON.
+    fn fmt_nested(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropValue::String(v) => write!(f, "{:?}", v),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+/// Numbers compare by value across their widths; other kinds only compare with themselves.
+impl PartialEq for PropValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(std::cmp::Ordering::Equal)
+    }
+}
+
+impl PartialOrd for PropValue {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        if let (Some(a), Some(b)) = (self.as_int(), other.as_int()) {
+            return a.partial_cmp(&b);
+        }
+        if let (Some(a), Some(b)) = (self.as_number(), other.as_number()) {
+            return a.partial_cmp(&b);
+        }
+        match (self, other) {
+            (PropValue::None, PropValue::None) => Some(std::cmp::Ordering::Equal),
+            (PropValue::String(a), PropValue::String(b)) => a.partial_cmp(b),
+            (PropValue::Boolean(a), PropValue::Boolean(b)) => a.partial_cmp(b),
+            (PropValue::Array(a), PropValue::Array(b)) => a.partial_cmp(b),
+            (PropValue::Object(a), PropValue::Object(b)) => {
+                if a.len() == b.len() && a.iter().all(|(key, value)| b.get(key) == Some(value)) {
+                    Some(std::cmp::Ordering::Equal)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PropValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            PropValue::String(v) => v.clone(),
-            PropValue::Float64(v) => v.to_string(),
-            PropValue::Float32(v) => v.to_string(),
-            PropValue::Int32(v) => v.to_string(),
-            PropValue::Int64(v) => v.to_string(),
-            PropValue::Boolean(v) => v.to_string(),
-            PropValue::None => "None".to_string(),
+            PropValue::String(v) => write!(f, "{}", v),
+            PropValue::Float64(v) => write!(f, "{}", v),
+            PropValue::Float32(v) => write!(f, "{}", v),
+            PropValue::Int32(v) => write!(f, "{}", v),
+            PropValue::Int64(v) => write!(f, "{}", v),
+            PropValue::Boolean(v) => write!(f, "{}", v),
+            PropValue::None => write!(f, "None"),
+            PropValue::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.fmt_nested(f)?;
+                }
+                write!(f, "]")
+            }
+            PropValue::Object(values) => {
+                // Sorted keys so categories and templates see the same text on every run.
+                let mut entries: Vec<_> = values.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                write!(f, "{{")?;
+                for (index, (key, value)) in entries.into_iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: ", key)?;
+                    value.fmt_nested(f)?;
+                }
+                write!(f, "}}")
+            }
         }
     }
 }
@@ -303,6 +761,7 @@ impl TryFrom<PropValue> for String {
             PropValue::Int32(v) => Ok(v.to_string()),
             PropValue::Int64(v) => Ok(v.to_string()),
             PropValue::Boolean(v) => Ok(v.to_string()),
+            PropValue::Array(_) | PropValue::Object(_) => Ok(value.to_string()),
             PropValue::None => Err("Cannot convert None to String".to_string()),
         }
     }
@@ -313,7 +772,7 @@ impl TryFrom<PropValue> for f32 {
     fn try_from(value: PropValue) -> Result<Self, Self::Error> {
         match value {
             PropValue::String(v) => v.parse().map_err(|e: std::num::ParseFloatError| {
-                format!("Convert from string error: {}", e.to_string())
+                format!("Convert from string error: {}", e)
             }),
             PropValue::Float64(v) => Ok(v as f32),
             PropValue::Float32(v) => Ok(v),
@@ -326,6 +785,8 @@ impl TryFrom<PropValue> for f32 {
                     Ok(0.0)
                 }
             }
+            PropValue::Array(_) => Err("Cannot convert Array to f32".to_string()),
+            PropValue::Object(_) => Err("Cannot convert Object to f32".to_string()),
             PropValue::None => Err("Cannot convert None to f32".to_string()),
         }
     }
@@ -337,7 +798,7 @@ impl TryFrom<PropValue> for f64 {
     fn try_from(value: PropValue) -> Result<Self, Self::Error> {
         match value {
             PropValue::String(v) => v.parse().map_err(|e: std::num::ParseFloatError| {
-                format!("Convert from string error: {}", e.to_string())
+                format!("Convert from string error: {}", e)
             }),
             PropValue::Float64(v) => Ok(v),
             PropValue::Float32(v) => Ok(v as f64),
@@ -350,25 +811,205 @@ impl TryFrom<PropValue> for f64 {
                     Ok(0.0)
                 }
             }
+            PropValue::Array(_) => Err("Cannot convert Array to f64".to_string()),
+            PropValue::Object(_) => Err("Cannot convert Object to f64".to_string()),
             PropValue::None => Err("Cannot convert None to f64".to_string()),
         }
     }
 }
 
+/// Arrays convert item by item, strings like `"4 2"` or `"4,2"` split on commas and spaces.
+impl TryFrom<PropValue> for Vec<f64> {
+    type Error = String;
+
+    fn try_from(value: PropValue) -> Result<Self, Self::Error> {
+        match value {
+            PropValue::None => Ok(Vec::new()),
+            PropValue::Array(values) => values.into_iter().map(f64::try_from).collect(),
+            PropValue::String(v) => v
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|item| !item.is_empty())
+                .map(|item| {
+                    item.parse().map_err(|e: std::num::ParseFloatError| {
+                        format!("Convert from string error: {}", e)
+                    })
+                })
+                .collect(),
+            PropValue::Object(_) => Err("Cannot convert Object to Vec<f64>".to_string()),
+            value => Ok(vec![f64::try_from(value)?]),
+        }
+    }
+}
+
 impl TryFrom<PropValue> for bool {
     type Error = String;
 
     fn try_from(value: PropValue) -> Result<Self, Self::Error> {
         match value {
-            PropValue::String(v) => v.parse().map_err(|e: std::str::ParseBoolError| {
-                format!("Convert from string error: {}", e.to_string())
-            }),
+            PropValue::String(v) => v
+                .parse()
+                .map_err(|e: std::str::ParseBoolError| format!("Convert from string error: {}", e)),
             PropValue::Float64(v) => Ok(v != 0.0),
             PropValue::Float32(v) => Ok(v != 0.0),
             PropValue::Int32(v) => Ok(v != 0),
             PropValue::Int64(v) => Ok(v != 0),
             PropValue::Boolean(v) => Ok(v),
+            PropValue::Array(_) => Err("Cannot convert Array to bool".to_string()),
+            PropValue::Object(_) => Err("Cannot convert Object to bool".to_string()),
             PropValue::None => Err("Cannot convert None to bool".to_string()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zoomed(zoom: f64) -> RenderContext {
+        RenderContext {
+            zoom,
+            ..Default::default()
+        }
+    }
+
+    fn animated(animation_time: f64) -> RenderContext {
+        RenderContext {
+            animation_time,
+            ..Default::default()
+        }
+    }
+
+    fn number(value: Option<PropValue>) -> f64 {
+        value.and_then(|value| value.as_number()).unwrap()
+    }
+
+    fn zoom_stops(interpolation: Interpolation) -> ZoomStops<PropValue> {
+        ZoomStops {
+            input: ZoomInput::Zoom,
+            interpolation,
+            stops: vec![(5f64, 0.5f64.into()), (16f64, 6f64.into())],
+        }
+    }
+
+    #[test]
+    fn zoom_stops_hold_outside_and_step_between() {
+        let stops = zoom_stops(Interpolation::Step);
+        assert_eq!(number(stops.evaluate(&zoomed(2f64))), 0.5);
+        assert_eq!(number(stops.evaluate(&zoomed(10f64))), 0.5);
+        assert_eq!(number(stops.evaluate(&zoomed(16f64))), 6f64);
+        assert_eq!(number(stops.evaluate(&zoomed(20f64))), 6f64);
+    }
+
+    #[test]
+    fn zoom_stops_interpolate_linearly_and_exponentially() {
+        let linear = zoom_stops(Interpolation::Linear);
+        assert!((number(linear.evaluate(&zoomed(10.5))) - 3.25).abs() < 1e-9);
+        let exponential = zoom_stops(Interpolation::Exponential(2f64));
+        // (2^1 - 1) / (2^11 - 1) of the way up one zoom past the lower stop
+        let expected = 0.5 + 5.5 * (1f64 / 2047f64);
+        assert!((number(exponential.evaluate(&zoomed(6f64))) - expected).abs() < 1e-9);
+        // a base of 1 is linear
+        let flat = zoom_stops(Interpolation::Exponential(1f64));
+        assert!((number(flat.evaluate(&zoomed(10.5))) - 3.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zoom_stops_can_key_on_the_scale_denominator() {
+        let stops = ZoomStops {
+            input: ZoomInput::ScaleDenominator,
+            interpolation: Interpolation::Linear,
+            stops: vec![(1000f64, 1f64.into()), (3000f64, 3f64.into())],
+        };
+        let context = RenderContext {
+            scale_denominator: 2000f64,
+            ..Default::default()
+        };
+        assert!((number(stops.evaluate(&context)) - 2f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn strokes_blend_between_zoom_stops() {
+        let stops = ZoomStops {
+            input: ZoomInput::Zoom,
+            interpolation: Interpolation::Linear,
+            stops: vec![
+                (5f64, Stroke::new(0.5).with_dashes(0f64, [2f64, 2f64])),
+                (16f64, Stroke::new(6f64).with_dashes(4f64, [4f64, 8f64])),
+            ],
+        };
+        let stroke = stops.evaluate(&zoomed(10.5)).unwrap();
+        assert!((stroke.width - 3.25).abs() < 1e-9);
+        assert!((stroke.dash_offset - 2f64).abs() < 1e-9);
+        assert_eq!(stroke.dash_pattern.as_slice(), [3f64, 5f64]);
+        // dashes of different lengths keep the lower ones
+        let uneven =
+            Stroke::new(2f64).interpolate(&Stroke::new(4f64).with_dashes(0f64, [1f64]), 0.5);
+        let uneven = uneven.unwrap();
+        assert_eq!(uneven.width, 3f64);
+        assert!(uneven.dash_pattern.is_empty());
+    }
+
+    #[test]
+    fn keyframes_follow_their_easing() {
+        let at = |easing: Easing, time: f64| {
+            let keyframes = Keyframes {
+                easing,
+                frames: vec![(0f64, 0f64.into()), (10f64, 1f64.into())],
+            };
+            number(keyframes.evaluate(&animated(time)))
+        };
+        assert_eq!(at(Easing::Step, 9f64), 0f64);
+        assert_eq!(at(Easing::Step, 10f64), 1f64);
+        assert!((at(Easing::Linear, 2.5) - 0.25).abs() < 1e-9);
+        assert!((at(Easing::EaseIn, 5f64) - 0.125).abs() < 1e-9);
+        assert!((at(Easing::EaseOut, 5f64) - 0.875).abs() < 1e-9);
+        assert!((at(Easing::EaseInOut, 2.5) - 0.0625).abs() < 1e-9);
+        assert!((at(Easing::EaseInOut, 5f64) - 0.5).abs() < 1e-9);
+        assert!((at(Easing::EaseInOut, 7.5) - 0.9375).abs() < 1e-9);
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(at(easing, -1f64), 0f64);
+            assert_eq!(at(easing, 20f64), 1f64);
+        }
+    }
+
+    #[test]
+    fn convert_prefers_prop_map_then_keyframes_then_zoom_stops_then_prop() {
+        let props = HashMap::from([
+            ("kind".to_string(), PropValue::from("road")),
+            ("width".to_string(), PropValue::from(7f64)),
+        ]);
+        let context = RenderContext {
+            zoom: 16f64,
+            animation_time: 10f64,
+            ..Default::default()
+        };
+        let keyframes = Keyframes {
+            easing: Easing::Linear,
+            frames: vec![(0f64, 2f64.into()), (10f64, 2f64.into())],
+        };
+        let prop_map = PropMap::Categorical {
+            prop: "kind".to_string(),
+            values: HashMap::from([("road".to_string(), 1f64.into())]),
+            default: 0f64.into(),
+        };
+        let converted = |value: MagicValue<PropValue>| {
+            let mut value = value;
+            value.convert(&props, &context).unwrap();
+            number(Some(value.unwrap()))
+        };
+        let mut value = MagicValue::wrap(0f64);
+        value.kind = MagicValueKind::Prop("width".to_string(), StrEncoding::Raw);
+        assert_eq!(converted(value.clone()), 7f64);
+        let value = value.with_zoom_stops(zoom_stops(Interpolation::Linear));
+        assert_eq!(converted(value.clone()), 6f64);
+        let value = value.with_keyframes(keyframes);
+        assert_eq!(converted(value.clone()), 2f64);
+        let value = value.with_prop_map(prop_map);
+        assert_eq!(converted(value), 1f64);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{HashMap, HashSet};
+
+use vello::kurbo::Rect;
+
+/// Geometry indices allowed to draw, keyed by renderer index in `RenderOption.renderers`.
+pub type PlacedGeometries = HashMap<usize, HashSet<usize>>;
+
+/// A label or symbol box in pixel space waiting for a placement decision.
+#[derive(Debug, Clone)]
+pub struct PlacementCandidate {
+    pub renderer_index: usize,
+    pub geom_index: usize,
+    pub bbox: Rect,
+    pub priority: f64,
+    pub allow_overlap: bool,
+}
+
+const CELL_SIZE: f64 = 64f64;
+
+/// Occupancy index of already placed boxes, bucketed on a pixel grid.
+#[derive(Debug, Default)]
+pub struct CollisionIndex {
+    boxes: Vec<Rect>,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl CollisionIndex {
+    fn cells_of(bbox: &Rect) -> impl Iterator<Item = (i64, i64)> {
+        let min_x = (bbox.x0 / CELL_SIZE).floor() as i64;
+        let max_x = (bbox.x1 / CELL_SIZE).floor() as i64;
+        let min_y = (bbox.y0 / CELL_SIZE).floor() as i64;
+        let max_y = (bbox.y1 / CELL_SIZE).floor() as i64;
+        (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+    }
+    pub fn collides(&self, bbox: &Rect) -> bool {
+        Self::cells_of(bbox).any(|cell| {
+            self.cells.get(&cell).is_some_and(|indices| {
+                indices
+                    .iter()
+                    .any(|index| self.boxes[*index].overlaps(*bbox))
+            })
+        })
+    }
+    pub fn insert(&mut self, bbox: Rect) {
+        let index = self.boxes.len();
+        self.boxes.push(bbox);
+        for cell in Self::cells_of(&bbox) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+}
+
+/// Greedily places candidates by descending priority, ties broken by renderer then geometry order.
+///
+/// Candidates outside `bounds` are dropped before placement, so two tiles that share a buffered
+/// area see the same candidates there. Labels across their seam are decided alike as long as
+/// every label they contend with, directly or through others, lies within both buffers.
+pub fn place_candidates(mut candidates: Vec<PlacementCandidate>, bounds: Rect) -> PlacedGeometries {
+    candidates.retain(|candidate| candidate.bbox.overlaps(bounds));
+    candidates.sort_by(|a, b| {
+        b.priority
+            .total_cmp(&a.priority)
+            .then(a.renderer_index.cmp(&b.renderer_index))
+            .then(a.geom_index.cmp(&b.geom_index))
+    });
+    let mut index = CollisionIndex::default();
+    let mut placed = PlacedGeometries::new();
+    for candidate in candidates {
+        if candidate.allow_overlap || !index.collides(&candidate.bbox) {
+            index.insert(candidate.bbox);
+            placed
+                .entry(candidate.renderer_index)
+                .or_default()
+                .insert(candidate.geom_index);
+        }
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PixelOption, RenderOption, RenderRegion};
+
+    fn candidate(geom_index: usize, bbox: Rect, priority: f64) -> PlacementCandidate {
+        PlacementCandidate {
+            renderer_index: 0,
+            geom_index,
+            bbox,
+            priority,
+            allow_overlap: false,
+        }
+    }
+
+    fn placed_geoms(placed: &PlacedGeometries, renderer_index: usize) -> Vec<usize> {
+        let mut geoms: Vec<usize> = placed
+            .get(&renderer_index)
+            .map(|geoms| geoms.iter().copied().collect())
+            .unwrap_or_default();
+        geoms.sort();
+        geoms
+    }
+
+    #[test]
+    fn boxes_collide_across_cell_edges() {
+        let mut index = CollisionIndex::default();
+        // spans the cells on both sides of x = 64, and below zero
+        index.insert(Rect::new(60f64, -10f64, 70f64, 10f64));
+        assert!(index.collides(&Rect::new(68f64, 5f64, 100f64, 20f64)));
+        assert!(index.collides(&Rect::new(0f64, -20f64, 61f64, -5f64)));
+        // edges touching count as overlapping
+        assert!(index.collides(&Rect::new(70f64, 10f64, 80f64, 20f64)));
+        assert!(!index.collides(&Rect::new(71f64, 0f64, 80f64, 10f64)));
+        assert!(!index.collides(&Rect::new(-100f64, -100f64, -90f64, -90f64)));
+    }
+
+    #[test]
+    fn higher_priorities_place_first_and_ties_keep_order() {
+        let bbox = Rect::new(0f64, 0f64, 10f64, 10f64);
+        let placed = place_candidates(
+            vec![candidate(0, bbox, 1f64), candidate(1, bbox, 2f64)],
+            Rect::new(0f64, 0f64, 100f64, 100f64),
+        );
+        assert_eq!(placed_geoms(&placed, 0), [1]);
+        // equal priorities go to the lower renderer, then the lower geometry
+        let mut later = candidate(0, bbox, 1f64);
+        later.renderer_index = 1;
+        let placed = place_candidates(
+            vec![later, candidate(3, bbox, 1f64), candidate(2, bbox, 1f64)],
+            Rect::new(0f64, 0f64, 100f64, 100f64),
+        );
+        assert_eq!(placed_geoms(&placed, 0), [2]);
+        assert!(placed_geoms(&placed, 1).is_empty());
+    }
+
+    #[test]
+    fn overlapping_candidates_still_block_others() {
+        let bbox = Rect::new(0f64, 0f64, 10f64, 10f64);
+        let mut overlapping = candidate(1, bbox, 1f64);
+        overlapping.allow_overlap = true;
+        let placed = place_candidates(
+            vec![
+                candidate(0, bbox, 3f64),
+                overlapping,
+                candidate(2, Rect::new(5f64, 5f64, 20f64, 20f64), 0f64),
+            ],
+            Rect::new(0f64, 0f64, 100f64, 100f64),
+        );
+        assert_eq!(placed_geoms(&placed, 0), [0, 1]);
+    }
+
+    #[test]
+    fn tiles_buffer_placement_by_64_pixels() {
+        let option = |region: RenderRegion, placement_buffer: Option<f64>| RenderOption {
+            region,
+            pixel_option: PixelOption {
+                width: 256,
+                height: 256,
+                ..Default::default()
+            },
+            placement_buffer,
+            ..Default::default()
+        };
+        let tile = RenderRegion::TileIndex(1, 1, 2);
+        assert_eq!(
+            option(tile.clone(), None).get_placement_bounds(),
+            Rect::new(-64f64, -64f64, 320f64, 320f64)
+        );
+        assert_eq!(
+            option(RenderRegion::All, None).get_placement_bounds(),
+            Rect::new(0f64, 0f64, 256f64, 256f64)
+        );
+        assert_eq!(
+            option(tile, Some(8f64)).get_placement_bounds(),
+            Rect::new(-8f64, -8f64, 264f64, 264f64)
+        );
+    }
+
+    #[test]
+    fn neighbouring_tiles_agree_across_their_seam() {
+        // boxes in the pixels of a 512px wide strip made of two 256px tiles
+        let boxes = [
+            (Rect::new(240f64, 100f64, 270f64, 110f64), 5f64),
+            (Rect::new(260f64, 105f64, 290f64, 115f64), 3f64),
+            (Rect::new(200f64, 100f64, 230f64, 110f64), 1f64),
+            (Rect::new(300f64, 100f64, 330f64, 110f64), 4f64),
+            (Rect::new(250f64, 0f64, 262f64, 8f64), 2f64),
+            (Rect::new(170f64, 0f64, 252f64, 8f64), 6f64),
+            (Rect::new(600f64, 100f64, 620f64, 110f64), 9f64),
+        ];
+        let bounds = Rect::new(0f64, 0f64, 256f64, 256f64).inflate(64f64, 64f64);
+        let decide = |left: f64| {
+            let candidates = boxes
+                .iter()
+                .enumerate()
+                .map(|(geom_index, (bbox, priority))| {
+                    candidate(
+                        geom_index,
+                        *bbox - vello::kurbo::Vec2::new(left, 0f64),
+                        *priority,
+                    )
+                })
+                .collect();
+            placed_geoms(&place_candidates(candidates, bounds), 0)
+        };
+        let (left, right) = (decide(0f64), decide(256f64));
+        // what each tile shows of the labels reaching over the seam
+        let seam: Vec<usize> = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, (bbox, _))| bbox.x0 < 256f64 && bbox.x1 > 256f64)
+            .map(|(geom_index, _)| geom_index)
+            .collect();
+        assert_eq!(seam, [0, 4]);
+        let shown = |placed: &[usize]| -> Vec<usize> {
+            seam.iter()
+                .copied()
+                .filter(|geom_index| placed.contains(geom_index))
+                .collect()
+        };
+        assert_eq!(shown(&left), [0]);
+        assert_eq!(shown(&left), shown(&right));
+        assert!(left.contains(&2) && right.contains(&3));
+    }
+}
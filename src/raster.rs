@@ -0,0 +1,635 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use vello::{
+    Scene,
+    kurbo::{Affine, Point, Rect},
+    peniko::{Blob, Brush, Image, ImageFormat, ImageQuality},
+};
+
+use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, TileProj, utils};
+
+/// Decoded raster with its samples band-interleaved by pixel, rows top to bottom.
+#[derive(Debug)]
+pub struct RasterData {
+    pub width: usize,
+    pub height: usize,
+    pub bands: usize,
+    pub samples: Vec<f32>,
+    /// Largest sample of the pixel type, 1 for floats.
+    pub sample_max: f32,
+    /// Maps pixel corners, `(0, 0)` being the top left of the image, into raster coordinates.
+    pub pixel_to_map: Affine,
+    pub nodata: Option<f64>,
+}
+
+impl RasterData {
+    fn sample(&self, column: usize, row: usize, band: usize) -> f32 {
+        self.samples[(row * self.width + column) * self.bands + band.min(self.bands - 1)]
+    }
+    /// Bands at a pixel position, `None` outside the image or on no data.
+    fn pixel(
+        &self,
+        position: Point,
+        resampling: Resampling,
+        nodata: Option<f64>,
+    ) -> Option<Vec<f32>> {
+        if position.x < 0f64
+            || position.y < 0f64
+            || position.x >= self.width as f64
+            || position.y >= self.height as f64
+        {
+            return None;
+        }
+        // NaN samples are always no data, like GDAL's `nan` nodata which never compares equal
+        let is_nodata =
+            |value: f32| value.is_nan() || nodata.is_some_and(|nodata| value as f64 == nodata);
+        let nearest = (position.x as usize, position.y as usize);
+        if is_nodata(self.sample(nearest.0, nearest.1, 0)) {
+            return None;
+        }
+        let values: Vec<f32> = match resampling {
+            Resampling::Nearest => (0..self.bands)
+                .map(|band| self.sample(nearest.0, nearest.1, band))
+                .collect(),
+            Resampling::Bilinear => {
+                let x = (position.x - 0.5f64).clamp(0f64, (self.width - 1) as f64);
+                let y = (position.y - 0.5f64).clamp(0f64, (self.height - 1) as f64);
+                let (left, top) = (x as usize, y as usize);
+                let (right, bottom) = (
+                    (left + 1).min(self.width - 1),
+                    (top + 1).min(self.height - 1),
+                );
+                let (tx, ty) = ((x - left as f64) as f32, (y - top as f64) as f32);
+                let corners = [(left, top), (right, top), (left, bottom), (right, bottom)];
+                if corners
+                    .iter()
+                    .any(|(column, row)| is_nodata(self.sample(*column, *row, 0)))
+                {
+                    return None;
+                }
+                (0..self.bands)
+                    .map(|band| {
+                        let [a, b, c, d] =
+                            corners.map(|(column, row)| self.sample(column, row, band));
+                        let upper = a + (b - a) * tx;
+                        let lower = c + (d - c) * tx;
+                        upper + (lower - upper) * ty
+                    })
+                    .collect()
+            }
+        };
+        (!values.iter().any(|value| value.is_nan())).then_some(values)
+    }
+}
+
+/// Decoded rasters kept in memory, the least recently used dropped first.
+const RASTER_CACHE_SIZE: usize = 8;
+/// Raster pixels coloured for an affine draw, as a multiple of the output pixels. Larger
+/// windows are sampled per output pixel instead.
+const MAX_AFFINE_OVERSAMPLE: usize = 4;
+
+/// Cached rasters with the modification time of their file, most recently used last.
+type RasterCache = Vec<(String, SystemTime, Arc<RasterData>)>;
+
+fn raster_cache() -> &'static Mutex<RasterCache> {
+    static RASTER_CACHE: OnceLock<Mutex<RasterCache>> = OnceLock::new();
+    RASTER_CACHE.get_or_init(Default::default)
+}
+
+/// Loads a GeoTIFF, or a PNG/JPEG/TIFF georeferenced by its world file. Rasters are reloaded
+/// when their file changes.
+pub fn load_raster(path: &str) -> Result<Arc<RasterData>, String> {
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("Read raster from File:{} error: {}", path, e))?;
+    let lock = || {
+        raster_cache()
+            .lock()
+            .map_err(|e| format!("Raster cache poisoned: {}", e))
+    };
+    {
+        let mut cache = lock()?;
+        if let Some(index) = cache
+            .iter()
+            .position(|(cached, time, _)| cached == path && *time == modified)
+        {
+            let entry = cache.remove(index);
+            let raster = entry.2.clone();
+            cache.push(entry);
+            return Ok(raster);
+        }
+    }
+    // decoded without the lock, so other rasters render meanwhile
+    let lower = path.to_lowercase();
+    let raster = if lower.ends_with(".tif") || lower.ends_with(".tiff") {
+        read_geotiff(path)?
+    } else {
+        read_image(path)?
+    };
+    let raster = Arc::new(raster);
+    let mut cache = lock()?;
+    cache.retain(|(cached, _, _)| cached != path);
+    cache.push((path.to_string(), modified, raster.clone()));
+    if cache.len() > RASTER_CACHE_SIZE {
+        cache.remove(0);
+    }
+    Ok(raster)
+}
+
+#[cfg(feature = "image")]
+fn read_image(path: &str) -> Result<RasterData, String> {
+    let image =
+        image::open(path).map_err(|e| format!("Read raster from File:{} error: {}", path, e))?;
+    let bands = image.color().channel_count() as usize;
+    let (samples, sample_max): (Vec<f32>, f32) = if let Some(flat) = image.as_flat_samples_u8() {
+        (
+            flat.samples.iter().map(|v| *v as f32).collect(),
+            u8::MAX as f32,
+        )
+    } else if let Some(flat) = image.as_flat_samples_u16() {
+        (
+            flat.samples.iter().map(|v| *v as f32).collect(),
+            u16::MAX as f32,
+        )
+    } else if let Some(flat) = image.as_flat_samples_f32() {
+        (flat.samples.to_vec(), 1f32)
+    } else {
+        return Err(format!("Unsupported pixel type of raster File:{}", path));
+    };
+    Ok(RasterData {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        bands,
+        samples,
+        sample_max,
+        pixel_to_map: read_world_file(path)?,
+        nodata: None,
+    })
+}
+
+#[cfg(not(feature = "image"))]
+fn read_image(path: &str) -> Result<RasterData, String> {
+    Err(format!(
+        "Read raster from File:{} error: PNG and JPEG rasters need the image feature",
+        path
+    ))
+}
+
+fn read_geotiff(path: &str) -> Result<RasterData, String> {
+    use tiff::{
+        decoder::{Decoder, DecodingResult},
+        tags::Tag,
+    };
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Read raster from File:{} error: {}", path, e))?;
+    let error = |e: tiff::TiffError| format!("Decode GeoTIFF File:{} error: {}", path, e);
+    let mut decoder = Decoder::new(std::io::BufReader::new(file)).map_err(error)?;
+    let (width, height) = decoder.dimensions().map_err(error)?;
+    let (width, height) = (width as usize, height as usize);
+    let transformation = decoder
+        .get_tag_f64_vec(Tag::ModelTransformationTag)
+        .ok()
+        .filter(|m| m.len() >= 8);
+    let tiepoint = decoder
+        .get_tag_f64_vec(Tag::ModelTiepointTag)
+        .ok()
+        .filter(|t| t.len() >= 6);
+    let scale = decoder
+        .get_tag_f64_vec(Tag::ModelPixelScaleTag)
+        .ok()
+        .filter(|s| s.len() >= 2);
+    let pixel_to_map = match (transformation, tiepoint, scale) {
+        (Some(m), _, _) => Affine::new([m[0], m[4], m[1], m[5], m[3], m[7]]),
+        (None, Some(t), Some(s)) => Affine::new([
+            s[0],
+            0f64,
+            0f64,
+            -s[1],
+            t[3] - t[0] * s[0],
+            t[4] + t[1] * s[1],
+        ]),
+        _ => read_world_file(path)?,
+    };
+    let nodata = decoder
+        .get_tag_ascii_string(Tag::GdalNodata)
+        .ok()
+        .and_then(|nodata| nodata.trim_matches(char::from(0)).trim().parse().ok());
+    let (samples, sample_max): (Vec<f32>, f32) = match decoder.read_image().map_err(error)? {
+        DecodingResult::U8(v) => (v.into_iter().map(|v| v as f32).collect(), u8::MAX as f32),
+        DecodingResult::U16(v) => (v.into_iter().map(|v| v as f32).collect(), u16::MAX as f32),
+        DecodingResult::U32(v) => (v.into_iter().map(|v| v as f32).collect(), u32::MAX as f32),
+        DecodingResult::U64(v) => (v.into_iter().map(|v| v as f32).collect(), u64::MAX as f32),
+        DecodingResult::I8(v) => (v.into_iter().map(|v| v as f32).collect(), i8::MAX as f32),
+        DecodingResult::I16(v) => (v.into_iter().map(|v| v as f32).collect(), i16::MAX as f32),
+        DecodingResult::I32(v) => (v.into_iter().map(|v| v as f32).collect(), i32::MAX as f32),
+        DecodingResult::I64(v) => (v.into_iter().map(|v| v as f32).collect(), i64::MAX as f32),
+        DecodingResult::F32(v) => (v, 1f32),
+        DecodingResult::F64(v) => (v.into_iter().map(|v| v as f32).collect(), 1f32),
+    };
+    let bands = (samples.len() / (width * height).max(1)).max(1);
+    Ok(RasterData {
+        width,
+        height,
+        bands,
+        samples,
+        sample_max,
+        pixel_to_map,
+        nodata,
+    })
+}
+
+/// Reads the world file next to `path`, e.g. `.pgw`, `.pngw` or `.wld` for a `.png`.
+fn read_world_file(path: &str) -> Result<Affine, String> {
+    let image_path = Path::new(path);
+    let extension = image_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut candidates = vec![format!("{}w", extension), "wld".to_string()];
+    if let (Some(first), Some(last)) = (extension.chars().next(), extension.chars().last()) {
+        candidates.insert(0, format!("{}{}w", first, last));
+    }
+    let world_path = candidates
+        .into_iter()
+        .map(|extension| image_path.with_extension(extension))
+        .find(|world_path| world_path.exists())
+        .ok_or(format!("No world file found for raster File:{}", path))?;
+    let content = std::fs::read_to_string(&world_path).map_err(|e| {
+        format!(
+            "Read world file from File:{} error: {}",
+            world_path.display(),
+            e
+        )
+    })?;
+    let values: Vec<f64> = content
+        .split_whitespace()
+        .map(|value| value.parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| {
+            format!(
+                "Parse world file File:{} error: {}",
+                world_path.display(),
+                e
+            )
+        })?;
+    let [a, d, b, e, c, f] = values[..] else {
+        return Err(format!(
+            "World file File:{} needs 6 values",
+            world_path.display()
+        ));
+    };
+    // world files locate pixel centers
+    Ok(Affine::new([a, d, b, e, c, f]) * Affine::translate((-0.5f64, -0.5f64)))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Resampling {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/// How raster bands become colours. Band indices start at 0.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub enum RasterStyle {
+    /// Gray, gray and alpha, RGB or RGBA bands used as they are.
+    #[default]
+    Image,
+    /// Bands stretched from `min..max` to full red, green and blue.
+    Bands {
+        red: usize,
+        green: usize,
+        blue: usize,
+        min: f64,
+        max: f64,
+    },
+    /// One band mapped over `min..max` onto a colour ramp with stops from 0 to 1.
+    Ramp {
+        band: usize,
+        min: f64,
+        max: f64,
+        ramp: Vec<(f64, Brush)>,
+    },
+}
+
+impl RasterStyle {
+    fn color(&self, raster: &RasterData, values: &[f32], table: &[[u8; 4]]) -> [u8; 4] {
+        let value = |band: usize| values[band.min(values.len() - 1)] as f64;
+        let stretch = |value: f64, min: f64, max: f64| {
+            if max > min {
+                ((value - min) / (max - min)).clamp(0f64, 1f64)
+            } else {
+                0f64
+            }
+        };
+        let byte = |t: f64| (t * 255f64).round() as u8;
+        match self {
+            RasterStyle::Image => {
+                let channel =
+                    |band: usize| byte(stretch(value(band), 0f64, raster.sample_max as f64));
+                match values.len() {
+                    1 => [channel(0), channel(0), channel(0), 255],
+                    2 => [channel(0), channel(0), channel(0), channel(1)],
+                    3 => [channel(0), channel(1), channel(2), 255],
+                    _ => [channel(0), channel(1), channel(2), channel(3)],
+                }
+            }
+            RasterStyle::Bands {
+                red,
+                green,
+                blue,
+                min,
+                max,
+            } => [
+                byte(stretch(value(*red), *min, *max)),
+                byte(stretch(value(*green), *min, *max)),
+                byte(stretch(value(*blue), *min, *max)),
+                255,
+            ],
+            RasterStyle::Ramp { band, min, max, .. } => {
+                table[byte(stretch(value(*band), *min, *max)) as usize]
+            }
+        }
+    }
+}
+
+/// A georeferenced image drawn into the render region.
+///
+/// Rasters in the render's `tile_proj` are drawn with an affine transform. Others are
+/// reprojected on the CPU, point by point for every output pixel, on each tile and frame.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RasterLayer {
+    pub path: String,
+    /// Projection of the raster coordinates, the render's `tile_proj` when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proj: Option<TileProj>,
+    pub opacity: MagicValue<PropValue>,
+    pub style: RasterStyle,
+    pub resampling: Resampling,
+    /// Band 0 value drawn transparent, read from GeoTIFFs when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nodata: Option<f64>,
+}
+
+impl std::default::Default for RasterLayer {
+    fn default() -> Self {
+        RasterLayer {
+            path: String::new(),
+            proj: None,
+            opacity: MagicValue::wrap(1f64),
+            style: RasterStyle::Image,
+            resampling: Resampling::Bilinear,
+            nodata: None,
+        }
+    }
+}
+
+impl MagicFetcher for RasterLayer {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.opacity.fetch()?;
+        Ok(())
+    }
+}
+
+impl MagicConverter for RasterLayer {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.opacity.convert(props, context)?;
+        Ok(())
+    }
+}
+
+impl RasterLayer {
+    /// Draws the raster over the `width` by `height` pixel image that `transform` maps the
+    /// `tile_proj` map space into.
+    pub fn draw(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        tile_proj: TileProj,
+        (width, height): (u32, u32),
+    ) -> Result<(), String> {
+        let opacity: f64 = self.opacity.inner_try_into()?;
+        if opacity <= 0f64 || width == 0 || height == 0 {
+            return Ok(());
+        }
+        let raster = load_raster(&self.path)?;
+        if raster.width == 0 || raster.height == 0 || raster.bands == 0 {
+            return Ok(());
+        }
+        let table = match &self.style {
+            RasterStyle::Ramp { ramp, .. } => utils::ramp_table(ramp),
+            _ => Vec::new(),
+        };
+        let nodata = self.nodata.or(raster.nodata);
+        let raster_proj = self.proj.unwrap_or(tile_proj);
+        if raster_proj == tile_proj
+            && let Some(window) = affine_window(&raster, transform, (width, height))
+        {
+            if window.is_zero_area() {
+                return Ok(());
+            }
+            let (column, row) = (window.x0 as usize, window.y0 as usize);
+            let (window_width, window_height) = (window.width() as usize, window.height() as usize);
+            let mut data = vec![0u8; window_width * window_height * 4];
+            for y in 0..window_height {
+                for x in 0..window_width {
+                    let center =
+                        Point::new((column + x) as f64 + 0.5f64, (row + y) as f64 + 0.5f64);
+                    let Some(values) = raster.pixel(center, Resampling::Nearest, nodata) else {
+                        continue;
+                    };
+                    let [r, g, b, a] = self.style.color(&raster, &values, &table);
+                    let offset = (y * window_width + x) * 4;
+                    data[offset..offset + 4].copy_from_slice(&[
+                        r,
+                        g,
+                        b,
+                        (a as f64 * opacity.min(1f64)).round() as u8,
+                    ]);
+                }
+            }
+            let quality = match self.resampling {
+                Resampling::Nearest => ImageQuality::Low,
+                Resampling::Bilinear => ImageQuality::Medium,
+            };
+            let image = Image::new(
+                Blob::new(Arc::new(data)),
+                ImageFormat::Rgba8,
+                window_width as u32,
+                window_height as u32,
+            )
+            .with_quality(quality);
+            scene.draw_image(
+                &image,
+                transform * raster.pixel_to_map * Affine::translate((window.x0, window.y0)),
+            );
+            return Ok(());
+        }
+        let to_map = transform.inverse();
+        let to_pixel = raster.pixel_to_map.inverse();
+        let mut data = vec![0u8; width as usize * height as usize * 4];
+        for row in 0..height as usize {
+            for column in 0..width as usize {
+                let point = to_map * Point::new(column as f64 + 0.5f64, row as f64 + 0.5f64);
+                let (x, y) = utils::transform_point(point.x, point.y, &tile_proj, &raster_proj);
+                let Some(values) =
+                    raster.pixel(to_pixel * Point::new(x, y), self.resampling, nodata)
+                else {
+                    continue;
+                };
+                let [r, g, b, a] = self.style.color(&raster, &values, &table);
+                let offset = (row * width as usize + column) * 4;
+                data[offset..offset + 4].copy_from_slice(&[
+                    r,
+                    g,
+                    b,
+                    (a as f64 * opacity.min(1f64)).round() as u8,
+                ]);
+            }
+        }
+        let image = Image::new(Blob::new(Arc::new(data)), ImageFormat::Rgba8, width, height);
+        scene.draw_image(&image, Affine::IDENTITY);
+        Ok(())
+    }
+}
+
+/// Whole raster pixels covering the output image, `None` when too many to colour at once.
+fn affine_window(
+    raster: &RasterData,
+    transform: Affine,
+    (width, height): (u32, u32),
+) -> Option<Rect> {
+    let to_raster = transform * raster.pixel_to_map;
+    if to_raster.determinant() == 0f64 {
+        return None;
+    }
+    let viewport = Rect::new(0f64, 0f64, width as f64, height as f64);
+    let window = to_raster.inverse().transform_rect_bbox(viewport);
+    let window = Rect::new(
+        window.x0.floor().max(0f64),
+        window.y0.floor().max(0f64),
+        window.x1.ceil().min(raster.width as f64),
+        window.y1.ceil().min(raster.height as f64),
+    );
+    if window.x0 >= window.x1 || window.y0 >= window.y1 {
+        return Some(Rect::ZERO);
+    }
+    let pixels = window.width() * window.height();
+    (pixels <= (width as usize * height as usize * MAX_AFFINE_OVERSAMPLE) as f64).then_some(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raster(width: usize, height: usize, samples: Vec<f32>) -> RasterData {
+        RasterData {
+            width,
+            height,
+            bands: samples.len() / (width * height),
+            samples,
+            sample_max: 1f32,
+            pixel_to_map: Affine::IDENTITY,
+            nodata: None,
+        }
+    }
+
+    #[test]
+    fn world_files_locate_pixel_centers() {
+        let dir = std::env::temp_dir().join(format!("geello-world-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("hillshade.png");
+        // 10 map units per pixel, north up, first pixel centred on (1005, 1995)
+        std::fs::write(dir.join("hillshade.pgw"), "10\n0\n0\n-10\n1005\n1995\n").unwrap();
+        let pixel_to_map = read_world_file(image.to_str().unwrap()).unwrap();
+        assert_eq!(
+            pixel_to_map * Point::new(0f64, 0f64),
+            Point::new(1000f64, 2000f64)
+        );
+        assert_eq!(
+            pixel_to_map * Point::new(0.5, 0.5),
+            Point::new(1005f64, 1995f64)
+        );
+        assert_eq!(
+            pixel_to_map * Point::new(2f64, 3f64),
+            Point::new(1020f64, 1970f64)
+        );
+        std::fs::write(dir.join("hillshade.pgw"), "10 0 0").unwrap();
+        assert!(read_world_file(image.to_str().unwrap()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(read_world_file(image.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn bilinear_pixels_blend_the_nearest_centers() {
+        let data = raster(2, 2, vec![0f32, 10f32, 20f32, 30f32]);
+        let at = |x: f64, y: f64, resampling| data.pixel(Point::new(x, y), resampling, None);
+        assert_eq!(at(0.5, 0.5, Resampling::Bilinear), Some(vec![0f32]));
+        assert_eq!(at(1f64, 0.5, Resampling::Bilinear), Some(vec![5f32]));
+        assert_eq!(at(1f64, 1f64, Resampling::Bilinear), Some(vec![15f32]));
+        // edges hold the outer pixels
+        assert_eq!(at(0.1, 1.9, Resampling::Bilinear), Some(vec![20f32]));
+        assert_eq!(at(1f64, 1f64, Resampling::Nearest), Some(vec![30f32]));
+        assert_eq!(at(2f64, 0.5, Resampling::Bilinear), None);
+        assert_eq!(at(-0.1, 0.5, Resampling::Nearest), None);
+    }
+
+    #[test]
+    fn nodata_and_nan_pixels_are_empty() {
+        let data = raster(2, 2, vec![0f32, -9999f32, f32::NAN, 30f32]);
+        let at =
+            |x: f64, y: f64, resampling| data.pixel(Point::new(x, y), resampling, Some(-9999f64));
+        assert_eq!(at(0.5, 0.5, Resampling::Nearest), Some(vec![0f32]));
+        assert_eq!(at(1.5, 0.5, Resampling::Nearest), None);
+        assert_eq!(at(0.5, 1.5, Resampling::Nearest), None);
+        // bilinear samples touching no data are empty too
+        assert_eq!(at(1f64, 1f64, Resampling::Bilinear), None);
+        assert_eq!(at(1.5, 1.5, Resampling::Bilinear), Some(vec![30f32]));
+        // NaN is no data without a nodata value, and in any band
+        assert_eq!(
+            data.pixel(Point::new(0.5, 1.5), Resampling::Nearest, None),
+            None
+        );
+        let bands = raster(1, 1, vec![1f32, f32::NAN, 3f32]);
+        assert_eq!(
+            bands.pixel(Point::new(0.5, 0.5), Resampling::Nearest, None),
+            None
+        );
+    }
+
+    #[test]
+    fn affine_windows_cover_the_viewport() {
+        let mut data = raster(100, 100, vec![0f32; 100 * 100]);
+        // raster pixels are 2 map units, the output shows map units 10..42 at 1px per unit
+        data.pixel_to_map = Affine::scale(2f64);
+        let transform = Affine::translate((-10f64, -10f64));
+        assert_eq!(
+            affine_window(&data, transform, (32, 32)),
+            Some(Rect::new(5f64, 5f64, 21f64, 21f64))
+        );
+        // clipped to the raster
+        let transform = Affine::translate((-190f64, -10f64));
+        assert_eq!(
+            affine_window(&data, transform, (32, 32)),
+            Some(Rect::new(95f64, 5f64, 100f64, 21f64))
+        );
+        // outside it
+        let transform = Affine::translate((-500f64, 0f64));
+        assert_eq!(affine_window(&data, transform, (32, 32)), Some(Rect::ZERO));
+        // zoomed far out, too many raster pixels to colour at once
+        let transform = Affine::scale(0.05);
+        assert_eq!(affine_window(&data, transform, (4, 4)), None);
+        assert_eq!(affine_window(&data, Affine::scale(0f64), (4, 4)), None);
+    }
+}
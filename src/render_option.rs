@@ -1,8 +1,13 @@
+use std::collections::HashMap;
+
 use geo::Rect;
 use peniko::color::{AlphaColor, Srgb};
 use vello::{kurbo::Affine, wgpu, wgpu::Extent3d};
 
-use crate::{GeometryRenderer, MagicFetcher, MagicValue, utils};
+use crate::{
+    GeometryRenderer, MagicConverter, MagicFetcher, MagicValue, PropValue, RasterLayer, TimeProps,
+    utils,
+};
 
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct PixelOption {
@@ -30,6 +35,7 @@ pub enum TileProj {
 
 #[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum RenderRegion {
+    /// Everything, drawn untransformed at zoom 0.
     #[default]
     All,
     Rect(Rect),
@@ -48,6 +54,122 @@ impl RenderRegion {
     }
 }
 
+/// Zoom level, scale denominator and times of the current render, used by zoom and time
+/// driven values.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderContext {
+    pub zoom: f64,
+    pub scale_denominator: f64,
+    /// Epoch seconds of the data shown, for temporal filters and trails.
+    pub time: f64,
+    /// Seconds since the animation started, or on the enclosing timeline, for keyframes.
+    pub animation_time: f64,
+    /// Projection of the tile grid, for rasters drawn within groups.
+    pub tile_proj: TileProj,
+    /// Width and height of the image, for rasters and layers drawn within groups.
+    pub pixel_size: (u32, u32),
+}
+
+/// Zoom and scale denominator limits, minimums inclusive and maximums exclusive.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ZoomRange {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_zoom: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_zoom: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_scale_denominator: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_scale_denominator: Option<f64>,
+}
+
+impl ZoomRange {
+    pub fn contains(&self, context: &RenderContext) -> bool {
+        self.min_zoom.is_none_or(|min| context.zoom >= min)
+            && self.max_zoom.is_none_or(|max| context.zoom < max)
+            && self
+                .min_scale_denominator
+                .is_none_or(|min| context.scale_denominator >= min)
+            && self
+                .max_scale_denominator
+                .is_none_or(|max| context.scale_denominator < max)
+    }
+}
+
+impl MagicFetcher for ZoomRange {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for ZoomRange {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Repeat {
+    /// Holds the first and last frames outside the timeline.
+    #[default]
+    Once,
+    Loop,
+    /// Plays forwards and backwards in turn.
+    PingPong,
+}
+
+/// Maps animation time onto an animation starting at `origin` and lasting `duration` seconds,
+/// both measured from the start of the animation rather than as epoch seconds.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Timeline {
+    pub origin: f64,
+    pub duration: f64,
+    pub repeat: Repeat,
+}
+
+impl Timeline {
+    /// Seconds into the timeline at animation time `time`.
+    pub fn local_time(&self, time: f64) -> f64 {
+        let elapsed = time - self.origin;
+        if self.duration <= 0f64 {
+            return elapsed.max(0f64);
+        }
+        match self.repeat {
+            Repeat::Once => elapsed.clamp(0f64, self.duration),
+            Repeat::Loop => elapsed.rem_euclid(self.duration),
+            Repeat::PingPong => {
+                let elapsed = elapsed.rem_euclid(self.duration * 2f64);
+                if elapsed > self.duration {
+                    self.duration * 2f64 - elapsed
+                } else {
+                    elapsed
+                }
+            }
+        }
+    }
+    /// `context` with its animation time moved onto the timeline.
+    pub fn context(&self, context: &RenderContext) -> RenderContext {
+        RenderContext {
+            animation_time: self.local_time(context.animation_time),
+            ..*context
+        }
+    }
+}
+
+impl MagicFetcher for Timeline {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for Timeline {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct RenderOption {
     #[serde(default)]
@@ -64,6 +186,28 @@ pub struct RenderOption {
     #[serde(default)]
     #[serde(skip_serializing_if = "crate::utils::is_default")]
     pub need_proj_geom: bool,
+    /// Props the time range of features is read from while rendering, unless one was given.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_props: Option<TimeProps>,
+    /// Pixels around the region where labels still take part in collision placement,
+    /// defaults to 64 for `TileIndex` so neighbouring tiles agree, 0 otherwise.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "crate::utils::is_default")]
+    pub placement_buffer: Option<f64>,
+    /// Epoch seconds of the data shown, for temporal filters and trails.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "crate::utils::is_default")]
+    pub time: f64,
+    /// Seconds since the animation started, so animated styles render any frame
+    /// deterministically.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "crate::utils::is_default")]
+    pub animation_time: f64,
+    /// Georeferenced images drawn under every renderer, first to last.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rasters: Vec<MagicValue<RasterLayer>>,
 }
 
 impl MagicFetcher for RenderOption {
@@ -71,6 +215,9 @@ impl MagicFetcher for RenderOption {
         for renderer in self.renderers.iter_mut() {
             renderer.fetch()?;
         }
+        for raster in self.rasters.iter_mut() {
+            raster.fetch()?;
+        }
         Ok(())
     }
 }
@@ -93,9 +240,52 @@ impl RenderOption {
             Affine::IDENTITY
         }
     }
+    /// Zoom follows the tile index for `TileIndex`, otherwise it is derived from the region and
+    /// pixel size as if the region were a 256px tiled map. `All` has no region to derive them
+    /// from, so zoom and scale denominator stay 0 and ranges with a minimum hide everything.
+    pub fn get_render_context(&self) -> RenderContext {
+        // OGC standardized rendering pixel size, 0.28mm
+        const PIXEL_SIZE_METERS: f64 = 0.00028;
+        let world_height = utils::EPSG3857_XY_MAX * 2f64;
+        let (world_width, meters_per_unit) = match self.tile_proj {
+            TileProj::EPSG4326 => (180f64, world_height / 360f64),
+            TileProj::EPSG3857 => (world_height, 1f64),
+        };
+        let rect = self.get_region_rect();
+        if rect.is_none() {
+            return RenderContext {
+                time: self.time,
+                animation_time: self.animation_time,
+                tile_proj: self.tile_proj,
+                pixel_size: self.get_pixel_size(),
+                ..Default::default()
+            };
+        }
+        let resolution = 1f64 / utils::transform_scale(self.get_scale_transform(&rect));
+        let zoom = match self.region {
+            RenderRegion::TileIndex(_, _, z) => z as f64,
+            _ => (world_width / 256f64 / resolution).log2(),
+        };
+        RenderContext {
+            zoom,
+            scale_denominator: resolution * meters_per_unit / PIXEL_SIZE_METERS,
+            time: self.time,
+            animation_time: self.animation_time,
+            tile_proj: self.tile_proj,
+            pixel_size: self.get_pixel_size(),
+        }
+    }
     pub fn get_region_rect(&self) -> Option<Rect> {
         self.region.get_rect(&self.tile_proj)
     }
+    pub fn get_placement_bounds(&self) -> vello::kurbo::Rect {
+        let buffer = self.placement_buffer.unwrap_or(match self.region {
+            RenderRegion::TileIndex(..) => 64f64,
+            _ => 0f64,
+        });
+        let (width, height) = self.get_pixel_size();
+        vello::kurbo::Rect::new(0f64, 0f64, width as f64, height as f64).inflate(buffer, buffer)
+    }
     pub fn get_pixel_size(&self) -> (u32, u32) {
         (self.pixel_option.width, self.pixel_option.height)
     }
@@ -106,7 +296,7 @@ impl RenderOption {
             depth_or_array_layers: 1,
         }
     }
-    pub fn get_texture_descriptor(&self) -> wgpu::TextureDescriptor {
+    pub fn get_texture_descriptor(&self) -> wgpu::TextureDescriptor<'_> {
         wgpu::TextureDescriptor {
             label: Some("Rendered Texture"),
             size: self.get_extent3d(),
@@ -133,3 +323,134 @@ impl RenderOption {
         self.get_padded_byte_width() as u64 * self.pixel_option.height as u64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timelines_leave_the_data_time_alone() {
+        let timeline = Timeline {
+            origin: 2f64,
+            duration: 10f64,
+            repeat: Repeat::Loop,
+        };
+        let context = RenderContext {
+            time: 1_700_000_000f64,
+            animation_time: 15f64,
+            ..Default::default()
+        };
+        let moved = timeline.context(&context);
+        assert_eq!(moved.time, context.time);
+        assert_eq!(moved.animation_time, 3f64);
+    }
+
+    fn option(region: RenderRegion, tile_proj: TileProj, size: u32) -> RenderOption {
+        RenderOption {
+            region,
+            tile_proj,
+            pixel_option: PixelOption {
+                width: size,
+                height: size,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn tiles_use_their_zoom_and_scale() {
+        let context =
+            option(RenderRegion::TileIndex(3, 2, 3), TileProj::EPSG3857, 256).get_render_context();
+        assert_eq!(context.zoom, 3f64);
+        // 559082264.03 at zoom 0 on the well known scale set, halving per level
+        assert_close(context.scale_denominator, 559082264.0287178 / 8f64);
+        // a 512px tile keeps the zoom of its index, but at half the scale denominator
+        let large =
+            option(RenderRegion::TileIndex(3, 2, 3), TileProj::EPSG3857, 512).get_render_context();
+        assert_eq!(large.zoom, 3f64);
+        assert_close(large.scale_denominator, context.scale_denominator / 2f64);
+    }
+
+    #[test]
+    fn regions_derive_the_zoom_from_their_resolution() {
+        let tile = utils::get_rect_from_xyz(1, 1, 2, &TileProj::EPSG3857);
+        let context =
+            option(RenderRegion::Rect(tile), TileProj::EPSG3857, 256).get_render_context();
+        assert_close(context.zoom, 2f64);
+        assert_close(context.scale_denominator, 559082264.0287178 / 4f64);
+        let context =
+            option(RenderRegion::Rect(tile), TileProj::EPSG3857, 512).get_render_context();
+        assert_close(context.zoom, 3f64);
+        // a degree spans as many meters at the equator in both projections
+        let tile = utils::get_rect_from_xyz(0, 0, 0, &TileProj::EPSG4326);
+        let context =
+            option(RenderRegion::Rect(tile), TileProj::EPSG4326, 256).get_render_context();
+        assert_close(context.zoom, 0f64);
+        assert_close(context.scale_denominator, 559082264.0287178 / 2f64);
+    }
+
+    #[test]
+    fn all_renders_at_zoom_zero() {
+        let context = option(RenderRegion::All, TileProj::EPSG3857, 256).get_render_context();
+        assert_eq!((context.zoom, context.scale_denominator), (0f64, 0f64));
+        let range = ZoomRange {
+            min_zoom: Some(1f64),
+            ..Default::default()
+        };
+        assert!(!range.contains(&context));
+    }
+
+    #[test]
+    fn zoom_ranges_include_minimums_and_exclude_maximums() {
+        let range = ZoomRange {
+            min_zoom: Some(5f64),
+            max_zoom: Some(10f64),
+            min_scale_denominator: Some(1000f64),
+            max_scale_denominator: Some(2000f64),
+        };
+        let context = |zoom, scale_denominator| RenderContext {
+            zoom,
+            scale_denominator,
+            ..Default::default()
+        };
+        assert!(range.contains(&context(5f64, 1000f64)));
+        assert!(range.contains(&context(9.99, 1999f64)));
+        assert!(!range.contains(&context(4.99, 1500f64)));
+        assert!(!range.contains(&context(10f64, 1500f64)));
+        assert!(!range.contains(&context(7f64, 999f64)));
+        assert!(!range.contains(&context(7f64, 2000f64)));
+        assert!(ZoomRange::default().contains(&context(0f64, 0f64)));
+    }
+
+    #[test]
+    fn scaled_lengths_stay_in_pixels() {
+        let tile = RenderRegion::TileIndex(0, 0, 1);
+        let option = option(tile, TileProj::EPSG3857, 256);
+        let rect = option.get_region_rect();
+        let transform = option.get_scale_transform(&rect) * option.get_view_transform(&rect);
+        let meters_per_pixel = utils::EPSG3857_XY_MAX / 256f64;
+        let pixels = MagicValue::wrap(10f64).with_need_scale(true);
+        assert_close(
+            pixels.map_length(transform).unwrap(),
+            10f64 * meters_per_pixel,
+        );
+        assert_close(pixels.pixel_length(transform).unwrap(), 10f64);
+        let meters = MagicValue::wrap(10f64 * meters_per_pixel);
+        assert_close(meters.pixel_length(transform).unwrap(), 10f64);
+        let dashes = MagicValue::wrap(vec![PropValue::Float64(4f64), PropValue::Float64(2f64)])
+            .with_need_scale(true);
+        let dashes = dashes.map_lengths(transform).unwrap();
+        assert_close(dashes[0], 4f64 * meters_per_pixel);
+        assert_close(dashes[1], 2f64 * meters_per_pixel);
+    }
+}
@@ -1,12 +1,35 @@
 use std::collections::HashMap;
 
-use crate::{PropValue, RenderedGeometryFilter, TileProj};
+use crate::{PropValue, RenderContext, RenderedGeometryFilter, TileProj};
 use geo::{
     BooleanOps, BoundingRect, Centroid, Contains, ConvexHull, CoordsIter, Geometry, InteriorPoint,
     Intersects, LineString, MultiLineString, MultiPoint, MultiPolygon, Point, Polygon, Rect,
 };
+/// Props holding when a feature is valid, as epoch seconds or RFC 3339 strings.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TimeProps {
+    pub start: String,
+    /// The feature is an instant at `start` when not set.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+}
+
+impl TimeProps {
+    pub fn time_range(&self, props: &HashMap<String, PropValue>) -> Option<(f64, f64)> {
+        let start = props.get(&self.start)?.as_timestamp()?;
+        let end = match &self.end {
+            Some(end) => props.get(end)?.as_timestamp()?,
+            None => start,
+        };
+        Some((start.min(end), start.max(end)))
+    }
+}
+
 pub struct RenderedGeometry {
     layer: Option<String>,
+    id: Option<PropValue>,
+    time_range: Option<(f64, f64)>,
     props: HashMap<String, PropValue>,
     inner_geom: Geometry,
     center_point: Option<Point>,
@@ -32,6 +55,8 @@ impl RenderedGeometry {
         }
         RenderedGeometry {
             layer,
+            id: None,
+            time_range: None,
             inner_geom,
             props,
             center_point: None,
@@ -42,10 +67,39 @@ impl RenderedGeometry {
             has_calc_areas: false,
         }
     }
+    pub fn with_id(mut self, id: Option<PropValue>) -> Self {
+        self.id = id;
+        self
+    }
+    pub fn id(&self) -> Option<&PropValue> {
+        self.id.as_ref()
+    }
+    pub fn with_time_range(mut self, time_range: Option<(f64, f64)>) -> Self {
+        self.time_range = time_range;
+        self
+    }
+    /// Reads the time range from props unless one was given.
+    pub(crate) fn apply_time_props(&mut self, time_props: &TimeProps) {
+        if self.time_range.is_none() {
+            self.time_range = time_props.time_range(&self.props);
+        }
+    }
+    /// Inclusive start and end in epoch seconds, `None` for features valid at any time.
+    pub fn time_range(&self) -> Option<(f64, f64)> {
+        self.time_range
+    }
+    /// Also true for features without a time range.
+    fn valid_during(&self, start: f64, end: f64) -> bool {
+        self.time_range
+            .is_none_or(|(self_start, self_end)| self_start <= end && self_end >= start)
+    }
+    pub fn geom(&self) -> &Geometry {
+        &self.inner_geom
+    }
     pub fn props(&self) -> &HashMap<String, PropValue> {
         &self.props
     }
-    pub fn fit_filter(&self, filter: &RenderedGeometryFilter) -> bool {
+    pub fn fit_filter(&self, filter: &RenderedGeometryFilter, context: &RenderContext) -> bool {
         match filter {
             RenderedGeometryFilter::None => true,
             RenderedGeometryFilter::Layer(other_layer) => {
@@ -55,6 +109,45 @@ impl RenderedGeometry {
                     true
                 }
             }
+            RenderedGeometryFilter::Eq(name, value) => self.props.get(name) == Some(value),
+            RenderedGeometryFilter::Ne(name, value) => self.props.get(name) != Some(value),
+            RenderedGeometryFilter::Lt(name, value) => {
+                self.props.get(name).is_some_and(|prop| prop < value)
+            }
+            RenderedGeometryFilter::Le(name, value) => {
+                self.props.get(name).is_some_and(|prop| prop <= value)
+            }
+            RenderedGeometryFilter::Gt(name, value) => {
+                self.props.get(name).is_some_and(|prop| prop > value)
+            }
+            RenderedGeometryFilter::Ge(name, value) => {
+                self.props.get(name).is_some_and(|prop| prop >= value)
+            }
+            RenderedGeometryFilter::In(name, values) => self
+                .props
+                .get(name)
+                .is_some_and(|prop| values.contains(prop)),
+            RenderedGeometryFilter::Has(name) => self.props.contains_key(name),
+            RenderedGeometryFilter::Match(name, regex) => self
+                .props
+                .get(name)
+                .is_some_and(|prop| regex.is_match(&prop.to_string())),
+            RenderedGeometryFilter::GeometryType(kind) => kind.fit(&self.inner_geom),
+            RenderedGeometryFilter::At(time) => {
+                let time = time.unwrap_or(context.time);
+                self.valid_during(time, time)
+            }
+            RenderedGeometryFilter::During(start, end) => self.valid_during(*start, *end),
+            RenderedGeometryFilter::Around(before, after) => {
+                self.valid_during(context.time - before, context.time + after)
+            }
+            RenderedGeometryFilter::And(filters) => filters
+                .iter()
+                .all(|filter| self.fit_filter(filter, context)),
+            RenderedGeometryFilter::Or(filters) => filters
+                .iter()
+                .any(|filter| self.fit_filter(filter, context)),
+            RenderedGeometryFilter::Not(filter) => !self.fit_filter(filter, context),
         }
     }
     pub fn lines(&mut self) -> Option<&MultiLineString> {
@@ -62,7 +155,7 @@ impl RenderedGeometry {
             return self.lines.as_ref();
         }
         let lines = RenderedGeometry::get_lines_from_geom(&self.inner_geom);
-        self.lines = if lines.len() > 0 {
+        self.lines = if !lines.is_empty() {
             let multi = MultiLineString::new(lines);
             Some(multi)
         } else {
@@ -76,7 +169,7 @@ impl RenderedGeometry {
             return self.areas.as_ref();
         }
         let areas = RenderedGeometry::get_areas_from_geom(&self.inner_geom);
-        self.areas = if areas.len() > 0 {
+        self.areas = if !areas.is_empty() {
             let multi = MultiPolygon::new(areas);
             Some(multi)
         } else {
@@ -183,7 +276,7 @@ impl RenderedGeometry {
         } else {
             let center = match rect {
                 Some(rect) => match &self.inner_geom {
-                    Geometry::Point(point) => Some(point.clone()),
+                    Geometry::Point(point) => Some(*point),
                     Geometry::Line(line) => {
                         if rect.intersects(&line.start) {
                             Some(line.start_point())
@@ -210,7 +303,7 @@ impl RenderedGeometry {
                         let mut inter_point = Vec::new();
                         for point in multi_point {
                             if rect.contains(point) {
-                                inter_point.push(point.clone());
+                                inter_point.push(*point);
                             }
                         }
                         let multi_point = MultiPoint::new(inter_point);
@@ -218,7 +311,7 @@ impl RenderedGeometry {
                     }
                     Geometry::MultiLineString(multi_line_string) => {
                         let rect_polygon = rect.to_polygon();
-                        let intersection = rect_polygon.clip(&multi_line_string, false);
+                        let intersection = rect_polygon.clip(multi_line_string, false);
                         intersection.interior_point()
                     }
                     Geometry::MultiPolygon(multi_polygon) => {
@@ -260,3 +353,59 @@ impl RenderedGeometry {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(values: &[(&str, &str)]) -> HashMap<String, PropValue> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), PropValue::String(value.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn time_props_read_epochs_and_rfc3339() {
+        let time_props = TimeProps {
+            start: "from".into(),
+            end: Some("to".into()),
+        };
+        let range =
+            time_props.time_range(&props(&[("from", "1970-01-01T00:01:00Z"), ("to", " 120 ")]));
+        assert_eq!(range, Some((60f64, 120f64)));
+        // swapped ends still make a range
+        let range = time_props.time_range(&props(&[("from", "120"), ("to", "60")]));
+        assert_eq!(range, Some((60f64, 120f64)));
+        // a missing or unreadable end leaves the feature without a range
+        assert_eq!(time_props.time_range(&props(&[("from", "60")])), None);
+        let instant = TimeProps {
+            start: "from".into(),
+            end: None,
+        };
+        assert_eq!(
+            instant.time_range(&props(&[("from", "60")])),
+            Some((60f64, 60f64))
+        );
+        assert_eq!(instant.time_range(&props(&[("from", "soon")])), None);
+    }
+
+    #[test]
+    fn ranges_overlap_at_their_inclusive_edges() {
+        let point = Geometry::Point(Point::new(0f64, 0f64));
+        let geometry =
+            RenderedGeometry::new_temp(HashMap::new(), point).with_time_range(Some((10f64, 20f64)));
+        assert!(geometry.valid_during(20f64, 30f64));
+        assert!(geometry.valid_during(0f64, 10f64));
+        assert!(geometry.valid_during(12f64, 15f64));
+        assert!(!geometry.valid_during(20.5, 30f64));
+        assert!(!geometry.valid_during(0f64, 9.5));
+        let mut geometry = RenderedGeometry::new_temp(props(&[("at", "5")]), geometry.inner_geom);
+        assert!(geometry.valid_during(f64::MAX, f64::MAX));
+        geometry.apply_time_props(&TimeProps {
+            start: "at".into(),
+            end: None,
+        });
+        assert_eq!(geometry.time_range(), Some((5f64, 5f64)));
+    }
+}
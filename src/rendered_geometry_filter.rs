@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{MagicConverter, MagicFetcher, PropValue, RenderContext};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub enum RenderedGeometryFilter {
+    #[default]
+    None,
+    Layer(String),
+    Eq(String, PropValue),
+    /// Also true when the prop is missing.
+    Ne(String, PropValue),
+    Lt(String, PropValue),
+    Le(String, PropValue),
+    Gt(String, PropValue),
+    Ge(String, PropValue),
+    In(String, Vec<PropValue>),
+    Has(String),
+    /// Matches the string form of the prop against a regex.
+    Match(String, PropRegex),
+    GeometryType(GeometryKind),
+    /// Valid at the instant in epoch seconds, the render time when not set. Features without a
+    /// time range always pass the time filters.
+    At(Option<f64>),
+    /// Valid at some point between the start and end.
+    During(f64, f64),
+    /// Valid at some point from the first value seconds before the render time to the second
+    /// value seconds after it.
+    Around(f64, f64),
+    And(Vec<RenderedGeometryFilter>),
+    Or(Vec<RenderedGeometryFilter>),
+    Not(Box<RenderedGeometryFilter>),
+}
+
+impl MagicFetcher for RenderedGeometryFilter {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for RenderedGeometryFilter {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GeometryKind {
+    Point,
+    Line,
+    LineString,
+    Polygon,
+    MultiPoint,
+    MultiLineString,
+    MultiPolygon,
+    GeometryCollection,
+    Rect,
+    Triangle,
+    /// Point or MultiPoint.
+    Puntal,
+    /// Line, LineString or MultiLineString.
+    Lineal,
+    /// Polygon, MultiPolygon, Rect or Triangle.
+    Polygonal,
+}
+
+impl GeometryKind {
+    pub fn fit(&self, geom: &geo::Geometry) -> bool {
+        use geo::Geometry as G;
+        match self {
+            GeometryKind::Point => matches!(geom, G::Point(_)),
+            GeometryKind::Line => matches!(geom, G::Line(_)),
+            GeometryKind::LineString => matches!(geom, G::LineString(_)),
+            GeometryKind::Polygon => matches!(geom, G::Polygon(_)),
+            GeometryKind::MultiPoint => matches!(geom, G::MultiPoint(_)),
+            GeometryKind::MultiLineString => matches!(geom, G::MultiLineString(_)),
+            GeometryKind::MultiPolygon => matches!(geom, G::MultiPolygon(_)),
+            GeometryKind::GeometryCollection => matches!(geom, G::GeometryCollection(_)),
+            GeometryKind::Rect => matches!(geom, G::Rect(_)),
+            GeometryKind::Triangle => matches!(geom, G::Triangle(_)),
+            GeometryKind::Puntal => matches!(geom, G::Point(_) | G::MultiPoint(_)),
+            GeometryKind::Lineal => {
+                matches!(geom, G::Line(_) | G::LineString(_) | G::MultiLineString(_))
+            }
+            GeometryKind::Polygonal => matches!(
+                geom,
+                G::Polygon(_) | G::MultiPolygon(_) | G::Rect(_) | G::Triangle(_)
+            ),
+        }
+    }
+}
+
+/// A regex serialized as its pattern string and compiled once on deserialize.
+#[derive(Debug, Clone)]
+pub struct PropRegex(Regex);
+
+impl PropRegex {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        Regex::new(pattern)
+            .map(PropRegex)
+            .map_err(|e| format!("Invalid regex {}: {}", pattern, e))
+    }
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl Serialize for PropRegex {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for PropRegex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        PropRegex::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{Geometry, Point};
+
+    use super::*;
+    use crate::RenderedGeometry;
+
+    fn geometry(props: &[(&str, PropValue)]) -> RenderedGeometry {
+        let props = props
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        RenderedGeometry::new_temp(props, Geometry::Point(Point::new(0f64, 0f64)))
+    }
+
+    fn fit(geometry: &RenderedGeometry, filter: RenderedGeometryFilter) -> bool {
+        geometry.fit_filter(&filter, &RenderContext::default())
+    }
+
+    #[test]
+    fn ne_passes_missing_props() {
+        let geometry = geometry(&[("kind", "road".into())]);
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Ne("lanes".into(), 2.into())
+        ));
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Ne("kind".into(), "rail".into())
+        ));
+        assert!(!fit(
+            &geometry,
+            RenderedGeometryFilter::Ne("kind".into(), "road".into())
+        ));
+        assert!(!fit(
+            &geometry,
+            RenderedGeometryFilter::Eq("lanes".into(), PropValue::None)
+        ));
+    }
+
+    #[test]
+    fn numbers_compare_across_widths() {
+        let geometry = geometry(&[("int", 3i64.into()), ("float", 2.5f32.into())]);
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Eq("int".into(), 3f64.into())
+        ));
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Gt("int".into(), 2.5f64.into())
+        ));
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Le("int".into(), 3i32.into())
+        ));
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Lt("float".into(), 3i64.into())
+        ));
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Ge("float".into(), 2.5f64.into())
+        ));
+        assert!(!fit(
+            &geometry,
+            RenderedGeometryFilter::Gt("float".into(), 2.5f64.into())
+        ));
+        // Numbers never order against strings, and missing props fail every comparison.
+        assert!(!fit(
+            &geometry,
+            RenderedGeometryFilter::Lt("int".into(), "4".into())
+        ));
+        assert!(!fit(
+            &geometry,
+            RenderedGeometryFilter::Ge("missing".into(), 0i32.into())
+        ));
+    }
+
+    #[test]
+    fn in_matches_mixed_values() {
+        let values = vec!["3".into(), 2.0f64.into(), true.into()];
+        let filter = |name: &str| RenderedGeometryFilter::In(name.into(), values.clone());
+        let geometry = geometry(&[
+            ("int", 2i32.into()),
+            ("text", "3".into()),
+            ("number", 3i64.into()),
+            ("flag", false.into()),
+        ]);
+        assert!(fit(&geometry, filter("int")));
+        assert!(fit(&geometry, filter("text")));
+        assert!(!fit(&geometry, filter("number")));
+        assert!(!fit(&geometry, filter("flag")));
+        assert!(!fit(&geometry, filter("missing")));
+    }
+
+    #[test]
+    fn match_uses_the_prop_text() {
+        let geometry = geometry(&[("name", "Main Street".into()), ("code", 101i64.into())]);
+        let regex = |pattern: &str| PropRegex::new(pattern).unwrap();
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Match("name".into(), regex("Street$"))
+        ));
+        assert!(fit(
+            &geometry,
+            RenderedGeometryFilter::Match("code".into(), regex("^10"))
+        ));
+        assert!(!fit(
+            &geometry,
+            RenderedGeometryFilter::Match("missing".into(), regex(".*"))
+        ));
+    }
+
+    #[test]
+    fn invalid_regex_is_rejected() {
+        assert!(PropRegex::new("(unclosed").is_err());
+        let parsed: Result<RenderedGeometryFilter, _> = ron::from_str(r#"Match("name", "[a-")"#);
+        assert!(parsed.is_err());
+        let parsed: RenderedGeometryFilter = ron::from_str(r#"Match("name", "^M")"#).unwrap();
+        assert!(fit(&geometry(&[("name", "Main".into())]), parsed));
+    }
+
+    #[test]
+    fn logic_filters_nest() {
+        use RenderedGeometryFilter::*;
+        let geometry = geometry(&[("kind", "road".into()), ("lanes", 4i64.into())]);
+        let wide_road = And(vec![
+            Eq("kind".into(), "road".into()),
+            Or(vec![Ge("lanes".into(), 4i64.into()), Has("bridge".into())]),
+        ]);
+        assert!(fit(&geometry, wide_road.clone()));
+        assert!(!fit(&geometry, Not(Box::new(wide_road.clone()))));
+        assert!(fit(
+            &geometry,
+            Not(Box::new(And(vec![wide_road, Has("bridge".into())])))
+        ));
+        assert!(fit(&geometry, And(vec![])));
+        assert!(!fit(&geometry, Or(vec![])));
+        assert!(fit(
+            &geometry,
+            Or(vec![
+                Not(Box::new(None)),
+                Not(Box::new(Has("bridge".into())))
+            ])
+        ));
+    }
+
+    #[test]
+    fn time_filters_use_the_render_time() {
+        use RenderedGeometryFilter::{Around, At, During};
+        let context = RenderContext {
+            time: 100f64,
+            ..Default::default()
+        };
+        let fit = |range: Option<(f64, f64)>, filter: RenderedGeometryFilter| {
+            geometry(&[])
+                .with_time_range(range)
+                .fit_filter(&filter, &context)
+        };
+        let range = Some((90f64, 95f64));
+        assert!(!fit(range, At(None)));
+        assert!(fit(range, At(Some(95f64))));
+        assert!(fit(Some((90f64, 100f64)), At(None)));
+        assert!(fit(range, During(95f64, 200f64)));
+        assert!(!fit(range, During(96f64, 200f64)));
+        assert!(fit(range, Around(5f64, 0f64)));
+        assert!(!fit(range, Around(4f64, 10f64)));
+        assert!(fit(Some((110f64, 120f64)), Around(0f64, 10f64)));
+        // features without a range pass every time filter
+        for filter in [
+            At(None),
+            At(Some(-1f64)),
+            During(0f64, 1f64),
+            Around(0f64, 0f64),
+        ] {
+            assert!(fit(None, filter));
+        }
+    }
+}
@@ -1,14 +1,18 @@
 use std::collections::HashMap;
 
-use geo::{Geometry, MultiPolygon, Polygon};
+use geo::{
+    BoundingRect, Geometry, MultiPolygon, Orient, Polygon, Rect as GeoRect, orient::Direction,
+};
 use vello::{
-    kurbo::{Affine, BezPath},
-    peniko::{Brush, color::palette},
+    kurbo::{Affine, BezPath, Rect, Stroke},
+    peniko::{Brush, Fill, color::palette},
 };
 
-use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue, RenderedGeometry};
+use crate::{
+    MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, RenderedGeometry, utils,
+};
 
-use super::{GeometryRenderer, LineRenderer};
+use super::{BrushSpace, FillPattern, GeometryRenderer, LineRenderer};
 
 pub type AreaLineRenderers = HashMap<LineKind, Vec<MagicValue<GeometryRenderer>>>;
 
@@ -19,10 +23,49 @@ pub enum LineKind {
     Interior,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+impl From<FillRule> for Fill {
+    fn from(value: FillRule) -> Self {
+        match value {
+            FillRule::NonZero => Fill::NonZero,
+            FillRule::EvenOdd => Fill::EvenOdd,
+        }
+    }
+}
+
+impl MagicFetcher for FillRule {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for FillRule {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct AreaRenderer {
     pub brush: MagicValue<Brush>,
     pub line_renderers: MagicValue<AreaLineRenderers>,
+    /// Hatching, dots or tiles drawn over the brush, clipped to the polygon.
+    #[serde(default)]
+    pub pattern: MagicValue<FillPattern>,
+    /// Rings are oriented before filling, so holes render with either rule.
+    #[serde(default)]
+    pub fill_rule: MagicValue<FillRule>,
+    #[serde(default)]
+    pub brush_space: MagicValue<BrushSpace>,
+    /// Pixels the fill is stroked around by.
+    #[serde(skip)]
+    pub(crate) widen: f64,
 }
 
 impl std::default::Default for AreaRenderer {
@@ -30,6 +73,10 @@ impl std::default::Default for AreaRenderer {
         Self {
             brush: Brush::Solid(palette::css::SEA_GREEN).into(),
             line_renderers: HashMap::default().into(),
+            pattern: Default::default(),
+            fill_rule: Default::default(),
+            brush_space: Default::default(),
+            widen: 0f64,
         }
     }
 }
@@ -38,14 +85,32 @@ impl MagicFetcher for AreaRenderer {
     fn fetch(&mut self) -> Result<(), String> {
         self.brush.fetch()?;
         self.line_renderers.fetch()?;
+        self.pattern.fetch()?;
+        self.fill_rule.fetch()?;
+        self.brush_space.fetch()?;
         Ok(())
     }
 }
 
 impl MagicConverter for AreaRenderer {
-    fn convert(&mut self, props: &HashMap<String, PropValue>) -> Result<(), String> {
-        self.brush.convert(props)?;
-        self.line_renderers.convert(props)?;
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.brush.convert(props, context)?;
+        self.line_renderers.convert(props, context)?;
+        self.pattern.convert(props, context)?;
+        self.fill_rule.convert(props, context)?;
+        self.brush_space.convert(props, context)?;
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.brush.prepare(layer)?;
+        self.line_renderers.prepare(layer)?;
+        self.pattern.prepare(layer)?;
+        self.fill_rule.prepare(layer)?;
+        self.brush_space.prepare(layer)?;
         Ok(())
     }
 }
@@ -62,10 +127,22 @@ impl MagicFetcher for AreaLineRenderers {
 }
 
 impl MagicConverter for AreaLineRenderers {
-    fn convert(&mut self, props: &HashMap<String, PropValue>) -> Result<(), String> {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        for (_, renderers) in self.iter_mut() {
+            for renderer in renderers {
+                renderer.convert(props, context)?;
+            }
+        }
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
         for (_, renderers) in self.iter_mut() {
             for renderer in renderers {
-                renderer.convert(props)?;
+                renderer.prepare(layer)?;
             }
         }
         Ok(())
@@ -73,14 +150,27 @@ impl MagicConverter for AreaLineRenderers {
 }
 
 impl AreaRenderer {
+    /// Grows the fill and outlines by `grow` pixels on each side, false when an outline can
+    /// not grow.
+    pub(crate) fn widen(&mut self, grow: f64) -> bool {
+        self.widen += grow;
+        self.line_renderers
+            .as_mut()
+            .values_mut()
+            .flatten()
+            .all(|renderer| renderer.as_mut().widen(grow))
+    }
     pub fn draw_multi(
         &mut self,
         scene: &mut vello::Scene,
         transform: Affine,
+        context: &RenderContext,
         polygons: &MultiPolygon,
+        render_rect: Option<GeoRect>,
     ) -> Result<(), String> {
+        let bounds = polygons.bounding_rect();
         for polygon in polygons {
-            self.draw(scene, transform, polygon)?;
+            self.draw_in_bounds(scene, transform, context, polygon, render_rect, bounds)?;
         }
         Ok(())
     }
@@ -88,20 +178,61 @@ impl AreaRenderer {
         &mut self,
         scene: &mut vello::Scene,
         transform: Affine,
+        context: &RenderContext,
+        polygon: &Polygon,
+        render_rect: Option<GeoRect>,
+    ) -> Result<(), String> {
+        let bounds = polygon.bounding_rect();
+        self.draw_in_bounds(scene, transform, context, polygon, render_rect, bounds)
+    }
+    /// Draws like [`AreaRenderer::draw`], placing `Bounds` brushes in `brush_bounds`.
+    fn draw_in_bounds(
+        &mut self,
+        scene: &mut vello::Scene,
+        transform: Affine,
+        context: &RenderContext,
         polygon: &Polygon,
+        render_rect: Option<GeoRect>,
+        brush_bounds: Option<GeoRect>,
     ) -> Result<(), String> {
         let brush = self.brush.as_ref();
+        let brush_transform = self
+            .brush_space
+            .as_ref()
+            .brush_transform(transform, brush_bounds);
         let line_renderers = self.line_renderers.as_mut();
         let exterior = polygon.exterior();
         let interiors = polygon.interiors();
-        let exterior_path = AreaRenderer::to_shape(polygon);
+        let exterior_path = AreaRenderer::to_shape(polygon)?;
         scene.fill(
-            vello::peniko::Fill::NonZero,
+            Fill::from(*self.fill_rule.as_ref()),
             transform,
             brush,
-            None,
+            brush_transform,
             &exterior_path,
         );
+        if self.widen > 0f64 {
+            // round joins grow the rings evenly, holes shrinking by as much
+            scene.stroke(
+                &Stroke::new(2f64 * self.widen / utils::transform_scale(transform)),
+                transform,
+                brush,
+                brush_transform,
+                &exterior_path,
+            );
+        }
+        if let Some(bounds) = polygon.bounding_rect() {
+            let to_rect =
+                |rect: GeoRect| Rect::new(rect.min().x, rect.min().y, rect.max().x, rect.max().y);
+            let bounds = match render_rect {
+                Some(render_rect) => to_rect(bounds).intersect(to_rect(render_rect)),
+                None => to_rect(bounds),
+            };
+            let factor = self.pattern.map_unit_factor(transform);
+            self.pattern
+                .as_ref()
+                .draw(scene, transform, factor, &exterior_path, bounds)?;
+        }
         let exterior_geom: Geometry = exterior.clone().into();
         let mut exterior_geom = vec![RenderedGeometry::new_temp(
             Default::default(),
@@ -119,34 +250,136 @@ impl AreaRenderer {
             match kind {
                 LineKind::All => {
                     for renderer in renderers.iter_mut().map(|x| x.as_mut()) {
-                        renderer.draw(scene, transform, &mut exterior_geom, None)?;
-                        renderer.draw(scene, transform, &mut interior_geoms, None)?;
+                        renderer.draw(scene, transform, context, &mut exterior_geom, None)?;
+                        renderer.draw(scene, transform, context, &mut interior_geoms, None)?;
                     }
                 }
                 LineKind::Exterior => {
                     for renderer in renderers.iter_mut().map(|x| x.as_mut()) {
-                        renderer.draw(scene, transform, &mut exterior_geom, None)?;
+                        renderer.draw(scene, transform, context, &mut exterior_geom, None)?;
                     }
                 }
                 LineKind::Interior => {
                     for renderer in renderers.iter_mut().map(|x| x.as_mut()) {
-                        renderer.draw(scene, transform, &mut interior_geoms, None)?;
+                        renderer.draw(scene, transform, context, &mut interior_geoms, None)?;
                     }
                 }
             }
         }
         Ok(())
     }
-    pub fn to_shape(polygon: &Polygon) -> BezPath {
+    /// Path of the polygon with its exterior counter-clockwise and its holes clockwise.
+    pub fn to_shape(polygon: &Polygon) -> Result<BezPath, String> {
+        let polygon = polygon.orient(Direction::Default);
         let exterior = polygon.exterior();
         let interiors = polygon.interiors();
-        let mut exterior_path = LineRenderer::to_shape(exterior);
-        interiors.iter().for_each(|x| {
-            let p = LineRenderer::to_shape(x);
+        let mut exterior_path = LineRenderer::to_shape(exterior)?;
+        for interior in interiors {
+            let p = LineRenderer::to_shape(interior)?;
             p.iter().for_each(|item| {
                 exterior_path.push(item);
             });
-        });
-        exterior_path
+        }
+        Ok(exterior_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vello::peniko::color::AlphaColor;
+
+    use super::*;
+    use crate::{Classification, LineRenderer, PropMap};
+
+    fn classified_brush() -> MagicValue<Brush> {
+        MagicValue::from(Brush::Solid(palette::css::BLACK)).with_prop_map(PropMap::Classify {
+            prop: "v".to_string(),
+            classification: Classification::EqualInterval,
+            classes: None,
+            values: vec![
+                Brush::Solid(palette::css::RED),
+                Brush::Solid(palette::css::BLUE),
+            ],
+            default: Brush::Solid(palette::css::BLACK),
+            breaks: Vec::new(),
+        })
+    }
+
+    fn color(brush: &Brush) -> AlphaColor<vello::peniko::color::Srgb> {
+        match brush {
+            Brush::Solid(color) => *color,
+            _ => panic!("not a solid brush"),
+        }
+    }
+
+    #[test]
+    fn outlines_see_the_layer_classes() {
+        let outline = LineRenderer {
+            brush: classified_brush(),
+            ..Default::default()
+        };
+        let mut renderer = AreaRenderer {
+            line_renderers: HashMap::from([(
+                LineKind::All,
+                vec![GeometryRenderer::Line(Default::default(), outline.into()).into()],
+            )])
+            .into(),
+            ..Default::default()
+        };
+        let layer: Vec<_> = [0f64, 5f64, 10f64]
+            .into_iter()
+            .map(|v| HashMap::from([("v".to_string(), PropValue::Float64(v))]))
+            .collect();
+        renderer.prepare(&layer.iter().collect::<Vec<_>>()).unwrap();
+        let outline_color = |renderer: &mut AreaRenderer, props| {
+            renderer.convert(props, &RenderContext::default()).unwrap();
+            let line = &renderer.line_renderers.as_ref()[&LineKind::All][0];
+            match line.as_ref() {
+                GeometryRenderer::Line(_, line) => color(line.as_ref().brush.as_ref()),
+                _ => panic!("not a line renderer"),
+            }
+        };
+        assert_eq!(outline_color(&mut renderer, &layer[0]), palette::css::RED);
+        assert_eq!(outline_color(&mut renderer, &layer[2]), palette::css::BLUE);
+    }
+
+    #[test]
+    fn exteriors_run_counter_clockwise_around_clockwise_holes() {
+        use geo::polygon;
+        use vello::kurbo::{PathEl, Shape};
+
+        // a clockwise exterior around a counter-clockwise hole, both to be reversed
+        let polygon = polygon!(
+            exterior: [
+                (x: 0f64, y: 0f64),
+                (x: 0f64, y: 10f64),
+                (x: 10f64, y: 10f64),
+                (x: 10f64, y: 0f64),
+            ],
+            interiors: [[
+                (x: 2f64, y: 2f64),
+                (x: 4f64, y: 2f64),
+                (x: 4f64, y: 4f64),
+                (x: 2f64, y: 4f64),
+            ]],
+        );
+        let path = AreaRenderer::to_shape(&polygon).unwrap();
+        let rings: Vec<_> = path
+            .elements()
+            .split(|element| *element == PathEl::ClosePath)
+            .filter(|ring| !ring.is_empty())
+            .map(|ring| {
+                let mut ring = BezPath::from_vec(ring.to_vec());
+                ring.close_path();
+                ring
+            })
+            .collect();
+        assert_eq!(rings.len(), 2);
+        // kurbo areas are positive for counter-clockwise paths in y up space
+        assert_eq!(rings[0].area(), 100f64);
+        assert_eq!(rings[1].area(), -4f64);
+        assert_eq!(path.area(), 96f64);
+        assert!(!path.contains(vello::kurbo::Point::new(3f64, 3f64)));
+        assert!(path.contains(vello::kurbo::Point::new(6f64, 6f64)));
     }
 }
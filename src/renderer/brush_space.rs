@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use geo::Rect;
+use vello::kurbo::Affine;
+
+use crate::{MagicConverter, MagicFetcher, PropValue, RenderContext};
+
+/// Space the points of gradient and image brushes are given in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BrushSpace {
+    /// Map units, shared by every feature.
+    #[default]
+    Map,
+    /// Pixels of the rendered image.
+    Pixel,
+    /// Fractions of the feature bounding box, `(0, 0)` at its top left and `(1, 1)` at its
+    /// bottom right on screen. A box flat on one side takes the other side's size around its
+    /// centre, and a single point falls back to map units.
+    Bounds,
+}
+
+impl MagicFetcher for BrushSpace {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for BrushSpace {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl BrushSpace {
+    /// Brush transform for shapes drawn in map space with `transform`.
+    pub fn brush_transform(&self, transform: Affine, bounds: Option<Rect>) -> Option<Affine> {
+        match self {
+            BrushSpace::Map => None,
+            BrushSpace::Pixel => Some(transform.inverse()),
+            BrushSpace::Bounds => bounds.and_then(|bounds| {
+                let size = bounds.width().max(bounds.height());
+                if size <= 0f64 {
+                    return None;
+                }
+                // flat sides are widened so the transform stays invertible
+                let width = if bounds.width() > 0f64 {
+                    bounds.width()
+                } else {
+                    size
+                };
+                let height = if bounds.height() > 0f64 {
+                    bounds.height()
+                } else {
+                    size
+                };
+                let center = bounds.center();
+                // map space has y up, so the top of the box is its max y
+                Some(Affine::new([
+                    width,
+                    0f64,
+                    0f64,
+                    -height,
+                    center.x - width / 2f64,
+                    center.y + height / 2f64,
+                ]))
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::coord;
+    use vello::kurbo::Point;
+
+    use super::*;
+
+    fn bounds_transform(min: (f64, f64), max: (f64, f64)) -> Option<Affine> {
+        let bounds = Rect::new(coord! { x: min.0, y: min.1 }, coord! { x: max.0, y: max.1 });
+        BrushSpace::Bounds.brush_transform(Affine::IDENTITY, Some(bounds))
+    }
+
+    #[test]
+    fn bounds_map_to_the_unit_square() {
+        let transform = bounds_transform((10f64, 20f64), (30f64, 60f64)).unwrap();
+        assert_eq!(transform * Point::new(0f64, 0f64), Point::new(10f64, 60f64));
+        assert_eq!(transform * Point::new(1f64, 1f64), Point::new(30f64, 20f64));
+    }
+
+    #[test]
+    fn flat_bounds_stay_invertible() {
+        // a horizontal line takes its width as height, centred on it
+        let transform = bounds_transform((0f64, 5f64), (10f64, 5f64)).unwrap();
+        assert_ne!(transform.determinant(), 0f64);
+        assert_eq!(transform * Point::new(0f64, 0f64), Point::new(0f64, 10f64));
+        assert_eq!(transform * Point::new(1f64, 1f64), Point::new(10f64, 0f64));
+        let transform = bounds_transform((3f64, 0f64), (3f64, 4f64)).unwrap();
+        assert_eq!(transform * Point::new(0f64, 0f64), Point::new(1f64, 4f64));
+        // a single point has no box to place brushes in
+        assert_eq!(bounds_transform((1f64, 1f64), (1f64, 1f64)), None);
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    f64::consts::{FRAC_PI_2, TAU},
+};
+
+use geo::Point;
+use vello::{
+    Scene,
+    kurbo::{self, Affine, CircleSegment, Rect},
+    peniko::{Brush, Fill},
+};
+
+use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext};
+
+use super::Outline;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ChartKind {
+    /// Slices clockwise from the top, a hole of `hole` times the radius making a donut.
+    #[default]
+    Pie,
+    /// Bars left to right, standing on the point.
+    Bar,
+}
+
+impl MagicFetcher for ChartKind {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for ChartKind {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Numeric prop shown as one slice or bar.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChartField {
+    pub prop: String,
+    pub brush: Brush,
+}
+
+/// Small pie or bar charts of several props, drawn at center points. Missing or negative
+/// values count as zero. Lengths are pixels when `need_scale` is set, map units otherwise.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ChartRenderer {
+    pub kind: MagicValue<ChartKind>,
+    pub fields: Vec<ChartField>,
+    /// Pie radius, or the height of a bar at `max_value`.
+    pub size: MagicValue<PropValue>,
+    pub hole: MagicValue<PropValue>,
+    pub bar_width: MagicValue<PropValue>,
+    /// Value of a full height bar. When not set it is the largest field value among the
+    /// features of the current request, so the same bar differs in height between tiles;
+    /// set it for a scale that holds across tiles.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<MagicValue<PropValue>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outline: Option<Outline>,
+    #[serde(skip)]
+    layer_max: f64,
+}
+
+impl std::default::Default for ChartRenderer {
+    fn default() -> Self {
+        ChartRenderer {
+            kind: ChartKind::Pie.into(),
+            fields: Vec::new(),
+            size: MagicValue::wrap(16f64).with_need_scale(true),
+            hole: MagicValue::wrap(0f64),
+            bar_width: MagicValue::wrap(6f64).with_need_scale(true),
+            max_value: None,
+            outline: None,
+            layer_max: 0f64,
+        }
+    }
+}
+
+impl MagicFetcher for ChartRenderer {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.kind.fetch()?;
+        self.size.fetch()?;
+        self.hole.fetch()?;
+        self.bar_width.fetch()?;
+        if let Some(max_value) = self.max_value.as_mut() {
+            max_value.fetch()?;
+        }
+        if let Some(outline) = self.outline.as_mut() {
+            outline.fetch()?;
+        }
+        Ok(())
+    }
+}
+
+impl MagicConverter for ChartRenderer {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.kind.convert(props, context)?;
+        self.size.convert(props, context)?;
+        self.hole.convert(props, context)?;
+        self.bar_width.convert(props, context)?;
+        if let Some(max_value) = self.max_value.as_mut() {
+            max_value.convert(props, context)?;
+        }
+        if let Some(outline) = self.outline.as_mut() {
+            outline.convert(props, context)?;
+        }
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.kind.prepare(layer)?;
+        self.size.prepare(layer)?;
+        self.hole.prepare(layer)?;
+        self.bar_width.prepare(layer)?;
+        if let Some(max_value) = self.max_value.as_mut() {
+            max_value.prepare(layer)?;
+        }
+        if let Some(outline) = self.outline.as_mut() {
+            outline.prepare(layer)?;
+        }
+        self.layer_max = layer
+            .iter()
+            .flat_map(|props| self.values(props))
+            .fold(0f64, f64::max);
+        Ok(())
+    }
+}
+
+impl ChartRenderer {
+    fn values(&self, props: &HashMap<String, PropValue>) -> Vec<f64> {
+        self.fields
+            .iter()
+            .map(|field| {
+                props
+                    .get(&field.prop)
+                    .and_then(|value| value.as_number())
+                    .filter(|value| value.is_finite())
+                    .unwrap_or_default()
+                    .max(0f64)
+            })
+            .collect()
+    }
+    /// Map-space shapes of the chart at `point`, with the brush of their field.
+    pub fn shapes(
+        &self,
+        transform: Affine,
+        props: &HashMap<String, PropValue>,
+        point: &Point,
+    ) -> Result<Vec<(kurbo::BezPath, &Brush)>, String> {
+        let center = kurbo::Point::new(point.x(), point.y());
+        let size = self.size.map_length(transform)?;
+        let values = self.values(props);
+        let mut shapes = Vec::new();
+        match self.kind.as_ref() {
+            ChartKind::Pie => {
+                let hole: f64 = self.hole.inner_try_into()?;
+                let inner = size * hole.clamp(0f64, 1f64);
+                for (index, start, sweep) in slice_angles(&values) {
+                    let slice = CircleSegment::new(center, size, inner, start, sweep);
+                    shapes.push((
+                        kurbo::Shape::to_path(&slice, size * 1e-3),
+                        &self.fields[index].brush,
+                    ));
+                }
+            }
+            ChartKind::Bar => {
+                let max = match &self.max_value {
+                    Some(max_value) => max_value.inner_try_into()?,
+                    None => self.layer_max,
+                };
+                if max <= 0f64 {
+                    return Ok(shapes);
+                }
+                let width = self.bar_width.map_length(transform)?;
+                let left = center.x - width * values.len() as f64 / 2f64;
+                for (index, (value, field)) in
+                    values.into_iter().zip(self.fields.iter()).enumerate()
+                {
+                    let x = left + width * index as f64;
+                    let bar = Rect::new(x, center.y, x + width, center.y + size * value / max);
+                    shapes.push((kurbo::Shape::to_path(&bar, 0.1), &field.brush));
+                }
+            }
+        }
+        Ok(shapes)
+    }
+    pub fn draw(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        props: &HashMap<String, PropValue>,
+        point: &Point,
+    ) -> Result<(), String> {
+        for (shape, brush) in self.shapes(transform, props, point)? {
+            scene.fill(Fill::NonZero, transform, brush, None, &shape);
+            if let Some(outline) = self.outline.as_ref() {
+                scene.stroke(
+                    &outline.stroke.map_stroke(transform),
+                    transform,
+                    outline.brush.as_ref(),
+                    None,
+                    &shape,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Start angle and sweep in map space of the slice of each non-zero value, by value index.
+/// Map space has y up, so slices run clockwise on screen from the top with negative sweeps.
+fn slice_angles(values: &[f64]) -> Vec<(usize, f64, f64)> {
+    let total: f64 = values.iter().sum();
+    if total <= 0f64 {
+        return Vec::new();
+    }
+    let mut start = FRAC_PI_2;
+    let mut slices = Vec::new();
+    for (index, value) in values.iter().enumerate() {
+        let sweep = -TAU * value / total;
+        if sweep != 0f64 {
+            slices.push((index, start, sweep));
+        }
+        start += sweep;
+    }
+    slices
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use vello::{kurbo::Shape, peniko::color::palette};
+
+    use super::*;
+
+    fn chart(kind: ChartKind, props: &[&str]) -> ChartRenderer {
+        ChartRenderer {
+            kind: kind.into(),
+            fields: props
+                .iter()
+                .map(|prop| ChartField {
+                    prop: prop.to_string(),
+                    brush: Brush::Solid(palette::css::RED),
+                })
+                .collect(),
+            size: MagicValue::wrap(10f64),
+            bar_width: MagicValue::wrap(2f64),
+            ..Default::default()
+        }
+    }
+
+    fn props(values: &[(&str, f64)]) -> HashMap<String, PropValue> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), PropValue::Float64(*value)))
+            .collect()
+    }
+
+    #[test]
+    fn pie_slices_run_clockwise_from_the_top() {
+        let slices = slice_angles(&[1f64, 0f64, 1f64, 2f64]);
+        assert_eq!(
+            slices,
+            vec![
+                (0, FRAC_PI_2, -FRAC_PI_2),
+                (2, 0f64, -FRAC_PI_2),
+                (3, -FRAC_PI_2, -PI)
+            ]
+        );
+        assert!(slice_angles(&[0f64, 0f64]).is_empty());
+        // missing and negative values count as zero
+        let renderer = chart(ChartKind::Pie, &["a", "b", "c"]);
+        let shapes = renderer
+            .shapes(
+                Affine::IDENTITY,
+                &props(&[("a", 3f64), ("b", -1f64)]),
+                &Point::new(0f64, 0f64),
+            )
+            .unwrap();
+        assert_eq!(shapes.len(), 1);
+        let full = shapes[0].0.bounding_box();
+        assert!((full.width() - 20f64).abs() < 1e-2 && (full.height() - 20f64).abs() < 1e-2);
+    }
+
+    #[test]
+    fn bars_stand_on_the_point_scaled_to_the_max() {
+        let mut renderer = chart(ChartKind::Bar, &["a", "b", "c"]);
+        let layer = [props(&[("a", 4f64)]), props(&[("b", 8f64), ("c", 2f64)])];
+        renderer.prepare(&layer.iter().collect::<Vec<_>>()).unwrap();
+        let shapes = renderer
+            .shapes(
+                Affine::IDENTITY,
+                &props(&[("a", 4f64), ("b", 8f64), ("c", 2f64)]),
+                &Point::new(10f64, 5f64),
+            )
+            .unwrap();
+        let bars: Vec<Rect> = shapes
+            .iter()
+            .map(|(shape, _)| shape.bounding_box())
+            .collect();
+        assert_eq!(
+            bars,
+            vec![
+                Rect::new(7f64, 5f64, 9f64, 10f64),
+                Rect::new(9f64, 5f64, 11f64, 15f64),
+                Rect::new(11f64, 5f64, 13f64, 7.5),
+            ]
+        );
+        renderer.max_value = Some(MagicValue::wrap(4f64));
+        let shapes = renderer
+            .shapes(
+                Affine::IDENTITY,
+                &props(&[("a", 4f64)]),
+                &Point::new(0f64, 0f64),
+            )
+            .unwrap();
+        assert_eq!(shapes[0].0.bounding_box().height(), 10f64);
+    }
+
+    #[test]
+    fn bars_without_max_value_scale_per_request() {
+        let mut renderer = chart(ChartKind::Bar, &["a"]);
+        let feature = props(&[("a", 4f64)]);
+        let height = |renderer: &mut ChartRenderer, layer: &[&HashMap<String, PropValue>]| {
+            renderer.prepare(layer).unwrap();
+            let shapes = renderer
+                .shapes(Affine::IDENTITY, &feature, &Point::new(0f64, 0f64))
+                .unwrap();
+            shapes[0].0.bounding_box().height()
+        };
+        // one tile holds only the feature, its neighbour also a feature twice as large
+        let larger = props(&[("a", 8f64)]);
+        assert_eq!(height(&mut renderer, &[&feature]), 10f64);
+        assert_eq!(height(&mut renderer, &[&feature, &larger]), 5f64);
+        renderer.max_value = Some(MagicValue::wrap(8f64));
+        assert_eq!(height(&mut renderer, &[&feature]), 5f64);
+        assert_eq!(height(&mut renderer, &[&feature, &larger]), 5f64);
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+
+use geo::{Point, Rect as GeoRect};
+use vello::{
+    Scene,
+    kurbo::{self, Affine},
+    peniko::{Brush, color::palette},
+};
+
+use crate::{
+    MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, RenderedGeometry, utils,
+};
+
+use super::{PointRenderer, TextRenderer};
+
+/// Prop holding the number of points, set on the props each cluster is styled with.
+pub const COUNT_PROP: &str = "count";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ClusterMethod {
+    /// Points sharing a grid cell of `radius`, cells counted from the map origin.
+    #[default]
+    Grid,
+    /// Points within `radius` of the first unclustered point, taken west to east.
+    Distance,
+}
+
+impl MagicFetcher for ClusterMethod {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for ClusterMethod {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Collapses nearby points into clusters drawn with `symbol` and `label`, styled from
+/// [`COUNT_PROP`]. Points left alone draw with `point` and their own props.
+///
+/// Clusters only depend on the points and the scale, so tiles sharing the layer agree on them.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ClusterRenderer {
+    pub method: MagicValue<ClusterMethod>,
+    /// Grid cell size or clustering distance, in pixels when `need_scale` is set.
+    pub radius: MagicValue<PropValue>,
+    /// Smallest number of points drawn as a cluster.
+    pub min_count: MagicValue<PropValue>,
+    /// Its radius is the size of a `min_count` cluster, growing with the square root of the count.
+    pub symbol: MagicValue<PointRenderer>,
+    /// Largest cluster symbol radius in pixels.
+    pub max_radius: MagicValue<PropValue>,
+    /// Drawn on every cluster, e.g. with the text `"{count}"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<MagicValue<TextRenderer>>,
+    pub point: MagicValue<PointRenderer>,
+}
+
+impl std::default::Default for ClusterRenderer {
+    fn default() -> Self {
+        let symbol = PointRenderer {
+            radius: MagicValue::wrap(12f64).with_need_scale(true),
+            brush: Brush::Solid(palette::css::ORANGE).into(),
+            ..Default::default()
+        };
+        ClusterRenderer {
+            method: ClusterMethod::Grid.into(),
+            radius: MagicValue::wrap(60f64).with_need_scale(true),
+            min_count: MagicValue::wrap(2f64),
+            symbol: symbol.into(),
+            max_radius: MagicValue::wrap(40f64),
+            label: None,
+            point: PointRenderer::default().into(),
+        }
+    }
+}
+
+impl MagicFetcher for ClusterRenderer {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.method.fetch()?;
+        self.radius.fetch()?;
+        self.min_count.fetch()?;
+        self.symbol.fetch()?;
+        self.max_radius.fetch()?;
+        if let Some(label) = self.label.as_mut() {
+            label.fetch()?;
+        }
+        self.point.fetch()?;
+        Ok(())
+    }
+}
+
+impl MagicConverter for ClusterRenderer {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.method.convert(props, context)?;
+        self.radius.convert(props, context)?;
+        self.min_count.convert(props, context)?;
+        self.max_radius.convert(props, context)?;
+        // symbols are converted per cluster and per point in `draw`
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.method.prepare(layer)?;
+        self.radius.prepare(layer)?;
+        self.min_count.prepare(layer)?;
+        self.symbol.prepare(layer)?;
+        self.max_radius.prepare(layer)?;
+        if let Some(label) = self.label.as_mut() {
+            label.prepare(layer)?;
+        }
+        self.point.prepare(layer)?;
+        Ok(())
+    }
+}
+
+impl ClusterRenderer {
+    /// Groups `points`, given in map units, into clusters of their indices.
+    pub fn clusters(
+        &self,
+        transform: Affine,
+        points: &[kurbo::Point],
+    ) -> Result<Vec<Vec<usize>>, String> {
+        let radius = self.radius.map_length(transform)?;
+        if radius <= 0f64 {
+            return Ok((0..points.len()).map(|index| vec![index]).collect());
+        }
+        let cell_of = |point: &kurbo::Point| {
+            (
+                (point.x / radius).floor() as i64,
+                (point.y / radius).floor() as i64,
+            )
+        };
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (index, point) in points.iter().enumerate() {
+            cells.entry(cell_of(point)).or_default().push(index);
+        }
+        match self.method.as_ref() {
+            ClusterMethod::Grid => {
+                let mut clusters: Vec<_> = cells.into_values().collect();
+                clusters.sort_by_key(|cluster| cluster[0]);
+                Ok(clusters)
+            }
+            ClusterMethod::Distance => {
+                let mut order: Vec<usize> = (0..points.len()).collect();
+                order.sort_by(|a, b| {
+                    points[*a]
+                        .x
+                        .total_cmp(&points[*b].x)
+                        .then(points[*a].y.total_cmp(&points[*b].y))
+                        .then(a.cmp(b))
+                });
+                let mut clustered = vec![false; points.len()];
+                let mut clusters = Vec::new();
+                for index in order {
+                    if clustered[index] {
+                        continue;
+                    }
+                    let center = points[index];
+                    let (x, y) = cell_of(&center);
+                    let mut cluster = Vec::new();
+                    for cell in (x - 1..=x + 1).flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y))) {
+                        for other in cells.get(&cell).into_iter().flatten() {
+                            if !clustered[*other] && points[*other].distance(center) <= radius {
+                                clustered[*other] = true;
+                                cluster.push(*other);
+                            }
+                        }
+                    }
+                    cluster.sort();
+                    clusters.push(cluster);
+                }
+                Ok(clusters)
+            }
+        }
+    }
+    /// Draws clusters of the geometries at `geom_indices`, by their center points.
+    pub fn draw(
+        &mut self,
+        scene: &mut Scene,
+        transform: Affine,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
+        geom_indices: &[usize],
+        render_rect: Option<GeoRect>,
+    ) -> Result<(), String> {
+        let mut members = Vec::new();
+        let mut points = Vec::new();
+        for geom_index in geom_indices {
+            if let Some(point) = rendered_geometrys[*geom_index].center_point(None) {
+                members.push(*geom_index);
+                points.push(kurbo::Point::new(point.x(), point.y()));
+            }
+        }
+        let min_count: f64 = self.min_count.inner_try_into()?;
+        let max_radius: f64 = self.max_radius.inner_try_into()?;
+        let margin = max_radius / utils::transform_scale(transform);
+        let visible = |point: &kurbo::Point| {
+            render_rect.is_none_or(|rect| {
+                point.x >= rect.min().x - margin
+                    && point.x <= rect.max().x + margin
+                    && point.y >= rect.min().y - margin
+                    && point.y <= rect.max().y + margin
+            })
+        };
+        for cluster in self.clusters(transform, &points)? {
+            if (cluster.len() as f64) < min_count {
+                for index in cluster {
+                    let rendered_geometry = &mut rendered_geometrys[members[index]];
+                    self.point.convert(rendered_geometry.props(), context)?;
+                    if visible(&points[index]) {
+                        self.point.as_ref().draw(
+                            scene,
+                            transform,
+                            &Point::new(points[index].x, points[index].y),
+                        )?;
+                    }
+                }
+                continue;
+            }
+            let count = cluster.len();
+            let center = cluster.iter().fold(kurbo::Vec2::ZERO, |sum, index| {
+                sum + points[*index].to_vec2()
+            }) / count as f64;
+            if !visible(&center.to_point()) {
+                continue;
+            }
+            let center = Point::new(center.x, center.y);
+            let props = HashMap::from([(COUNT_PROP.to_string(), PropValue::from(count as i64))]);
+            self.symbol.convert(&props, context)?;
+            let mut symbol = self.symbol.as_ref().clone();
+            let base = symbol.radius.pixel_length(transform)?;
+            let radius = (base * (count as f64 / min_count.max(1f64)).sqrt()).min(max_radius);
+            symbol.radius = MagicValue::wrap(radius).with_need_scale(true);
+            symbol.draw(scene, transform, &center)?;
+            if let Some(label) = self.label.as_mut() {
+                label.convert(&props, context)?;
+                let label = label.as_ref();
+                if let Some(text) = label.layout_at(transform, &center)? {
+                    label.draw_label(scene, &text)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer(method: ClusterMethod, radius: f64) -> ClusterRenderer {
+        ClusterRenderer {
+            method: method.into(),
+            radius: MagicValue::wrap(radius),
+            ..Default::default()
+        }
+    }
+
+    fn points(coords: &[(f64, f64)]) -> Vec<kurbo::Point> {
+        coords
+            .iter()
+            .map(|(x, y)| kurbo::Point::new(*x, *y))
+            .collect()
+    }
+
+    #[test]
+    fn grid_clusters_share_origin_aligned_cells() {
+        let points = points(&[
+            (1., 1.),
+            (9., 9.),
+            (11., 1.),
+            (-1., 1.),
+            (19., 2.),
+            (5., 5.),
+        ]);
+        let clusters = renderer(ClusterMethod::Grid, 10f64)
+            .clusters(Affine::IDENTITY, &points)
+            .unwrap();
+        assert_eq!(clusters, vec![vec![0, 1, 5], vec![2, 4], vec![3]]);
+    }
+
+    #[test]
+    fn distance_clusters_grow_west_to_east() {
+        let points = points(&[
+            (12., 0.),
+            (0., 0.),
+            (5., 3.),
+            (9., 0.),
+            (30., 0.),
+            (35., 0.),
+        ]);
+        let clusters = renderer(ClusterMethod::Distance, 10f64)
+            .clusters(Affine::IDENTITY, &points)
+            .unwrap();
+        // (0, 0) comes first and takes everything within 10, leaving (12, 0) alone
+        assert_eq!(clusters, vec![vec![1, 2, 3], vec![0], vec![4, 5]]);
+        let counts: Vec<usize> = clusters.iter().map(Vec::len).collect();
+        assert_eq!(counts.iter().sum::<usize>(), points.len());
+    }
+
+    #[test]
+    fn clusters_agree_across_tiles() {
+        // points straddling the seam between two 256px tiles at map x = 128
+        let points = points(&[
+            (120., 10.),
+            (126., 12.),
+            (131., 9.),
+            (140., 11.),
+            (60., 60.),
+        ]);
+        let left = Affine::scale(2f64);
+        let right = Affine::translate((-256f64, 0f64)) * left;
+        for method in [ClusterMethod::Grid, ClusterMethod::Distance] {
+            let renderer = ClusterRenderer {
+                radius: MagicValue::wrap(40f64).with_need_scale(true),
+                ..renderer(method, 0f64)
+            };
+            let in_left = renderer.clusters(left, &points).unwrap();
+            assert_eq!(in_left, renderer.clusters(right, &points).unwrap());
+            // the same layer handed over in another order clusters the same points together
+            let reversed: Vec<_> = points.iter().rev().copied().collect();
+            let mut in_reversed: Vec<Vec<usize>> = renderer
+                .clusters(right, &reversed)
+                .unwrap()
+                .into_iter()
+                .map(|cluster| {
+                    let mut cluster: Vec<_> = cluster
+                        .iter()
+                        .map(|index| points.len() - 1 - index)
+                        .collect();
+                    cluster.sort();
+                    cluster
+                })
+                .collect();
+            in_reversed.sort();
+            let mut in_left = in_left;
+            in_left.sort();
+            assert_eq!(in_left, in_reversed);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use vello::peniko::{Brush, Color};
+
+use crate::{MagicConverter, MagicFetcher, PropValue, RenderContext, utils};
+
+/// Solid colours at positions from 0 to 1 in ascending order, interpolated in between.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ColorRamp {
+    pub stops: Vec<(f64, Brush)>,
+}
+
+impl MagicFetcher for ColorRamp {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for ColorRamp {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        match self
+            .stops
+            .iter()
+            .find(|(_, brush)| !matches!(brush, Brush::Solid(_)))
+        {
+            Some((stop, _)) => Err(format!("Color ramp stop {} is not a solid color", stop)),
+            None => Ok(()),
+        }
+    }
+    fn interpolate(&self, other: &Self, t: f64) -> Option<Self> {
+        if self.stops.len() != other.stops.len() {
+            return None;
+        }
+        let stops = self
+            .stops
+            .iter()
+            .zip(other.stops.iter())
+            .map(|((a_stop, a), (b_stop, b))| {
+                Some((a_stop + (b_stop - a_stop) * t, a.interpolate(b, t)?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(ColorRamp { stops })
+    }
+}
+
+impl From<Vec<(f64, Brush)>> for ColorRamp {
+    fn from(stops: Vec<(f64, Brush)>) -> Self {
+        ColorRamp { stops }
+    }
+}
+
+impl ColorRamp {
+    /// Colour at `t`, `None` for an empty ramp.
+    pub fn brush(&self, t: f64) -> Option<Brush> {
+        utils::ramp_brush(&self.stops, t)
+    }
+    /// Colour at `t`, transparent for an empty ramp.
+    pub fn color(&self, t: f64) -> Color {
+        match self.brush(t) {
+            Some(Brush::Solid(color)) => color,
+            _ => Color::TRANSPARENT,
+        }
+    }
+    /// RGBA lookup table for values from 0 to 1.
+    pub fn table(&self) -> Vec<[u8; 4]> {
+        utils::ramp_table(&self.stops)
+    }
+}
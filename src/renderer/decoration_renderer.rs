@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use geo::{Geometry, LineString, MultiLineString, Point};
+use vello::kurbo::{Affine, Rect};
+
+use crate::{
+    MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, RenderedGeometry, utils,
+};
+
+use super::GeometryRenderer;
+
+/// Prop set on every placed symbol, clockwise degrees from screen up along the line.
+pub const BEARING_PROP: &str = "bearing";
+
+/// Pixels around the viewport where symbols are still placed, so those reaching in are drawn.
+const DECORATION_MARGIN: f64 = 64f64;
+
+/// Repeats symbols along lines, each drawn on a point carrying the feature props plus
+/// [`BEARING_PROP`], so e.g. `"rotation": {"Float64": 0, "kind": {"Prop": ["bearing", "Raw"]}}`
+/// turns them with the line. Lengths are in pixels when `need_scale` is set, map units otherwise.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DecorationRenderer {
+    /// Distance between symbols, at least a pixel, and a single symbol at `start_offset` when
+    /// not positive.
+    pub interval: MagicValue<PropValue>,
+    pub start_offset: MagicValue<PropValue>,
+    pub end_offset: MagicValue<PropValue>,
+    pub symbols: Vec<MagicValue<GeometryRenderer>>,
+}
+
+impl std::default::Default for DecorationRenderer {
+    fn default() -> Self {
+        Self {
+            interval: MagicValue::wrap(64f64).with_need_scale(true),
+            start_offset: MagicValue::wrap(32f64).with_need_scale(true),
+            end_offset: MagicValue::wrap(0f64),
+            symbols: Vec::new(),
+        }
+    }
+}
+
+impl MagicFetcher for DecorationRenderer {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.interval.fetch()?;
+        self.start_offset.fetch()?;
+        self.end_offset.fetch()?;
+        for renderer in self.symbols.iter_mut() {
+            renderer.fetch()?;
+        }
+        Ok(())
+    }
+}
+
+impl MagicConverter for DecorationRenderer {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.interval.convert(props, context)?;
+        self.start_offset.convert(props, context)?;
+        self.end_offset.convert(props, context)?;
+        // symbols are converted per placed point, where the bearing is known
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.interval.prepare(layer)?;
+        self.start_offset.prepare(layer)?;
+        self.end_offset.prepare(layer)?;
+        for renderer in self.symbols.iter_mut() {
+            renderer.prepare(layer)?;
+        }
+        Ok(())
+    }
+}
+
+impl DecorationRenderer {
+    /// Symbol positions along `line` in map units, with their bearings in degrees. Positions
+    /// further than [`DECORATION_MARGIN`] out of the `pixel_size` viewport are skipped.
+    pub fn positions(
+        &self,
+        transform: Affine,
+        pixel_size: (u32, u32),
+        line: &LineString,
+    ) -> Result<Vec<(Point, f64)>, String> {
+        let path = utils::pixel_path(transform, line);
+        let length = utils::path_length(&path);
+        let interval = self.interval.pixel_length(transform)?;
+        let start = self.start_offset.pixel_length(transform)?;
+        let end = length - self.end_offset.pixel_length(transform)?;
+        let viewport = Rect::new(0f64, 0f64, pixel_size.0 as f64, pixel_size.1 as f64)
+            .inflate(DECORATION_MARGIN, DECORATION_MARGIN);
+        let inverse = transform.inverse();
+        let mut positions = Vec::new();
+        let mut distance = start;
+        while distance <= end {
+            if let Some((position, angle)) = utils::point_along_path(&path, distance)
+                && viewport.contains(position)
+            {
+                let position = inverse * position;
+                positions.push((
+                    Point::new(position.x, position.y),
+                    angle.to_degrees() + 90f64,
+                ));
+            }
+            if interval <= 0f64 {
+                break;
+            }
+            distance += interval.max(1f64);
+        }
+        Ok(positions)
+    }
+    /// Grows the symbols by `grow` pixels on each side, false when one can not grow.
+    pub(crate) fn widen(&mut self, grow: f64) -> bool {
+        self.symbols
+            .iter_mut()
+            .all(|renderer| renderer.as_mut().widen(grow))
+    }
+    pub fn draw(
+        &mut self,
+        scene: &mut vello::Scene,
+        transform: Affine,
+        context: &RenderContext,
+        props: &HashMap<String, PropValue>,
+        line: &LineString,
+    ) -> Result<(), String> {
+        let mut rendered_geometrys: Vec<RenderedGeometry> = self
+            .positions(transform, context.pixel_size, line)?
+            .into_iter()
+            .map(|(point, bearing)| {
+                let mut props = props.clone();
+                props.insert(BEARING_PROP.to_string(), bearing.into());
+                let geom: Geometry = point.into();
+                RenderedGeometry::new_temp(props, geom)
+            })
+            .collect();
+        if rendered_geometrys.is_empty() {
+            return Ok(());
+        }
+        for renderer in self.symbols.iter_mut().map(|x| x.as_mut()) {
+            renderer.draw(scene, transform, context, &mut rendered_geometrys, None)?;
+        }
+        Ok(())
+    }
+    pub fn draw_multi(
+        &mut self,
+        scene: &mut vello::Scene,
+        transform: Affine,
+        context: &RenderContext,
+        props: &HashMap<String, PropValue>,
+        lines: &MultiLineString,
+    ) -> Result<(), String> {
+        for line in lines {
+            self.draw(scene, transform, context, props, line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PointRenderer;
+
+    #[test]
+    fn symbols_turn_with_the_bearing_prop() {
+        let rotation: MagicValue<PropValue> =
+            ron::from_str(r#"{"Float64": 0.0, "kind": Prop("bearing", Raw)}"#).unwrap();
+        let symbol = PointRenderer {
+            rotation,
+            ..Default::default()
+        };
+        let mut decoration = DecorationRenderer {
+            interval: MagicValue::wrap(0f64),
+            start_offset: MagicValue::wrap(50f64),
+            symbols: vec![GeometryRenderer::Point(Default::default(), symbol.into()).into()],
+            ..Default::default()
+        };
+        let line = LineString::from(vec![(0f64, 0f64), (100f64, 0f64)]);
+        let mut scene = vello::Scene::new();
+        decoration
+            .draw(
+                &mut scene,
+                Affine::IDENTITY,
+                &RenderContext::default(),
+                &HashMap::new(),
+                &line,
+            )
+            .unwrap();
+        // a line heading right has a bearing of 90 degrees
+        let turned = scene.encoding().transforms.iter().any(|transform| {
+            let [a, b, c, d] = transform.matrix;
+            a.abs() < 1e-6 && (b - 1f32).abs() < 1e-6 && (c + 1f32).abs() < 1e-6 && d.abs() < 1e-6
+        });
+        assert!(turned);
+    }
+
+    fn distances(decoration: &DecorationRenderer, line: &LineString) -> Vec<f64> {
+        decoration
+            .positions(Affine::IDENTITY, (100, 100), line)
+            .unwrap()
+            .into_iter()
+            .map(|(point, _)| point.x())
+            .collect()
+    }
+
+    #[test]
+    fn symbols_repeat_between_the_offsets() {
+        let line = LineString::from(vec![(0f64, 50f64), (100f64, 50f64)]);
+        let mut decoration = DecorationRenderer {
+            interval: MagicValue::wrap(30f64),
+            start_offset: MagicValue::wrap(10f64),
+            ..Default::default()
+        };
+        assert_eq!(distances(&decoration, &line), [10f64, 40f64, 70f64, 100f64]);
+        decoration.end_offset = MagicValue::wrap(30f64);
+        assert_eq!(distances(&decoration, &line), [10f64, 40f64, 70f64]);
+        decoration.start_offset = MagicValue::wrap(80f64);
+        assert!(distances(&decoration, &line).is_empty());
+    }
+
+    #[test]
+    fn non_positive_intervals_place_a_single_symbol() {
+        let line = LineString::from(vec![(0f64, 50f64), (100f64, 50f64)]);
+        for interval in [0f64, -5f64] {
+            let decoration = DecorationRenderer {
+                interval: MagicValue::wrap(interval),
+                start_offset: MagicValue::wrap(20f64),
+                ..Default::default()
+            };
+            assert_eq!(distances(&decoration, &line), [20f64]);
+        }
+        // tiny intervals still advance a pixel at a time
+        let decoration = DecorationRenderer {
+            interval: MagicValue::wrap(1e-9),
+            start_offset: MagicValue::wrap(0f64),
+            ..Default::default()
+        };
+        assert_eq!(distances(&decoration, &line).len(), 101);
+    }
+
+    #[test]
+    fn symbols_far_outside_the_viewport_are_skipped() {
+        let line = LineString::from(vec![(-1000f64, 50f64), (1000f64, 50f64)]);
+        let decoration = DecorationRenderer {
+            interval: MagicValue::wrap(100f64),
+            start_offset: MagicValue::wrap(50f64),
+            ..Default::default()
+        };
+        // within 64 pixels of the 100 pixel viewport
+        assert_eq!(distances(&decoration, &line), [-50f64, 50f64, 150f64]);
+    }
+}
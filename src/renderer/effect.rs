@@ -0,0 +1,265 @@
+use std::{collections::HashMap, f64::consts::TAU};
+
+use vello::{
+    Scene,
+    kurbo::{Affine, Rect, Vec2},
+    peniko::{BlendMode, Brush, Compose, Fill, Mix, color::palette},
+};
+
+use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext};
+
+use super::GeometryRenderer;
+
+/// Pixels between the copies drawn over the grown area for renderers whose strokes can not be
+/// widened, so every point of it is within a pixel of a copy.
+const EFFECT_COPY_SPACING: f64 = 1f64;
+/// Most copies drawn for a ring, beyond which they are spaced further apart.
+const EFFECT_MAX_COPIES: usize = 512;
+/// Rings a blur fades out over.
+const EFFECT_BLUR_RINGS: usize = 3;
+/// Pixel extent of effect layers, larger than any rendered image.
+const EFFECT_EXTENT: Rect = Rect::new(-1e5, -1e5, 1e5, 1e5);
+
+/// Silhouette of a renderer's drawing in `brush`, drawn beneath it. An offset and blur make a
+/// drop shadow, a blur alone a glow, and a spread a halo or casing. Lengths are pixels.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Effect {
+    pub brush: MagicValue<Brush>,
+    pub opacity: MagicValue<PropValue>,
+    /// Shift to the right.
+    pub offset_x: MagicValue<PropValue>,
+    /// Shift downwards.
+    pub offset_y: MagicValue<PropValue>,
+    /// Distance the silhouette is grown by. Renderers whose strokes can not be widened, such as
+    /// icons and text, are drawn again at offsets over the disc of the spread, spaced as
+    /// `EFFECT_COPY_SPACING` allows within `EFFECT_MAX_COPIES`, so wide spreads are coarser
+    /// around thin parts.
+    pub spread: MagicValue<PropValue>,
+    /// Distance the edge fades out over, beyond the spread. Each of its `EFFECT_BLUR_RINGS`
+    /// rings draws the renderer again, or its copies when its strokes can not be widened.
+    pub blur: MagicValue<PropValue>,
+}
+
+impl std::default::Default for Effect {
+    fn default() -> Self {
+        Effect {
+            brush: Brush::Solid(palette::css::BLACK).into(),
+            opacity: MagicValue::wrap(0.5f64),
+            offset_x: MagicValue::wrap(2f64),
+            offset_y: MagicValue::wrap(2f64),
+            spread: MagicValue::wrap(0f64),
+            blur: MagicValue::wrap(2f64),
+        }
+    }
+}
+
+impl MagicFetcher for Effect {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.brush.fetch()?;
+        self.opacity.fetch()?;
+        self.offset_x.fetch()?;
+        self.offset_y.fetch()?;
+        self.spread.fetch()?;
+        self.blur.fetch()?;
+        Ok(())
+    }
+}
+
+impl MagicConverter for Effect {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.brush.convert(props, context)?;
+        self.opacity.convert(props, context)?;
+        self.offset_x.convert(props, context)?;
+        self.offset_y.convert(props, context)?;
+        self.spread.convert(props, context)?;
+        self.blur.convert(props, context)?;
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.brush.prepare(layer)?;
+        self.opacity.prepare(layer)?;
+        self.offset_x.prepare(layer)?;
+        self.offset_y.prepare(layer)?;
+        self.spread.prepare(layer)?;
+        self.blur.prepare(layer)?;
+        Ok(())
+    }
+}
+
+impl Effect {
+    /// Pixel shift of the silhouette.
+    pub fn offset(&self) -> Result<Vec2, String> {
+        let offset_x: f64 = self.offset_x.inner_try_into()?;
+        let offset_y: f64 = self.offset_y.inner_try_into()?;
+        Ok(Vec2::new(offset_x, offset_y))
+    }
+    /// Opacity of each ring and the pixels the silhouette grows by in it, outermost ring first.
+    pub fn rings(&self) -> Result<Vec<(f32, f64)>, String> {
+        let spread = self.spread.inner_try_into::<f64>()?.max(0f64);
+        let blur: f64 = self.blur.inner_try_into()?;
+        let mut rings = Vec::new();
+        if blur > 0f64 {
+            for index in (1..=EFFECT_BLUR_RINGS).rev() {
+                let fraction = index as f64 / EFFECT_BLUR_RINGS as f64;
+                let alpha = 1f64 - index as f64 / (EFFECT_BLUR_RINGS + 1) as f64;
+                rings.push((alpha as f32, spread + blur * fraction));
+            }
+        }
+        rings.push((1f32, spread));
+        Ok(rings)
+    }
+    /// Pixel offsets of the copies that grow a silhouette by `grow` when its strokes can not be
+    /// widened: the drawing itself and concentric rings out to `grow`, spaced a pixel apart
+    /// across and along them, or further apart to keep within [`EFFECT_MAX_COPIES`].
+    pub fn copy_offsets(grow: f64) -> Vec<Vec2> {
+        if grow <= 0f64 {
+            return vec![Vec2::ZERO];
+        }
+        // ring radii and the copies around each
+        let rings = |spacing: f64| {
+            let count = (grow / spacing).ceil() as usize;
+            (1..=count).map(move |index| {
+                let radius = grow * index as f64 / count as f64;
+                (radius, (TAU * radius / spacing).ceil() as usize)
+            })
+        };
+        let mut spacing = EFFECT_COPY_SPACING;
+        while rings(spacing).map(|(_, copies)| copies).sum::<usize>() > EFFECT_MAX_COPIES {
+            spacing *= 1.05;
+        }
+        let mut offsets = vec![Vec2::ZERO];
+        for (radius, copies) in rings(spacing) {
+            offsets.extend(
+                (0..copies)
+                    .map(|index| Vec2::from_angle(TAU * index as f64 / copies as f64) * radius),
+            );
+        }
+        offsets
+    }
+    /// Draws the silhouette of `renderer` with `draw`, once for each ring and copy, then
+    /// `renderer` itself over it.
+    pub fn draw_with(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        renderer: &mut GeometryRenderer,
+        mut draw: impl FnMut(&mut Scene, Affine, &mut GeometryRenderer) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let shifted = Affine::translate(self.offset()?) * transform;
+        self.push_layer(scene)?;
+        for (alpha, grow) in self.rings()? {
+            self.push_ring(scene, alpha);
+            let widened = if grow > 0f64 {
+                renderer.widened(grow)
+            } else {
+                None
+            };
+            match widened {
+                // strokes grow evenly, where copies would leave stripes beside thin lines
+                Some(mut widened) => draw(scene, shifted, &mut widened)?,
+                None => {
+                    for offset in Effect::copy_offsets(grow) {
+                        draw(scene, Affine::translate(offset) * shifted, renderer)?;
+                    }
+                }
+            }
+            self.pop_ring(scene);
+        }
+        self.pop_layer(scene);
+        draw(scene, transform, renderer)
+    }
+    /// Starts the layer the rings are drawn in, ended with [`Effect::pop_layer`].
+    pub fn push_layer(&self, scene: &mut Scene) -> Result<(), String> {
+        let opacity: f64 = self.opacity.inner_try_into()?;
+        scene.push_layer(
+            Mix::Normal,
+            opacity as f32,
+            Affine::IDENTITY,
+            &EFFECT_EXTENT,
+        );
+        Ok(())
+    }
+    /// Starts a ring drawn at `alpha`, ended with [`Effect::pop_ring`].
+    pub fn push_ring(&self, scene: &mut Scene, alpha: f32) {
+        scene.push_layer(Mix::Normal, alpha, Affine::IDENTITY, &EFFECT_EXTENT);
+    }
+    /// Recolours the copies drawn in the ring with `brush`, keeping their coverage.
+    pub fn pop_ring(&self, scene: &mut Scene) {
+        scene.push_layer(
+            BlendMode::new(Mix::Normal, Compose::SrcIn),
+            1f32,
+            Affine::IDENTITY,
+            &EFFECT_EXTENT,
+        );
+        scene.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            self.brush.as_ref(),
+            None,
+            &EFFECT_EXTENT,
+        );
+        scene.pop_layer();
+        scene.pop_layer();
+    }
+    pub fn pop_layer(&self, scene: &mut Scene) {
+        scene.pop_layer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Largest distance to the nearest copy from points `1 / density` pixels apart over the
+    /// disc of radius `grow`.
+    fn widest_gap(grow: f64, density: f64) -> f64 {
+        let offsets = Effect::copy_offsets(grow);
+        let steps = (grow * density).ceil() as i64;
+        let mut widest = 0f64;
+        for x in -steps..=steps {
+            for y in -steps..=steps {
+                let point = Vec2::new(x as f64, y as f64) * (grow / steps as f64);
+                if point.length() > grow {
+                    continue;
+                }
+                let nearest = offsets
+                    .iter()
+                    .map(|offset| (*offset - point).length())
+                    .fold(f64::INFINITY, f64::min);
+                widest = widest.max(nearest);
+            }
+        }
+        widest
+    }
+
+    #[test]
+    fn copies_cover_the_grown_disc() {
+        assert_eq!(Effect::copy_offsets(0f64), [Vec2::ZERO]);
+        // every point of the spread is near a copy, so pixel-wide strokes grow without gaps
+        for grow in [0.5, 1f64, 2.5, 6f64, 10f64] {
+            let gap = widest_gap(grow, 8f64);
+            assert!(gap <= 0.75 * EFFECT_COPY_SPACING, "{} at {}", gap, grow);
+        }
+        let offsets = Effect::copy_offsets(6f64);
+        assert!(offsets.iter().all(|offset| offset.length() <= 6f64 + 1e-9));
+        assert!(
+            offsets
+                .iter()
+                .any(|offset| (offset.length() - 6f64).abs() < 1e-9)
+        );
+    }
+
+    #[test]
+    fn copies_are_capped_for_large_grows() {
+        let offsets = Effect::copy_offsets(40f64);
+        assert!(offsets.len() <= EFFECT_MAX_COPIES + 1);
+        // still spread evenly over the whole disc, only further apart
+        let gap = widest_gap(40f64, 1f64);
+        assert!(gap < 40f64 * (std::f64::consts::PI / EFFECT_MAX_COPIES as f64).sqrt() * 1.5);
+    }
+}
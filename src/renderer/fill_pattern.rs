@@ -0,0 +1,288 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use vello::{
+    Scene,
+    kurbo::{Affine, BezPath, Circle, Point, Rect, Shape, Stroke, Vec2},
+    peniko::{Brush, Fill, Mix, color::palette},
+};
+
+use crate::{MagicConverter, MagicFetcher, PropValue, RenderContext};
+
+use super::load_icon;
+
+/// Patterns needing more hatch lines, dots or tiles than this for one polygon are thinned out,
+/// keeping every second, fourth, ... item counted from the map origin.
+const MAX_PATTERN_ITEMS: usize = 100_000;
+
+/// Pattern drawn over an area fill. Lengths are map units, or pixels when the wrapping
+/// `MagicValue` has `need_scale`. Patterns are laid out from the map origin, so neighbouring
+/// tiles continue each other.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub enum FillPattern {
+    #[default]
+    None,
+    /// Parallel lines at `angle` degrees from the x axis.
+    Hatch {
+        angle: f64,
+        spacing: f64,
+        width: f64,
+        #[serde(default = "default_pattern_brush")]
+        brush: Brush,
+    },
+    /// Dots on a square grid, every other row shifted by half a step when `staggered`.
+    Dots {
+        spacing: f64,
+        radius: f64,
+        #[serde(default)]
+        staggered: bool,
+        #[serde(default = "default_pattern_brush")]
+        brush: Brush,
+    },
+    /// SVG or raster image repeated on a grid, `size` being the longer tile side.
+    Tile { path: String, size: f64 },
+}
+
+fn default_pattern_brush() -> Brush {
+    Brush::Solid(palette::css::BLACK)
+}
+
+impl MagicFetcher for FillPattern {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for FillPattern {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl FillPattern {
+    /// Draws the pattern clipped to `shape`, over the part of it inside `bounds`.
+    ///
+    /// `factor` turns the pattern lengths into map units.
+    pub fn draw(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        factor: f64,
+        shape: &BezPath,
+        bounds: Rect,
+    ) -> Result<(), String> {
+        if matches!(self, FillPattern::None) || bounds.is_zero_area() {
+            return Ok(());
+        }
+        scene.push_layer(Mix::Clip, 1f32, transform, shape);
+        let result = self.draw_unclipped(scene, transform, factor, bounds);
+        scene.pop_layer();
+        result
+    }
+    fn draw_unclipped(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        factor: f64,
+        bounds: Rect,
+    ) -> Result<(), String> {
+        match self {
+            FillPattern::None => {}
+            FillPattern::Hatch {
+                angle,
+                spacing,
+                width,
+                brush,
+            } => {
+                let spacing = spacing * factor;
+                if spacing <= 0f64 {
+                    return Ok(());
+                }
+                let direction = Vec2::from_angle(angle.to_radians());
+                let normal = direction.turn_90();
+                let corners = [
+                    bounds.origin(),
+                    (bounds.x1, bounds.y0).into(),
+                    (bounds.x0, bounds.y1).into(),
+                    (bounds.x1, bounds.y1).into(),
+                ];
+                let project = |vector: Vec2| corners.map(|corner| corner.to_vec2().dot(vector));
+                let (across, along) = (project(normal), project(direction));
+                let min = |values: [f64; 4]| values.into_iter().fold(f64::INFINITY, f64::min);
+                let max = |values: [f64; 4]| values.into_iter().fold(f64::NEG_INFINITY, f64::max);
+                // lines a half width outside the bounds still reach into them
+                let half_width = width * factor / 2f64;
+                let indices =
+                    grid_range(min(across) - half_width, max(across) + half_width, spacing);
+                let stride = thinning(indices.clone().count() as f64, 1);
+                let (start, end) = (min(along), max(along));
+                let mut path = BezPath::new();
+                for index in strided(indices, stride) {
+                    let offset = normal * (index as f64 * spacing);
+                    path.move_to((offset + direction * start).to_point());
+                    path.line_to((offset + direction * end).to_point());
+                }
+                scene.stroke(&Stroke::new(width * factor), transform, brush, None, &path);
+            }
+            FillPattern::Dots {
+                spacing,
+                radius,
+                staggered,
+                brush,
+            } => {
+                let spacing = spacing * factor;
+                if spacing <= 0f64 {
+                    return Ok(());
+                }
+                let mut path = BezPath::new();
+                let radius = radius * factor;
+                for center in dot_centers(bounds, spacing, radius, *staggered) {
+                    path.extend(Circle::new(center, radius).path_elements(radius * 1e-3));
+                }
+                scene.fill(Fill::NonZero, transform, brush, None, &path);
+            }
+            FillPattern::Tile { path, size } => {
+                let icon = match load_icon(path) {
+                    Ok(icon) => icon,
+                    Err(e) => {
+                        log::warn!("Skip tile pattern: {}", e);
+                        return Ok(());
+                    }
+                };
+                let (width, height) = icon.size();
+                let tile_scale = size * factor / width.max(height);
+                let (tile_width, tile_height) = (width * tile_scale, height * tile_scale);
+                if tile_width <= 0f64 || tile_height <= 0f64 {
+                    return Ok(());
+                }
+                let columns = grid_range(bounds.x0, bounds.x1, tile_width);
+                let rows = grid_range(bounds.y0, bounds.y1, tile_height);
+                let stride = thinning(
+                    columns.clone().count() as f64 * rows.clone().count() as f64,
+                    2,
+                );
+                for row in strided(rows, stride) {
+                    for column in strided(columns.clone(), stride) {
+                        // tiles keep the icon upright, their top left at the cell's top left
+                        let corner = (column as f64 * tile_width, (row + 1) as f64 * tile_height);
+                        let icon_transform = transform
+                            * Affine::translate(corner)
+                            * Affine::scale_non_uniform(tile_scale, -tile_scale);
+                        icon.draw(scene, icon_transform, None);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Indices of grid cells of `step` covering `min..max`, counted from the origin.
+fn grid_range(min: f64, max: f64, step: f64) -> RangeInclusive<i64> {
+    (min / step).floor() as i64..=(max / step).floor() as i64
+}
+
+/// Power of two step through grid indices keeping at most [`MAX_PATTERN_ITEMS`] of `count`
+/// items laid out in `dimensions` directions.
+fn thinning(count: f64, dimensions: i32) -> i64 {
+    let mut stride = 1i64;
+    while count / (stride as f64).powi(dimensions) > MAX_PATTERN_ITEMS as f64 {
+        stride *= 2;
+    }
+    stride
+}
+
+/// Indices in `range` that are multiples of `stride`, so thinned patterns still line up
+/// across tiles.
+fn strided(range: RangeInclusive<i64>, stride: i64) -> impl Iterator<Item = i64> + Clone {
+    let first = range.start().div_euclid(stride) * stride;
+    let first = if first < *range.start() {
+        first + stride
+    } else {
+        first
+    };
+    (first..=*range.end()).step_by(stride as usize)
+}
+
+/// Centres of the dots reaching into `bounds`, in map units.
+fn dot_centers(bounds: Rect, spacing: f64, radius: f64, staggered: bool) -> Vec<Point> {
+    // staggered rows move right by half a step, so columns start half a step further left
+    let shift = if staggered { spacing / 2f64 } else { 0f64 };
+    let columns = grid_range(bounds.x0 - radius - shift, bounds.x1 + radius, spacing);
+    let rows = grid_range(bounds.y0 - radius, bounds.y1 + radius, spacing);
+    let stride = thinning(
+        columns.clone().count() as f64 * rows.clone().count() as f64,
+        2,
+    );
+    let mut centers = Vec::new();
+    for row in strided(rows, stride) {
+        let row_shift = if row.rem_euclid(2) == 1 { shift } else { 0f64 };
+        for column in strided(columns.clone(), stride) {
+            centers.push(Point::new(
+                column as f64 * spacing + row_shift,
+                row as f64 * spacing,
+            ));
+        }
+    }
+    centers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reaches(center: Point, radius: f64, bounds: Rect) -> bool {
+        let nearest = Point::new(
+            center.x.clamp(bounds.x0, bounds.x1),
+            center.y.clamp(bounds.y0, bounds.y1),
+        );
+        nearest.distance(center) < radius
+    }
+
+    #[test]
+    fn dots_continue_across_tile_seams() {
+        // one polygon spanning two tiles, each tile seeing only its own part
+        let (left, right) = (Rect::new(0., 0., 11., 11.), Rect::new(11., 0., 22., 11.));
+        let whole = left.union(right);
+        for staggered in [false, true] {
+            let (spacing, radius) = (3f64, 1.2f64);
+            let all = dot_centers(whole, spacing, radius, staggered);
+            for tile in [left, right] {
+                let drawn = dot_centers(tile, spacing, radius, staggered);
+                for center in all.iter().filter(|center| reaches(**center, radius, tile)) {
+                    assert!(
+                        drawn.iter().any(|other| other.distance(*center) < 1e-9),
+                        "dot at {center:?} missing from {tile:?}"
+                    );
+                }
+            }
+        }
+        // half-dots past the right and top edges of the left tile
+        let drawn = dot_centers(left, 3f64, 1.2f64, false);
+        assert!(drawn.contains(&Point::new(12., 3.)));
+        assert!(drawn.contains(&Point::new(3., 12.)));
+    }
+
+    #[test]
+    fn dense_patterns_are_thinned_in_line() {
+        let bounds = Rect::new(0., 0., 1000., 1000.);
+        let dots = dot_centers(bounds, 1f64, 0.1f64, false);
+        assert!(dots.len() <= MAX_PATTERN_ITEMS);
+        assert!(!dots.is_empty());
+        // the thinned grid keeps every n-th dot of the full grid from the origin, so a
+        // neighbouring tile with as many dots thins to the same positions
+        let stride = dots[1].x - dots[0].x;
+        assert!(stride > 1f64);
+        assert!(dots.iter().all(|dot| (dot.x / stride).fract() == 0f64));
+        let neighbour = dot_centers(Rect::new(1000., 0., 2000., 1000.), 1f64, 0.1f64, false);
+        assert!(neighbour.iter().all(|dot| (dot.x / stride).fract() == 0f64));
+    }
+
+    #[test]
+    fn strided_indices_are_multiples() {
+        assert_eq!(strided(-5..=5, 4).collect::<Vec<_>>(), [-4, 0, 4]);
+        assert_eq!(strided(-5..=5, 1).count(), 11);
+        assert_eq!(thinning(MAX_PATTERN_ITEMS as f64, 2), 1);
+        assert_eq!(thinning(MAX_PATTERN_ITEMS as f64 * 4f64, 2), 2);
+        assert_eq!(thinning(MAX_PATTERN_ITEMS as f64 * 4f64, 1), 4);
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use geo::{Coord, LineString, Point};
+use vello::{
+    Scene,
+    kurbo::{self, Affine, ParamCurve, QuadBez, Vec2},
+};
+
+use crate::{
+    MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, RenderedGeometry, utils,
+};
+
+use super::{LineRenderer, PointRenderer};
+
+/// Number of segments curved edges are drawn with.
+const EDGE_SEGMENTS: usize = 32;
+
+/// Geometry index of an edge with the positions of its source and target among the nodes.
+pub type GraphEdge = (usize, usize, usize);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum EdgeShape {
+    #[default]
+    Straight,
+    /// Quadratic bezier peaking `bend` edge lengths left of the straight edge.
+    Bezier,
+    /// Circular arc peaking `bend` edge lengths left of the straight edge.
+    Arc,
+}
+
+impl MagicFetcher for EdgeShape {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for EdgeShape {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Draws node/edge data. Nodes are geometries with `id_prop`, or a feature id, drawn with
+/// `node` at their center. Edges are geometries with `source_prop` and `target_prop`, drawn
+/// with `edge` and `arrow` between the nodes they name, using their own props.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct GraphRenderer {
+    pub id_prop: String,
+    pub source_prop: String,
+    pub target_prop: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<MagicValue<PointRenderer>>,
+    pub edge: MagicValue<LineRenderer>,
+    pub shape: MagicValue<EdgeShape>,
+    /// Negative values bend edges to the right, so opposite edges between two nodes part.
+    pub bend: MagicValue<PropValue>,
+    /// Drawn turned along the edge, its tip on the outline of the target node symbol.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrow: Option<MagicValue<PointRenderer>>,
+}
+
+impl std::default::Default for GraphRenderer {
+    fn default() -> Self {
+        GraphRenderer {
+            id_prop: "id".to_string(),
+            source_prop: "source".to_string(),
+            target_prop: "target".to_string(),
+            node: Some(PointRenderer::default().into()),
+            edge: LineRenderer::default().into(),
+            shape: EdgeShape::Straight.into(),
+            bend: MagicValue::wrap(0.2f64),
+            arrow: None,
+        }
+    }
+}
+
+impl MagicFetcher for GraphRenderer {
+    fn fetch(&mut self) -> Result<(), String> {
+        if let Some(node) = self.node.as_mut() {
+            node.fetch()?;
+        }
+        self.edge.fetch()?;
+        self.shape.fetch()?;
+        self.bend.fetch()?;
+        if let Some(arrow) = self.arrow.as_mut() {
+            arrow.fetch()?;
+        }
+        Ok(())
+    }
+}
+
+impl MagicConverter for GraphRenderer {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        // nodes and edges are converted with their own props in `draw`
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        if let Some(node) = self.node.as_mut() {
+            node.prepare(layer)?;
+        }
+        self.edge.prepare(layer)?;
+        self.bend.prepare(layer)?;
+        if let Some(arrow) = self.arrow.as_mut() {
+            arrow.prepare(layer)?;
+        }
+        Ok(())
+    }
+}
+
+impl GraphRenderer {
+    fn is_edge(&self, rendered_geometry: &RenderedGeometry) -> bool {
+        let props = rendered_geometry.props();
+        props.contains_key(&self.source_prop) && props.contains_key(&self.target_prop)
+    }
+    fn node_id(&self, rendered_geometry: &RenderedGeometry) -> Option<String> {
+        rendered_geometry
+            .props()
+            .get(&self.id_prop)
+            .or(rendered_geometry.id())
+            .map(|id| id.to_string())
+    }
+    /// Edge from `source` to `target` in map units, sampled when curved.
+    pub fn edge_line(&self, source: Point, target: Point) -> Result<LineString, String> {
+        let (start, end) = (
+            kurbo::Point::new(source.x(), source.y()),
+            kurbo::Point::new(target.x(), target.y()),
+        );
+        let chord = end - start;
+        let length = chord.hypot();
+        let bend: f64 = self.bend.inner_try_into()?;
+        let height = bend * length;
+        let to_coord = |point: kurbo::Point| Coord {
+            x: point.x,
+            y: point.y,
+        };
+        if length == 0f64 || height == 0f64 {
+            return Ok(LineString::new(vec![to_coord(start), to_coord(end)]));
+        }
+        // left of the edge direction, y pointing up in map space
+        let normal = Vec2::new(-chord.y, chord.x) / length;
+        let middle = start.midpoint(end);
+        let points: Vec<kurbo::Point> = match self.shape.as_ref() {
+            EdgeShape::Straight => vec![start, end],
+            EdgeShape::Bezier => {
+                let curve = QuadBez::new(start, middle + normal * 2f64 * height, end);
+                (0..=EDGE_SEGMENTS)
+                    .map(|index| curve.eval(index as f64 / EDGE_SEGMENTS as f64))
+                    .collect()
+            }
+            EdgeShape::Arc => {
+                let radius = (length * length / 4f64 + height * height) / (2f64 * height);
+                let center = middle + normal * (height - radius);
+                let angle = |point: kurbo::Point| (point - center).atan2();
+                let first = angle(start);
+                let mut half_sweep = angle(middle + normal * height) - first;
+                if half_sweep > std::f64::consts::PI {
+                    half_sweep -= std::f64::consts::TAU;
+                } else if half_sweep < -std::f64::consts::PI {
+                    half_sweep += std::f64::consts::TAU;
+                }
+                (0..=EDGE_SEGMENTS)
+                    .map(|index| {
+                        let t = index as f64 / EDGE_SEGMENTS as f64;
+                        center + Vec2::from_angle(first + 2f64 * half_sweep * t) * radius.abs()
+                    })
+                    .collect()
+            }
+        };
+        Ok(points.into_iter().map(to_coord).collect())
+    }
+    /// Nodes among `geom_indices` with their centers, and the edges between them as their
+    /// geometry index with the positions of their source and target in the nodes. Edges naming
+    /// a missing node, or the same node twice, are left out.
+    pub fn match_edges(
+        &self,
+        rendered_geometrys: &mut [RenderedGeometry],
+        geom_indices: &[usize],
+    ) -> (Vec<(usize, Point)>, Vec<GraphEdge>) {
+        let mut nodes = Vec::new();
+        let mut node_ids = HashMap::new();
+        let mut edges = Vec::new();
+        for geom_index in geom_indices {
+            let rendered_geometry = &mut rendered_geometrys[*geom_index];
+            if self.is_edge(rendered_geometry) {
+                edges.push(*geom_index);
+            } else if let Some(id) = self.node_id(rendered_geometry)
+                && let Some(point) = rendered_geometry.center_point(None)
+            {
+                node_ids.insert(id, nodes.len());
+                nodes.push((*geom_index, *point));
+            }
+        }
+        let edges = edges
+            .into_iter()
+            .filter_map(|geom_index| {
+                let props = rendered_geometrys[geom_index].props();
+                let endpoint = |prop: &str| {
+                    props
+                        .get(prop)
+                        .and_then(|id| node_ids.get(&id.to_string()))
+                        .copied()
+                };
+                let (source, target) = (endpoint(&self.source_prop)?, endpoint(&self.target_prop)?);
+                (source != target).then_some((geom_index, source, target))
+            })
+            .collect();
+        (nodes, edges)
+    }
+    /// Map-space center and clockwise screen rotation in degrees of an arrow whose tip lies
+    /// `pull_back` pixels before the end of `line`, following curved edges.
+    pub fn arrow_placement(
+        transform: Affine,
+        line: &LineString,
+        pull_back: f64,
+    ) -> Option<(Point, f64)> {
+        let mut path = utils::pixel_path(transform, line);
+        path.reverse();
+        let (position, angle) = utils::point_along_path(&path, pull_back.max(0f64))?;
+        // the reversed path points back to the source
+        let direction = -Vec2::from_angle(angle);
+        let center = transform.inverse() * position;
+        Some((
+            Point::new(center.x, center.y),
+            direction.x.atan2(-direction.y).to_degrees(),
+        ))
+    }
+    pub fn draw(
+        &mut self,
+        scene: &mut Scene,
+        transform: Affine,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
+        geom_indices: &[usize],
+    ) -> Result<(), String> {
+        let (nodes, edges) = self.match_edges(rendered_geometrys, geom_indices);
+        for (geom_index, source, target) in edges {
+            let props = rendered_geometrys[geom_index].props();
+            self.bend.convert(props, context)?;
+            self.shape.convert(props, context)?;
+            let line = self.edge_line(nodes[source].1, nodes[target].1)?;
+            self.edge.convert(props, context)?;
+            self.edge.as_mut().draw(scene, transform, context, &line)?;
+            let Some(arrow) = self.arrow.as_mut() else {
+                continue;
+            };
+            // the target node is drawn over the edge, so the arrow stops at its outline
+            let node_radius = match self.node.as_mut() {
+                Some(node) => {
+                    node.convert(rendered_geometrys[nodes[target].0].props(), context)?;
+                    node.as_ref().radius.pixel_length(transform)?
+                }
+                None => 0f64,
+            };
+            arrow.convert(props, context)?;
+            let mut arrow = arrow.as_ref().clone();
+            let pull_back = node_radius + arrow.radius.pixel_length(transform)?;
+            if let Some((center, rotation)) =
+                GraphRenderer::arrow_placement(transform, &line, pull_back)
+            {
+                arrow.rotation = MagicValue::wrap(rotation);
+                arrow.draw(scene, transform, &center)?;
+            }
+        }
+        if let Some(node) = self.node.as_mut() {
+            for (geom_index, point) in nodes {
+                node.convert(rendered_geometrys[geom_index].props(), context)?;
+                node.as_ref().draw(scene, transform, &point)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{Geometry, LineString};
+
+    use super::*;
+
+    fn graph(shape: EdgeShape, bend: f64) -> GraphRenderer {
+        GraphRenderer {
+            shape: shape.into(),
+            bend: MagicValue::wrap(bend),
+            ..Default::default()
+        }
+    }
+
+    /// Signed distance of the middle vertex from the x axis.
+    fn peak(line: &LineString) -> f64 {
+        line.0[line.0.len() / 2].y
+    }
+
+    #[test]
+    fn edges_run_between_the_nodes() {
+        let (source, target) = (Point::new(0f64, 0f64), Point::new(10f64, 0f64));
+        for shape in [EdgeShape::Straight, EdgeShape::Bezier, EdgeShape::Arc] {
+            for bend in [0.2f64, -0.2f64, 0f64] {
+                let line = graph(shape, bend).edge_line(source, target).unwrap();
+                let (first, last) = (line.0.first().unwrap(), line.0.last().unwrap());
+                assert!((first.x - 0f64).abs() < 1e-9 && first.y.abs() < 1e-9);
+                assert!((last.x - 10f64).abs() < 1e-9 && last.y.abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn positive_bends_go_left() {
+        let (source, target) = (Point::new(0f64, 0f64), Point::new(10f64, 0f64));
+        for shape in [EdgeShape::Bezier, EdgeShape::Arc] {
+            let left = graph(shape, 0.2).edge_line(source, target).unwrap();
+            let right = graph(shape, -0.2).edge_line(source, target).unwrap();
+            assert_eq!(left.0.len(), EDGE_SEGMENTS + 1);
+            // both curves peak `bend` edge lengths away from the chord
+            assert!((peak(&left) - 2f64).abs() < 1e-9, "{shape:?}");
+            assert!((peak(&right) + 2f64).abs() < 1e-9, "{shape:?}");
+        }
+        let straight = graph(EdgeShape::Straight, 0.2)
+            .edge_line(source, target)
+            .unwrap();
+        assert_eq!(straight.0.len(), 2);
+        // reversed edges bend to the other side, so edges both ways part
+        let back = graph(EdgeShape::Arc, 0.2)
+            .edge_line(target, source)
+            .unwrap();
+        assert!((peak(&back) + 2f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn edges_find_their_nodes_by_id() {
+        let node = |props: &[(&str, PropValue)], x: f64| {
+            RenderedGeometry::new_temp(
+                props
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect(),
+                Geometry::Point(Point::new(x, 0f64)),
+            )
+        };
+        let edge = |source: PropValue, target: PropValue| {
+            RenderedGeometry::new_temp(
+                HashMap::from([
+                    ("source".to_string(), source),
+                    ("target".to_string(), target),
+                ]),
+                Geometry::LineString(LineString::from(vec![(0f64, 0f64), (1f64, 1f64)])),
+            )
+        };
+        let mut geometrys = vec![
+            node(&[("id", 1i64.into())], 0f64),
+            edge("1".into(), "b".into()),
+            node(&[], 5f64).with_id(Some("b".into())),
+            edge(1i64.into(), "missing".into()),
+            edge("b".into(), "b".into()),
+            node(&[("name", "no id".into())], 9f64),
+            edge("b".into(), 1i64.into()),
+        ];
+        let renderer = GraphRenderer::default();
+        let (nodes, edges) = renderer.match_edges(&mut geometrys, &[0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            nodes,
+            vec![(0, Point::new(0f64, 0f64)), (2, Point::new(5f64, 0f64))]
+        );
+        assert_eq!(edges, vec![(1, 0, 1), (6, 1, 0)]);
+        // only the geometries handed in take part
+        let (nodes, edges) = renderer.match_edges(&mut geometrys, &[1, 2]);
+        assert_eq!(nodes.len(), 1);
+        assert!(edges.is_empty());
+    }
+
+    #[test]
+    fn arrows_stop_at_the_node_outline() {
+        let line = LineString::from(vec![(0f64, 0f64), (10f64, 0f64)]);
+        // two pixels per map unit, y flipped like the map transform
+        let transform = Affine::new([2f64, 0f64, 0f64, -2f64, 0f64, 0f64]);
+        let (center, rotation) = GraphRenderer::arrow_placement(transform, &line, 6f64).unwrap();
+        assert!((center.x() - 7f64).abs() < 1e-9 && center.y().abs() < 1e-9);
+        // pointing right on screen
+        assert!((rotation - 90f64).abs() < 1e-9);
+        let curved = graph(EdgeShape::Arc, 0.5)
+            .edge_line(Point::new(0f64, 0f64), Point::new(10f64, 0f64))
+            .unwrap();
+        let (center, _) = GraphRenderer::arrow_placement(transform, &curved, 6f64).unwrap();
+        // the arrow follows the curve rather than the chord
+        assert!(center.y() > 0.5);
+        assert!(
+            (Point::new(10f64, 0f64) - center).x().hypot(center.y()) < 3.001,
+            "{center:?}"
+        );
+    }
+}
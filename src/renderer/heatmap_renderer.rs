@@ -0,0 +1,267 @@
+use std::{collections::HashMap, sync::Arc};
+
+use geo::{CoordsIter, Rect as GeoRect};
+use vello::{
+    Scene,
+    kurbo::{self, Affine, Rect},
+    peniko::{Blob, Brush, Image, ImageFormat, color::AlphaColor},
+};
+
+use crate::{
+    GeometryKind, MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext,
+    RenderedGeometry,
+};
+
+use super::ColorRamp;
+
+/// Largest heatmap raster side; larger outputs are sampled on coarser cells and scaled up.
+const MAX_HEATMAP_SIZE: f64 = 4096f64;
+
+/// Accumulates a kernel density of points in pixel space and draws it as an image.
+///
+/// Densities are scaled by `intensity` and clamped to `0..1` rather than normalised by the
+/// maximum, so neighbouring tiles share one colour scale.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct HeatmapRenderer {
+    /// Kernel radius, in pixels when `need_scale` is set.
+    pub radius: MagicValue<PropValue>,
+    /// Contribution of each point, usually taken from a prop.
+    pub weight: MagicValue<PropValue>,
+    pub intensity: MagicValue<PropValue>,
+    /// Colours at densities from 0 to 1, interpolated in between.
+    pub ramp: MagicValue<ColorRamp>,
+}
+
+impl std::default::Default for HeatmapRenderer {
+    fn default() -> Self {
+        let stop = |t: f64, r: u8, g: u8, b: u8, a: u8| {
+            (t, Brush::Solid(AlphaColor::from_rgba8(r, g, b, a)))
+        };
+        HeatmapRenderer {
+            radius: MagicValue::wrap(20f64).with_need_scale(true),
+            weight: MagicValue::wrap(1f64),
+            intensity: MagicValue::wrap(1f64),
+            ramp: ColorRamp::from(vec![
+                stop(0f64, 0, 0, 255, 0),
+                stop(0.2, 0, 0, 255, 160),
+                stop(0.4, 0, 255, 255, 200),
+                stop(0.6, 0, 255, 0, 220),
+                stop(0.8, 255, 255, 0, 240),
+                stop(1f64, 255, 0, 0, 255),
+            ])
+            .into(),
+        }
+    }
+}
+
+impl MagicFetcher for HeatmapRenderer {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.radius.fetch()?;
+        self.weight.fetch()?;
+        self.intensity.fetch()?;
+        self.ramp.fetch()?;
+        Ok(())
+    }
+}
+
+impl MagicConverter for HeatmapRenderer {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.radius.convert(props, context)?;
+        self.weight.convert(props, context)?;
+        self.intensity.convert(props, context)?;
+        self.ramp.convert(props, context)?;
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.radius.prepare(layer)?;
+        self.weight.prepare(layer)?;
+        self.intensity.prepare(layer)?;
+        self.ramp.prepare(layer)?;
+        Ok(())
+    }
+}
+
+impl HeatmapRenderer {
+    /// Weighted map-space points of a geometry, every point for puntal ones, else its center.
+    pub fn weighted_points(
+        &self,
+        rendered_geometry: &mut RenderedGeometry,
+    ) -> Result<Vec<(kurbo::Point, f64)>, String> {
+        let weight: f64 = self.weight.inner_try_into()?;
+        if GeometryKind::Puntal.fit(rendered_geometry.geom()) {
+            return Ok(rendered_geometry
+                .geom()
+                .coords_iter()
+                .map(|coord| (kurbo::Point::new(coord.x, coord.y), weight))
+                .collect());
+        }
+        Ok(rendered_geometry
+            .center_point(None)
+            .map(|point| vec![(kurbo::Point::new(point.x(), point.y()), weight)])
+            .unwrap_or_default())
+    }
+    /// Draws the density of `points` over `render_rect`, or over the points when it is `None`.
+    pub fn draw(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        points: &[(kurbo::Point, f64)],
+        render_rect: Option<GeoRect>,
+    ) -> Result<(), String> {
+        let radius = self.radius.pixel_length(transform)?;
+        if points.is_empty() || radius <= 0f64 {
+            return Ok(());
+        }
+        let pixels: Vec<(kurbo::Point, f64)> = points
+            .iter()
+            .map(|(point, weight)| (transform * *point, *weight))
+            .collect();
+        let bounds = match render_rect {
+            Some(rect) => transform.transform_rect_bbox(Rect::new(
+                rect.min().x,
+                rect.min().y,
+                rect.max().x,
+                rect.max().y,
+            )),
+            None => pixels
+                .iter()
+                .fold(
+                    Rect::from_points(pixels[0].0, pixels[0].0),
+                    |rect, (point, _)| rect.union_pt(*point),
+                )
+                .inflate(radius, radius),
+        }
+        .expand();
+        // large outputs get a coarser raster, scaled back up when drawn
+        let cell = (bounds.width().max(bounds.height()) / MAX_HEATMAP_SIZE).max(1f64);
+        let (density, width, height) = density_grid(&pixels, bounds, radius, cell);
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let image = Image::new(
+            Blob::new(Arc::new(self.colorize(&density)?)),
+            ImageFormat::Rgba8,
+            width as u32,
+            height as u32,
+        );
+        scene.draw_image(
+            &image,
+            Affine::translate(bounds.origin().to_vec2()) * Affine::scale(cell),
+        );
+        Ok(())
+    }
+    /// RGBA pixels of densities scaled by `intensity`, clamped to the ends of the ramp.
+    fn colorize(&self, density: &[f64]) -> Result<Vec<u8>, String> {
+        let intensity: f64 = self.intensity.inner_try_into()?;
+        let table = self.ramp.as_ref().table();
+        Ok(density
+            .iter()
+            .flat_map(|value| {
+                let t = (value * intensity).clamp(0f64, 1f64);
+                table[(t * 255f64).round() as usize]
+            })
+            .collect())
+    }
+}
+
+/// Quartic kernel density of pixel-space `points` over `bounds`, sampled at the centres of
+/// square cells `cell` pixels wide. Returns the densities row by row with the grid size.
+fn density_grid(
+    points: &[(kurbo::Point, f64)],
+    bounds: Rect,
+    radius: f64,
+    cell: f64,
+) -> (Vec<f64>, usize, usize) {
+    let width = (bounds.width() / cell).ceil() as usize;
+    let height = (bounds.height() / cell).ceil() as usize;
+    let mut density = vec![0f64; width * height];
+    if width == 0 || height == 0 {
+        return (density, width, height);
+    }
+    let reach = (radius / cell).ceil() as i64;
+    for (point, weight) in points.iter() {
+        let local = ((point.x - bounds.x0) / cell, (point.y - bounds.y0) / cell);
+        let (center_x, center_y) = (local.0.floor() as i64, local.1.floor() as i64);
+        for y in (center_y - reach).max(0)..=(center_y + reach).min(height as i64 - 1) {
+            for x in (center_x - reach).max(0)..=(center_x + reach).min(width as i64 - 1) {
+                let dx = (x as f64 + 0.5 - local.0) * cell;
+                let dy = (y as f64 + 0.5 - local.1) * cell;
+                let distance_squared = (dx * dx + dy * dy) / (radius * radius);
+                if distance_squared < 1f64 {
+                    // quartic kernel
+                    let falloff = 1f64 - distance_squared;
+                    density[y as usize * width + x as usize] += weight * falloff * falloff;
+                }
+            }
+        }
+    }
+    (density, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(grid: &(Vec<f64>, usize, usize), x: usize, y: usize) -> f64 {
+        grid.0[y * grid.1 + x]
+    }
+
+    #[test]
+    fn quartic_kernel_falls_off_to_the_radius() {
+        let bounds = Rect::new(0f64, 0f64, 21f64, 21f64);
+        // a point on the centre of cell (10, 10)
+        let grid = density_grid(&[(kurbo::Point::new(10.5, 10.5), 2f64)], bounds, 4f64, 1f64);
+        assert_eq!((grid.1, grid.2), (21, 21));
+        assert_eq!(at(&grid, 10, 10), 2f64);
+        // half the radius away: (1 - 0.25)^2
+        assert_eq!(at(&grid, 12, 10), 2f64 * 0.5625);
+        assert_eq!(at(&grid, 10, 8), 2f64 * 0.5625);
+        assert_eq!(at(&grid, 14, 10), 0f64);
+        assert_eq!(at(&grid, 13, 13), 0f64);
+        assert_eq!(grid.0.iter().filter(|value| **value > 0f64).count(), 45);
+    }
+
+    #[test]
+    fn overlapping_points_add_up() {
+        let bounds = Rect::new(0f64, 0f64, 10f64, 10f64);
+        let point = (kurbo::Point::new(4.5, 4.5), 1f64);
+        let single = density_grid(&[point], bounds, 3f64, 1f64);
+        let double = density_grid(&[point, point], bounds, 3f64, 1f64);
+        for (a, b) in single.0.iter().zip(double.0.iter()) {
+            assert_eq!(2f64 * a, *b);
+        }
+    }
+
+    #[test]
+    fn coarse_cells_keep_the_kernel_in_pixels() {
+        let bounds = Rect::new(0f64, 0f64, 40f64, 20f64);
+        let point = (kurbo::Point::new(20f64, 10f64), 1f64);
+        let coarse = density_grid(&[point], bounds, 8f64, 4f64);
+        assert_eq!((coarse.1, coarse.2), (10, 5));
+        // the centre of coarse cell (5, 2) is pixel (22, 10), 2 pixels from the point
+        assert_eq!(at(&coarse, 5, 2), (1f64 - 4f64 / 64f64).powi(2));
+    }
+
+    #[test]
+    fn densities_scale_by_intensity_and_clamp() {
+        let renderer = HeatmapRenderer {
+            intensity: MagicValue::wrap(2f64),
+            ..Default::default()
+        };
+        let table = renderer.ramp.as_ref().table();
+        let pixels = renderer.colorize(&[0f64, 0.25, 0.5, 3f64, -1f64]).unwrap();
+        let pixels: Vec<[u8; 4]> = pixels
+            .chunks(4)
+            .map(|pixel| pixel.try_into().unwrap())
+            .collect();
+        assert_eq!(
+            pixels,
+            [table[0], table[128], table[255], table[255], table[0]]
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, RwLock},
+};
+
+use vello::{
+    Scene,
+    kurbo::{Affine, Rect},
+    peniko::{BlendMode, Brush, Compose, Fill, Image, Mix},
+};
+
+use crate::utils;
+
+/// A point symbol loaded from an SVG or raster file, drawn in its own pixel space.
+#[derive(Clone)]
+pub enum Icon {
+    Svg(Arc<Scene>, f64, f64),
+    Raster(Image),
+}
+
+impl std::fmt::Debug for Icon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Icon::Svg(_, width, height) => write!(f, "Svg({}x{})", width, height),
+            Icon::Raster(image) => write!(f, "Raster({}x{})", image.width, image.height),
+        }
+    }
+}
+
+/// Decoded icons kept in memory, the least recently used dropped first.
+const ICON_CACHE_SIZE: usize = 64;
+
+/// Cached icons by resolved path, most recently used last.
+type IconCache = Vec<(PathBuf, Icon)>;
+
+fn icon_cache() -> &'static Mutex<IconCache> {
+    static ICON_CACHE: OnceLock<Mutex<IconCache>> = OnceLock::new();
+    ICON_CACHE.get_or_init(Default::default)
+}
+
+fn icon_dir() -> &'static RwLock<PathBuf> {
+    static ICON_DIR: OnceLock<RwLock<PathBuf>> = OnceLock::new();
+    ICON_DIR.get_or_init(|| RwLock::new(PathBuf::from(".")))
+}
+
+/// Sets the directory icon paths are resolved against, the working directory by default.
+pub fn set_icon_dir(dir: impl Into<PathBuf>) {
+    if let Ok(mut icon_dir) = icon_dir().write() {
+        *icon_dir = dir.into();
+    }
+}
+
+/// Resolves `path` inside the icon directory. Icon paths may come from feature props, so
+/// paths resolving outside the directory are rejected, absolute or relative.
+pub fn resolve_icon_path(path: &str) -> Result<PathBuf, String> {
+    let dir = icon_dir()
+        .read()
+        .map_err(|e| format!("Icon directory poisoned: {}", e))?
+        .clone();
+    utils::resolve_in_dir(&dir, path, "icon")
+}
+
+/// Loads an icon from the icon directory, SVGs by extension and anything else through
+/// `image`.
+pub fn load_icon(path: &str) -> Result<Icon, String> {
+    let resolved = resolve_icon_path(path)?;
+    let lock = || {
+        icon_cache()
+            .lock()
+            .map_err(|e| format!("Icon cache poisoned: {}", e))
+    };
+    {
+        let mut cache = lock()?;
+        if let Some(index) = cache.iter().position(|(cached, _)| *cached == resolved) {
+            let entry = cache.remove(index);
+            let icon = entry.1.clone();
+            cache.push(entry);
+            return Ok(icon);
+        }
+    }
+    let icon = if path.to_lowercase().ends_with(".svg") {
+        let content = std::fs::read_to_string(&resolved)
+            .map_err(|e| format!("Read icon from File:{} error: {}", path, e))?;
+        let tree = vello_svg::usvg::Tree::from_str(&content, &Default::default())
+            .map_err(|e| format!("Parse svg from File:{} error: {}", path, e))?;
+        let size = tree.size();
+        Icon::Svg(
+            Arc::new(vello_svg::render_tree(&tree)),
+            size.width() as f64,
+            size.height() as f64,
+        )
+    } else {
+        Icon::Raster(load_image(&resolved)?)
+    };
+    let mut cache = lock()?;
+    cache.retain(|(cached, _)| *cached != resolved);
+    cache.push((resolved, icon.clone()));
+    if cache.len() > ICON_CACHE_SIZE {
+        cache.remove(0);
+    }
+    Ok(icon)
+}
+
+#[cfg(feature = "image")]
+pub fn load_image(path: &Path) -> Result<Image, String> {
+    use vello::peniko::{Blob, ImageFormat};
+    let image = image::open(path)
+        .map_err(|e| format!("Read image from File:{} error: {}", path.display(), e))?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    Ok(Image::new(
+        Blob::new(Arc::new(image.into_raw())),
+        ImageFormat::Rgba8,
+        width,
+        height,
+    ))
+}
+
+#[cfg(not(feature = "image"))]
+pub fn load_image(path: &Path) -> Result<Image, String> {
+    Err(format!(
+        "Read image from File:{} error: raster icons need the image feature",
+        path.display()
+    ))
+}
+
+impl Icon {
+    pub fn size(&self) -> (f64, f64) {
+        match self {
+            Icon::Svg(_, width, height) => (*width, *height),
+            Icon::Raster(image) => (image.width as f64, image.height as f64),
+        }
+    }
+    /// Draws the icon with `transform` mapping its `(0, 0, width, height)` box into the scene,
+    /// recoloured by `tint` while keeping the icon's own alpha.
+    pub fn draw(&self, scene: &mut Scene, transform: Affine, tint: Option<&Brush>) {
+        let (width, height) = self.size();
+        let rect = Rect::new(0f64, 0f64, width, height);
+        if tint.is_some() {
+            scene.push_layer(Mix::Normal, 1f32, transform, &rect);
+        }
+        match self {
+            Icon::Svg(icon_scene, _, _) => scene.append(icon_scene, Some(transform)),
+            Icon::Raster(image) => scene.draw_image(image, transform),
+        }
+        if let Some(tint) = tint {
+            scene.push_layer(
+                BlendMode::new(Mix::Normal, Compose::SrcAtop),
+                1f32,
+                transform,
+                &rect,
+            );
+            scene.fill(Fill::NonZero, transform, tint, None, &rect);
+            scene.pop_layer();
+            scene.pop_layer();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tests run from the crate root, the default icon directory
+
+    #[test]
+    fn icons_outside_the_directory_are_rejected() {
+        assert!(resolve_icon_path("assets/web-map/logo.svg").is_ok());
+        assert!(resolve_icon_path("assets/../src/lib.rs").is_ok());
+        assert!(resolve_icon_path("src/../..").is_err());
+        let absolute = std::env::current_dir().unwrap().join("Cargo.toml");
+        assert!(resolve_icon_path(absolute.to_str().unwrap()).is_ok());
+        assert!(resolve_icon_path("/etc/hostname").is_err());
+    }
+
+    #[test]
+    fn missing_icons_fall_back_to_the_marker() {
+        let renderer = crate::PointRenderer {
+            icon: crate::MagicValue::wrap("assets/missing.svg"),
+            ..Default::default()
+        };
+        assert!(load_icon("assets/missing.svg").is_err());
+        let mut scene = Scene::new();
+        renderer
+            .draw(&mut scene, Affine::IDENTITY, &geo::Point::new(1f64, 1f64))
+            .unwrap();
+        assert!(!scene.encoding().is_empty());
+    }
+}
@@ -1,14 +1,17 @@
 use std::collections::HashMap;
 
-use geo::{Geometry, LineString, MultiLineString};
+use geo::{BoundingRect, Geometry, LineString, MultiLineString, Rect};
 use vello::{
-    kurbo::{Affine, BezPath, PathEl, Point, Stroke},
-    peniko::{Brush, color::palette},
+    Scene,
+    kurbo::{Affine, Arc, BezPath, Cap, Join, Line, PathEl, Point, Stroke, Vec2},
+    peniko::{Brush, Color, Fill, Gradient, color::palette},
 };
 
-use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue, RenderedGeometry};
+use crate::{
+    MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, RenderedGeometry, utils,
+};
 
-use super::GeometryRenderer;
+use super::{BrushSpace, ColorRamp, GeometryRenderer};
 
 pub type LineNodeRenderers = HashMap<NodeKind, Vec<MagicValue<GeometryRenderer>>>;
 
@@ -20,11 +23,95 @@ pub enum NodeKind {
     End,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LineCap {
+    Butt,
+    Square,
+    #[default]
+    Round,
+}
+
+impl From<LineCap> for Cap {
+    fn from(value: LineCap) -> Self {
+        match value {
+            LineCap::Butt => Cap::Butt,
+            LineCap::Square => Cap::Square,
+            LineCap::Round => Cap::Round,
+        }
+    }
+}
+
+impl MagicFetcher for LineCap {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for LineCap {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LineJoin {
+    Bevel,
+    Miter,
+    #[default]
+    Round,
+}
+
+impl From<LineJoin> for Join {
+    fn from(value: LineJoin) -> Self {
+        match value {
+            LineJoin::Bevel => Join::Bevel,
+            LineJoin::Miter => Join::Miter,
+            LineJoin::Round => Join::Round,
+        }
+    }
+}
+
+impl MagicFetcher for LineJoin {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for LineJoin {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Lengths follow `need_scale` on each value: map units by default, pixels when set.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct LineRenderer {
     pub stroke: MagicValue<Stroke>,
     pub brush: MagicValue<Brush>,
     pub node_renderers: MagicValue<LineNodeRenderers>,
+    /// Dash lengths like `[4, 2]` or `"4 2"`, replacing the stroke's dashes unless empty.
+    #[serde(default)]
+    pub dash_array: MagicValue<PropValue>,
+    #[serde(default)]
+    pub dash_offset: MagicValue<PropValue>,
+    /// Replaces the stroke's caps when set.
+    #[serde(default)]
+    pub cap: Option<MagicValue<LineCap>>,
+    /// Replaces the stroke's join when set.
+    #[serde(default)]
+    pub join: Option<MagicValue<LineJoin>>,
+    /// Shifts the line to its left, or to its right when negative.
+    #[serde(default)]
+    pub offset: MagicValue<PropValue>,
+    #[serde(default)]
+    pub brush_space: MagicValue<BrushSpace>,
+    /// Colour ramp from the start of the line at 0 to its end at 1, drawn instead of `brush`
+    /// so the colours follow the line around bends.
+    #[serde(default)]
+    pub progress: Option<MagicValue<ColorRamp>>,
+    /// Pixels added to each side of the stroke.
+    #[serde(skip)]
+    pub(crate) widen: f64,
 }
 
 impl std::default::Default for LineRenderer {
@@ -33,6 +120,14 @@ impl std::default::Default for LineRenderer {
             stroke: Stroke::new(0.1f64).into(),
             brush: Brush::Solid(palette::css::AQUA).into(),
             node_renderers: HashMap::default().into(),
+            dash_array: Default::default(),
+            dash_offset: MagicValue::wrap(0f64),
+            cap: None,
+            join: None,
+            offset: MagicValue::wrap(0f64),
+            brush_space: Default::default(),
+            progress: None,
+            widen: 0f64,
         }
     }
 }
@@ -42,15 +137,64 @@ impl MagicFetcher for LineRenderer {
         self.stroke.fetch()?;
         self.brush.fetch()?;
         self.node_renderers.fetch()?;
+        self.dash_array.fetch()?;
+        self.dash_offset.fetch()?;
+        if let Some(cap) = self.cap.as_mut() {
+            cap.fetch()?;
+        }
+        if let Some(join) = self.join.as_mut() {
+            join.fetch()?;
+        }
+        self.offset.fetch()?;
+        self.brush_space.fetch()?;
+        if let Some(progress) = self.progress.as_mut() {
+            progress.fetch()?;
+        }
         Ok(())
     }
 }
 
 impl MagicConverter for LineRenderer {
-    fn convert(&mut self, props: &HashMap<String, PropValue>) -> Result<(), String> {
-        self.stroke.convert(props)?;
-        self.brush.convert(props)?;
-        self.node_renderers.convert(props)?;
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.stroke.convert(props, context)?;
+        self.brush.convert(props, context)?;
+        self.node_renderers.convert(props, context)?;
+        self.dash_array.convert(props, context)?;
+        self.dash_offset.convert(props, context)?;
+        if let Some(cap) = self.cap.as_mut() {
+            cap.convert(props, context)?;
+        }
+        if let Some(join) = self.join.as_mut() {
+            join.convert(props, context)?;
+        }
+        self.offset.convert(props, context)?;
+        self.brush_space.convert(props, context)?;
+        if let Some(progress) = self.progress.as_mut() {
+            progress.convert(props, context)?;
+        }
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.stroke.prepare(layer)?;
+        self.brush.prepare(layer)?;
+        self.node_renderers.prepare(layer)?;
+        self.dash_array.prepare(layer)?;
+        self.dash_offset.prepare(layer)?;
+        if let Some(cap) = self.cap.as_mut() {
+            cap.prepare(layer)?;
+        }
+        if let Some(join) = self.join.as_mut() {
+            join.prepare(layer)?;
+        }
+        self.offset.prepare(layer)?;
+        self.brush_space.prepare(layer)?;
+        if let Some(progress) = self.progress.as_mut() {
+            progress.prepare(layer)?;
+        }
         Ok(())
     }
 }
@@ -67,10 +211,22 @@ impl MagicFetcher for LineNodeRenderers {
 }
 
 impl MagicConverter for LineNodeRenderers {
-    fn convert(&mut self, props: &HashMap<String, PropValue>) -> Result<(), String> {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        for (_, renderers) in self.iter_mut() {
+            for renderer in renderers {
+                renderer.convert(props, context)?;
+            }
+        }
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
         for (_, renderers) in self.iter_mut() {
             for renderer in renderers {
-                renderer.convert(props)?;
+                renderer.prepare(layer)?;
             }
         }
         Ok(())
@@ -82,14 +238,78 @@ impl LineRenderer {
         &mut self,
         scene: &mut vello::Scene,
         transform: Affine,
+        context: &RenderContext,
+        line: &LineString,
+    ) -> Result<(), String> {
+        self.draw_in_bounds(scene, transform, context, line, line.bounding_rect(), None)
+    }
+    /// Draws like [`LineRenderer::draw`] at the opacity `alpha_at` gives for each key in `keys`,
+    /// one for each vertex, changing linearly in between. Node symbols are drawn opaque.
+    pub fn draw_faded(
+        &mut self,
+        scene: &mut Scene,
+        transform: Affine,
+        context: &RenderContext,
+        line: &LineString,
+        keys: &[f64],
+        alpha_at: impl Fn(f64) -> f64,
+    ) -> Result<(), String> {
+        // repeated vertices are dropped here, as offsetting the line would drop them
+        let mut coords = Vec::new();
+        let mut alphas = Vec::new();
+        for (coord, key) in line.coords().zip(keys.iter()) {
+            if coords.last() != Some(coord) {
+                coords.push(*coord);
+                alphas.push(alpha_at(*key).clamp(0f64, 1f64));
+            }
+        }
+        if alphas.iter().all(|alpha| *alpha >= 1f64) {
+            return self.draw(scene, transform, context, line);
+        }
+        let line = LineString::new(coords);
+        let bounds = line.bounding_rect();
+        self.draw_in_bounds(scene, transform, context, &line, bounds, Some(&alphas))
+    }
+    /// Draws like [`LineRenderer::draw`], placing `Bounds` brushes in `bounds`, faded by
+    /// `alphas` when given.
+    fn draw_in_bounds(
+        &mut self,
+        scene: &mut vello::Scene,
+        transform: Affine,
+        context: &RenderContext,
         line: &LineString,
+        bounds: Option<Rect>,
+        alphas: Option<&[f64]>,
     ) -> Result<(), String> {
-        let path = LineRenderer::to_shape(line);
-        let stroke = self.stroke.as_ref();
-        let brush = self.brush.as_ref();
+        let offset = self.offset.map_length(transform)?;
+        let stroked_line = if offset == 0f64 {
+            line
+        } else {
+            &utils::offset_line(line, offset)
+        };
+        let stroke = &self.map_stroke(transform)?;
+        let brush_transform = self.brush_space.as_ref().brush_transform(transform, bounds);
+        if self.progress.is_some() || alphas.is_some() {
+            self.stroke_segments(
+                scene,
+                transform,
+                stroke,
+                stroked_line,
+                alphas,
+                brush_transform,
+            );
+        } else {
+            let path = LineRenderer::to_shape(stroked_line)?;
+            scene.stroke(
+                stroke,
+                transform,
+                self.brush.as_ref(),
+                brush_transform,
+                &path,
+            );
+        }
         let node_renderers = self.node_renderers.as_mut();
-        scene.stroke(stroke, transform, brush, None, &path);
-        if node_renderers.len() > 0 {
+        if !node_renderers.is_empty() {
             let points = line.points();
             let len = points.len();
             for (index, point) in line.points().enumerate() {
@@ -105,7 +325,13 @@ impl LineRenderer {
                     match kind {
                         NodeKind::All => {
                             for renderer in renderers {
-                                renderer.draw(scene, transform, &mut rendered_geometry, None)?;
+                                renderer.draw(
+                                    scene,
+                                    transform,
+                                    context,
+                                    &mut rendered_geometry,
+                                    None,
+                                )?;
                             }
                         }
                         NodeKind::Mid => {
@@ -114,6 +340,7 @@ impl LineRenderer {
                                     renderer.draw(
                                         scene,
                                         transform,
+                                        context,
                                         &mut rendered_geometry,
                                         None,
                                     )?;
@@ -126,6 +353,7 @@ impl LineRenderer {
                                     renderer.draw(
                                         scene,
                                         transform,
+                                        context,
                                         &mut rendered_geometry,
                                         None,
                                     )?;
@@ -138,6 +366,7 @@ impl LineRenderer {
                                     renderer.draw(
                                         scene,
                                         transform,
+                                        context,
                                         &mut rendered_geometry,
                                         None,
                                     )?;
@@ -150,14 +379,150 @@ impl LineRenderer {
         };
         Ok(())
     }
+    /// Grows the stroke and node symbols by `grow` pixels on each side, false when a node
+    /// symbol can not grow.
+    pub(crate) fn widen(&mut self, grow: f64) -> bool {
+        self.widen += grow;
+        self.node_renderers
+            .as_mut()
+            .values_mut()
+            .flatten()
+            .all(|renderer| renderer.as_mut().widen(grow))
+    }
+    /// The stroke in map units with dashes, caps and join overrides applied.
+    pub fn map_stroke(&self, transform: Affine) -> Result<Stroke, String> {
+        let mut stroke = self.stroke.map_stroke(transform);
+        stroke.width += 2f64 * self.widen / utils::transform_scale(transform);
+        let dash_array = self.dash_array.map_lengths(transform)?;
+        if !dash_array.is_empty() {
+            stroke = stroke.with_dashes(self.dash_offset.map_length(transform)?, dash_array);
+        }
+        if let Some(cap) = self.cap.as_ref() {
+            stroke = stroke.with_caps(Cap::from(*cap.as_ref()));
+        }
+        if let Some(join) = self.join.as_ref() {
+            stroke = stroke.with_join(Join::from(*join.as_ref()));
+        }
+        Ok(stroke)
+    }
+    /// Strokes each segment on its own, coloured by the `progress` ramp at the fraction of the
+    /// line length and faded by `alphas`, one for each vertex, both changing linearly along the
+    /// segment. Segments meet with butt caps and the gaps they leave outside bends are filled
+    /// in the shape of the stroke join, so no part of the line is painted twice.
+    fn stroke_segments(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        stroke: &Stroke,
+        line: &LineString,
+        alphas: Option<&[f64]>,
+        brush_transform: Option<Affine>,
+    ) {
+        let mut vertices: Vec<(Point, f64)> = Vec::new();
+        for (index, coord) in line.coords().enumerate() {
+            let point = Point::new(coord.x, coord.y);
+            if vertices.last().is_none_or(|(last, _)| *last != point) {
+                vertices.push((point, alphas.map_or(1f64, |alphas| alphas[index])));
+            }
+        }
+        if vertices.len() < 2 {
+            return;
+        }
+        let mut distances = vec![0f64];
+        for pair in vertices.windows(2) {
+            distances.push(distances[distances.len() - 1] + pair[0].0.distance(pair[1].0));
+        }
+        let total = distances[distances.len() - 1];
+        let last = vertices.len() - 1;
+        let closed = last > 1 && vertices[0].0 == vertices[last].0;
+        let ramp = self.progress.as_ref().map(|ramp| ramp.as_ref());
+        let color = match self.brush.as_ref() {
+            Brush::Solid(color) => Some(*color),
+            _ => None,
+        };
+        // progress and opacity at each vertex
+        let paint = |index: usize| (distances[index] / total, vertices[index].1);
+        // brushes other than solid colours are faded as a whole
+        let brush_between =
+            |start: Point, end: Point, from: (f64, f64), to: (f64, f64)| match (ramp, color) {
+                (None, None) => (
+                    self.brush
+                        .as_ref()
+                        .clone()
+                        .multiply_alpha(((from.1 + to.1) / 2f64) as f32),
+                    brush_transform,
+                ),
+                _ => {
+                    let stops = segment_stops(ramp, color.unwrap_or(Color::TRANSPARENT), from, to);
+                    let brush = if start == end {
+                        Brush::Solid(stops[0].1)
+                    } else {
+                        Gradient::new_linear(start, end)
+                            .with_stops(stops.as_slice())
+                            .into()
+                    };
+                    (brush, None)
+                }
+            };
+        for index in 0..last {
+            let (from, to) = (paint(index), paint(index + 1));
+            if from.1 <= 0f64 && to.1 <= 0f64 {
+                continue;
+            }
+            let (start, end) = (vertices[index].0, vertices[index + 1].0);
+            let mut segment = stroke
+                .clone()
+                .with_start_cap(if index == 0 && !closed {
+                    stroke.start_cap
+                } else {
+                    Cap::Butt
+                })
+                .with_end_cap(if index + 1 == last && !closed {
+                    stroke.end_cap
+                } else {
+                    Cap::Butt
+                });
+            segment.dash_offset += distances[index];
+            let (brush, brush_transform) = brush_between(start, end, from, to);
+            scene.stroke(
+                &segment,
+                transform,
+                &brush,
+                brush_transform,
+                &Line::new(start, end),
+            );
+        }
+        // joins of dashed lines mostly fall in gaps, so they are left out
+        if !stroke.dash_pattern.is_empty() {
+            return;
+        }
+        let direction = |from: usize, to: usize| (vertices[to].0 - vertices[from].0).normalize();
+        for index in if closed { 0..last } else { 1..last } {
+            let before = if index == 0 { last - 1 } else { index - 1 };
+            let point = vertices[index].0;
+            let wedge = join_wedge(
+                point,
+                direction(before, index),
+                direction(index, index + 1),
+                stroke,
+            );
+            if let Some(wedge) = wedge.filter(|_| vertices[index].1 > 0f64) {
+                let (brush, brush_transform) =
+                    brush_between(point, point, paint(index), paint(index));
+                scene.fill(Fill::NonZero, transform, &brush, brush_transform, &wedge);
+            }
+        }
+    }
     pub fn draw_multi(
         &mut self,
         scene: &mut vello::Scene,
         transform: Affine,
+        context: &RenderContext,
         lines: &MultiLineString,
     ) -> Result<(), String> {
+        let bounds = lines.bounding_rect();
         for line in lines {
-            self.draw(scene, transform, line)?;
+            self.draw_in_bounds(scene, transform, context, line, bounds, None)?;
         }
         Ok(())
     }
@@ -165,22 +530,248 @@ impl LineRenderer {
         &mut self,
         scene: &mut vello::Scene,
         transform: Affine,
+        context: &RenderContext,
         lines: Vec<&LineString>,
     ) -> Result<(), String> {
+        let bounds = lines
+            .iter()
+            .filter_map(|line| line.bounding_rect())
+            .reduce(|a, b| {
+                Rect::new(
+                    (a.min().x.min(b.min().x), a.min().y.min(b.min().y)),
+                    (a.max().x.max(b.max().x), a.max().y.max(b.max().y)),
+                )
+            });
         for line in lines {
-            self.draw(scene, transform, line)?;
+            self.draw_in_bounds(scene, transform, context, line, bounds, None)?;
         }
         Ok(())
     }
-    pub fn to_shape(line: &LineString) -> BezPath {
+    /// Closes the path only for closed linestrings, such as polygon rings and `Rect`/`Triangle`
+    /// outlines, so open lines are not joined back to their start.
+    pub fn to_shape(line: &LineString) -> Result<BezPath, String> {
+        let closed = line.0.len() > 2 && line.is_closed();
         let mut points = line.points();
         let mut path = BezPath::new();
-        let first_point = points.next().unwrap();
+        let Some(first_point) = points.next() else {
+            return Err("Cannot build a path from an empty LineString".to_string());
+        };
         path.push(PathEl::MoveTo(Point::new(first_point.x(), first_point.y())));
-        for point in points {
+        let rest = if closed {
+            line.0.len() - 2
+        } else {
+            line.0.len() - 1
+        };
+        for point in points.take(rest) {
             path.push(PathEl::LineTo(Point::new(point.x(), point.y())));
         }
-        path.push(PathEl::ClosePath);
-        path
+        if closed {
+            path.push(PathEl::ClosePath);
+        }
+        Ok(path)
+    }
+}
+
+/// Gradient stops of a segment with `(progress, alpha)` at its ends, coloured by `ramp` or else
+/// `color`, at the segment ends and where the ramp bends in between.
+fn segment_stops(
+    ramp: Option<&ColorRamp>,
+    color: Color,
+    from: (f64, f64),
+    to: (f64, f64),
+) -> Vec<(f32, Color)> {
+    let color_at = |t: f64| {
+        let progress = from.0 + (to.0 - from.0) * t;
+        let alpha = from.1 + (to.1 - from.1) * t;
+        ramp.map_or(color, |ramp| ramp.color(progress))
+            .multiply_alpha(alpha as f32)
+    };
+    let mut stops = vec![(0f32, color_at(0f64))];
+    if let Some(ramp) = ramp {
+        stops.extend(
+            ramp.stops
+                .iter()
+                .map(|(stop, _)| *stop)
+                .filter(|stop| (stop - from.0) * (stop - to.0) < 0f64)
+                .map(|stop| {
+                    let t = (stop - from.0) / (to.0 - from.0);
+                    (t as f32, color_at(t))
+                }),
+        );
+    }
+    stops.push((1f32, color_at(1f64)));
+    stops
+}
+
+/// The part of a join outside the bend at `point`, between the butt ends of the segments
+/// arriving in direction `before` and leaving in `after`, both unit vectors.
+fn join_wedge(point: Point, before: Vec2, after: Vec2, stroke: &Stroke) -> Option<BezPath> {
+    let half_width = stroke.width / 2f64;
+    let turn = before.cross(after);
+    if half_width <= 0f64 || (turn.abs() < 1e-9 && before.dot(after) > 0f64) {
+        return None;
+    }
+    // the outside is to the right of a left turn
+    let side = if turn > 0f64 { -1f64 } else { 1f64 };
+    let from = Vec2::new(-before.y, before.x) * side;
+    let to = Vec2::new(-after.y, after.x) * side;
+    let mut wedge = BezPath::new();
+    wedge.move_to(point);
+    wedge.line_to(point + from * half_width);
+    match stroke.join {
+        Join::Bevel => {}
+        Join::Miter => {
+            // cosine of half the angle between the segment ends
+            let cos = (from + to).hypot() / 2f64;
+            if cos > 1e-9 && 1f64 / cos <= stroke.miter_limit {
+                wedge.line_to(point + (from + to).normalize() * (half_width / cos));
+            }
+        }
+        Join::Round => {
+            let sweep = from.cross(to).atan2(from.dot(to));
+            let arc = Arc::new(point, (half_width, half_width), from.atan2(), sweep, 0f64);
+            wedge.extend(arc.append_iter(half_width * 1e-3));
+        }
+    }
+    wedge.line_to(point + to * half_width);
+    wedge.close_path();
+    Some(wedge)
+}
+
+#[cfg(test)]
+mod tests {
+    use vello::kurbo::Shape;
+
+    use super::*;
+
+    fn ramp() -> ColorRamp {
+        vec![
+            (0f64, Brush::Solid(palette::css::RED)),
+            (0.5f64, Brush::Solid(palette::css::LIME)),
+            (1f64, Brush::Solid(palette::css::BLUE)),
+        ]
+        .into()
+    }
+
+    /// Paths drawn by fading a line bent at a right angle with `alphas` at its vertices.
+    fn faded_paths(alphas: [f64; 3]) -> u32 {
+        let mut renderer = LineRenderer {
+            stroke: Stroke::new(4f64).into(),
+            ..Default::default()
+        };
+        let context = RenderContext::default();
+        renderer.convert(&HashMap::new(), &context).unwrap();
+        let line = LineString::from(vec![(0f64, 0f64), (100f64, 0f64), (100f64, 100f64)]);
+        let mut scene = Scene::new();
+        renderer
+            .draw_faded(
+                &mut scene,
+                Affine::IDENTITY,
+                &context,
+                &line,
+                &[0f64, 1f64, 2f64],
+                |key| alphas[key as usize],
+            )
+            .unwrap();
+        scene.encoding().n_paths
+    }
+
+    #[test]
+    fn faded_segments_carry_the_alpha_at_their_ends() {
+        let red = palette::css::RED;
+        assert_eq!(
+            segment_stops(None, red, (0f64, 0.25), (0f64, 0.75)),
+            [(0f32, red.with_alpha(0.25)), (1f32, red.with_alpha(0.75))]
+        );
+        // two segments and the join between them, or the line stroked once when opaque
+        assert_eq!(faded_paths([0.5, 0.5, 1f64]), 3);
+        assert_eq!(faded_paths([1f64, 1f64, 1f64]), 1);
+        // nothing is drawn where the line has faded out
+        assert_eq!(faded_paths([0f64, 0f64, 1f64]), 1);
+    }
+
+    #[test]
+    fn progress_segments_bend_with_the_ramp() {
+        let ramp = ramp();
+        let stops = segment_stops(Some(&ramp), Color::BLACK, (0.25, 1f64), (0.75, 0.5));
+        assert_eq!(stops.len(), 3);
+        assert_eq!(stops[0], (0f32, ramp.color(0.25)));
+        assert_eq!(stops[1], (0.5f32, palette::css::LIME.with_alpha(0.75)));
+        assert_eq!(stops[2], (1f32, ramp.color(0.75).multiply_alpha(0.5)));
+        // no stop falls inside a segment between two ramp stops
+        assert_eq!(
+            segment_stops(Some(&ramp), Color::BLACK, (0.1, 1f64), (0.4, 1f64)).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn join_wedges_fill_the_outside_of_bends() {
+        let point = Point::ORIGIN;
+        let (east, north) = (Vec2::new(1f64, 0f64), Vec2::new(0f64, 1f64));
+        let wedge = |join: Join| {
+            let stroke = Stroke::new(2f64).with_join(join);
+            join_wedge(point, east, north, &stroke).unwrap()
+        };
+        assert!((wedge(Join::Bevel).area().abs() - 0.5).abs() < 1e-9);
+        assert!((wedge(Join::Miter).area().abs() - 1f64).abs() < 1e-9);
+        assert!((wedge(Join::Round).area().abs() - std::f64::consts::FRAC_PI_4).abs() < 1e-3);
+        // turning towards +y leaves the gap on the -y side of the vertex
+        assert_eq!(
+            wedge(Join::Miter).bounding_box(),
+            vello::kurbo::Rect::new(0f64, -1f64, 1f64, 0f64)
+        );
+        assert!(join_wedge(point, east, east, &Stroke::new(2f64)).is_none());
+    }
+
+    #[test]
+    fn scaled_dashes_stay_in_pixels() {
+        let dashes = || MagicValue::wrap(vec![PropValue::Float64(4f64), PropValue::Float64(2f64)]);
+        let mut renderer = LineRenderer {
+            stroke: MagicValue::from(Stroke::new(2f64).with_dashes(1f64, [6f64, 2f64]))
+                .with_need_scale(true),
+            ..Default::default()
+        };
+        let transform = Affine::scale(2f64);
+        // the stroke's own dashes follow its scale
+        let stroke = renderer.map_stroke(transform).unwrap();
+        assert_eq!(stroke.width, 1f64);
+        assert_eq!(stroke.dash_offset, 0.5);
+        assert_eq!(stroke.dash_pattern.as_slice(), [3f64, 1f64]);
+        renderer.dash_array = dashes().with_need_scale(true);
+        renderer.dash_offset = MagicValue::wrap(2f64).with_need_scale(true);
+        let stroke = renderer.map_stroke(transform).unwrap();
+        assert_eq!(stroke.dash_offset, 1f64);
+        assert_eq!(stroke.dash_pattern.as_slice(), [2f64, 1f64]);
+        // without need_scale the dashes are map units
+        renderer.dash_array = dashes();
+        renderer.dash_offset = MagicValue::wrap(2f64);
+        let stroke = renderer.map_stroke(transform).unwrap();
+        assert_eq!(stroke.dash_offset, 2f64);
+        assert_eq!(stroke.dash_pattern.as_slice(), [4f64, 2f64]);
+    }
+
+    #[test]
+    fn only_closed_lines_close_their_path() {
+        let open = LineString::from(vec![(0f64, 0f64), (4f64, 0f64), (4f64, 4f64)]);
+        let path = LineRenderer::to_shape(&open).unwrap();
+        assert_eq!(
+            path.elements(),
+            [
+                PathEl::MoveTo(Point::new(0f64, 0f64)),
+                PathEl::LineTo(Point::new(4f64, 0f64)),
+                PathEl::LineTo(Point::new(4f64, 4f64)),
+            ]
+        );
+        let mut ring = open.clone();
+        ring.close();
+        let path = LineRenderer::to_shape(&ring).unwrap();
+        assert_eq!(path.elements().len(), 4);
+        assert_eq!(path.elements()[3], PathEl::ClosePath);
+        // a single point is no ring, even though its ends coincide
+        let point = LineString::from(vec![(1f64, 1f64)]);
+        let path = LineRenderer::to_shape(&point).unwrap();
+        assert_eq!(path.elements(), [PathEl::MoveTo(Point::new(1f64, 1f64))]);
+        assert!(LineRenderer::to_shape(&LineString::new(vec![])).is_err());
     }
 }
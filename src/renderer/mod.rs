@@ -5,12 +5,46 @@ pub mod line_renderer;
 pub use line_renderer::*;
 pub mod area_renderer;
 pub use area_renderer::*;
+pub mod text_renderer;
+pub use text_renderer::*;
+pub mod icon;
+pub use icon::*;
+pub mod decoration_renderer;
+pub use decoration_renderer::*;
+pub mod brush_space;
+pub use brush_space::*;
+pub mod fill_pattern;
+pub use fill_pattern::*;
+pub mod color_ramp;
+pub use color_ramp::*;
+pub mod heatmap_renderer;
+pub use heatmap_renderer::*;
+pub mod cluster_renderer;
+pub use cluster_renderer::*;
+pub mod graph_renderer;
+pub use graph_renderer::*;
+pub mod proportional_renderer;
+pub use proportional_renderer::*;
+pub mod chart_renderer;
+pub use chart_renderer::*;
+pub mod trail_renderer;
+pub use trail_renderer::*;
+pub mod effect;
+pub use effect::*;
+// the filter moved to the crate root, re-exported so `renderer::RenderedGeometryFilter` resolves
+pub use crate::RenderedGeometryFilter;
+
+use std::collections::HashSet;
 
 use geo::Rect;
 use vello::{Scene, kurbo::Affine};
 
-use crate::{MagicConverter, MagicFetcher, MagicValue, rendered_geometry::RenderedGeometry};
+use crate::{
+    LayerGroup, MagicConverter, MagicFetcher, MagicValue, PlacementCandidate, RenderContext,
+    Timeline, ZoomRange, rendered_geometry::RenderedGeometry,
+};
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub enum GeometryRenderer {
     #[default]
@@ -27,6 +61,65 @@ pub enum GeometryRenderer {
         #[serde(default)] MagicValue<RenderedGeometryFilter>,
         #[serde(default)] MagicValue<AreaRenderer>,
     ),
+    Text(
+        #[serde(default)] MagicValue<RenderedGeometryFilter>,
+        #[serde(default)] MagicValue<TextRenderer>,
+    ),
+    /// Repeats symbols along lines.
+    Decoration(
+        #[serde(default)] MagicValue<RenderedGeometryFilter>,
+        #[serde(default)] MagicValue<DecorationRenderer>,
+    ),
+    /// Kernel density of the points, drawn as one image.
+    Heatmap(
+        #[serde(default)] MagicValue<RenderedGeometryFilter>,
+        #[serde(default)] MagicValue<HeatmapRenderer>,
+    ),
+    /// Groups nearby points into count-sized cluster symbols.
+    Cluster(
+        #[serde(default)] MagicValue<RenderedGeometryFilter>,
+        #[serde(default)] Box<MagicValue<ClusterRenderer>>,
+    ),
+    /// Nodes and the edges between them, looked up by id props.
+    Graph(
+        #[serde(default)] MagicValue<RenderedGeometryFilter>,
+        #[serde(default)] Box<MagicValue<GraphRenderer>>,
+    ),
+    /// Tracks of moving objects up to the render time.
+    Trail(
+        #[serde(default)] MagicValue<RenderedGeometryFilter>,
+        #[serde(default)] Box<MagicValue<TrailRenderer>>,
+    ),
+    /// Symbols sized by the square root of a value.
+    Proportional(
+        #[serde(default)] MagicValue<RenderedGeometryFilter>,
+        #[serde(default)] Box<MagicValue<ProportionalRenderer>>,
+    ),
+    /// Pie or bar charts of several props.
+    Chart(
+        #[serde(default)] MagicValue<RenderedGeometryFilter>,
+        #[serde(default)] MagicValue<ChartRenderer>,
+    ),
+    /// Draws the inner renderer only while the render context is in range.
+    Visible(
+        #[serde(default)] MagicValue<ZoomRange>,
+        Box<MagicValue<GeometryRenderer>>,
+    ),
+    /// Draws the inner renderer with the animation time moved onto the timeline, for keyframes.
+    Animated(
+        #[serde(default)] MagicValue<Timeline>,
+        Box<MagicValue<GeometryRenderer>>,
+    ),
+    /// Draws a shadow, glow or halo of the inner renderer beneath it. The effect is converted
+    /// without props.
+    Effect(
+        #[serde(default)] MagicValue<Effect>,
+        Box<MagicValue<GeometryRenderer>>,
+    ),
+    /// Composites its rasters and renderers together, in place among the other renderers. Labels
+    /// inside collide with the rest, also when the group is wrapped in `Visible`, `Animated` or
+    /// `Effect`.
+    Group(#[serde(default)] Box<LayerGroup>),
 }
 
 impl MagicFetcher for GeometryRenderer {
@@ -45,6 +138,51 @@ impl MagicFetcher for GeometryRenderer {
                 filter.fetch()?;
                 renderer.fetch()?;
             }
+            GeometryRenderer::Text(filter, renderer) => {
+                filter.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Decoration(filter, renderer) => {
+                filter.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Heatmap(filter, renderer) => {
+                filter.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Cluster(filter, renderer) => {
+                filter.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Graph(filter, renderer) => {
+                filter.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Trail(filter, renderer) => {
+                filter.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Proportional(filter, renderer) => {
+                filter.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Chart(filter, renderer) => {
+                filter.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Visible(range, renderer) => {
+                range.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Animated(timeline, renderer) => {
+                timeline.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Effect(effect, renderer) => {
+                effect.fetch()?;
+                renderer.fetch()?;
+            }
+            GeometryRenderer::Group(group) => group.fetch()?,
         };
         Ok(())
     }
@@ -54,42 +192,347 @@ impl MagicConverter for GeometryRenderer {
     fn convert(
         &mut self,
         props: &std::collections::HashMap<String, crate::PropValue>,
+        context: &RenderContext,
     ) -> Result<(), String> {
         match self {
             GeometryRenderer::None => {}
             GeometryRenderer::Point(filter, renderer) => {
-                filter.convert(props)?;
-                renderer.convert(props)?;
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
             }
             GeometryRenderer::Line(filter, renderer) => {
-                filter.convert(props)?;
-                renderer.convert(props)?;
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
             }
             GeometryRenderer::Area(filter, renderer) => {
-                filter.convert(props)?;
-                renderer.convert(props)?;
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Text(filter, renderer) => {
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Decoration(filter, renderer) => {
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Heatmap(filter, renderer) => {
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Cluster(filter, renderer) => {
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Graph(filter, renderer) => {
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Trail(filter, renderer) => {
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Proportional(filter, renderer) => {
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Chart(filter, renderer) => {
+                filter.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Visible(range, renderer) => {
+                range.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Animated(timeline, renderer) => {
+                timeline.convert(props, context)?;
+                renderer.convert(props, &timeline.as_ref().context(context))?;
+            }
+            GeometryRenderer::Effect(effect, renderer) => {
+                effect.convert(props, context)?;
+                renderer.convert(props, context)?;
+            }
+            GeometryRenderer::Group(group) => group.convert(props, context)?,
+        };
+        Ok(())
+    }
+    fn prepare(
+        &mut self,
+        layer: &[&std::collections::HashMap<String, crate::PropValue>],
+    ) -> Result<(), String> {
+        match self {
+            GeometryRenderer::None => {}
+            GeometryRenderer::Point(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Line(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Area(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Text(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Decoration(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Heatmap(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Cluster(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Graph(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Trail(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Proportional(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Chart(_, renderer) => renderer.prepare(layer)?,
+            GeometryRenderer::Visible(range, renderer) => {
+                range.prepare(layer)?;
+                renderer.prepare(layer)?;
+            }
+            GeometryRenderer::Animated(timeline, renderer) => {
+                timeline.prepare(layer)?;
+                renderer.prepare(layer)?;
             }
+            GeometryRenderer::Effect(effect, renderer) => {
+                effect.prepare(layer)?;
+                renderer.prepare(layer)?;
+            }
+            // members prepare their own layers
+            GeometryRenderer::Group(_) => {}
         };
         Ok(())
     }
 }
 
 impl GeometryRenderer {
+    /// Prepares layer-derived values, such as class breaks, from the geometries the filter keeps.
+    pub fn prepare_layer(
+        &mut self,
+        context: &RenderContext,
+        rendered_geometrys: &[RenderedGeometry],
+    ) -> Result<(), String> {
+        let filter = match self {
+            GeometryRenderer::None => return Ok(()),
+            GeometryRenderer::Point(filter, _)
+            | GeometryRenderer::Line(filter, _)
+            | GeometryRenderer::Area(filter, _)
+            | GeometryRenderer::Text(filter, _)
+            | GeometryRenderer::Decoration(filter, _)
+            | GeometryRenderer::Heatmap(filter, _)
+            | GeometryRenderer::Cluster(filter, _)
+            | GeometryRenderer::Graph(filter, _)
+            | GeometryRenderer::Trail(filter, _)
+            | GeometryRenderer::Proportional(filter, _)
+            | GeometryRenderer::Chart(filter, _) => filter.as_ref().clone(),
+            GeometryRenderer::Visible(_, renderer) => {
+                return (**renderer)
+                    .as_mut()
+                    .prepare_layer(context, rendered_geometrys);
+            }
+            GeometryRenderer::Animated(timeline, renderer) => {
+                return (**renderer)
+                    .as_mut()
+                    .prepare_layer(&timeline.as_ref().context(context), rendered_geometrys);
+            }
+            GeometryRenderer::Effect(_, renderer) => {
+                return (**renderer)
+                    .as_mut()
+                    .prepare_layer(context, rendered_geometrys);
+            }
+            GeometryRenderer::Group(group) => {
+                for renderer in group.renderers.iter_mut() {
+                    let renderer = renderer.as_mut();
+                    if renderer.is_visible(context) {
+                        renderer.prepare_layer(context, rendered_geometrys)?;
+                    }
+                }
+                return Ok(());
+            }
+        };
+        let layer: Vec<_> = rendered_geometrys
+            .iter()
+            .filter(|rendered_geometry| rendered_geometry.fit_filter(&filter, context))
+            .map(|rendered_geometry| rendered_geometry.props())
+            .collect();
+        self.prepare(&layer)
+    }
+    /// A copy drawing the same with its strokes and fills grown by a further `grow` pixels on
+    /// each side, `None` when it draws text, images or charts that can not grow.
+    pub fn widened(&self, grow: f64) -> Option<GeometryRenderer> {
+        let mut widened = self.clone();
+        widened.widen(grow).then_some(widened)
+    }
+    pub(crate) fn widen(&mut self, grow: f64) -> bool {
+        match self {
+            GeometryRenderer::None => true,
+            GeometryRenderer::Point(_, renderer) => renderer.as_mut().widen(grow),
+            GeometryRenderer::Line(_, renderer) => renderer.as_mut().widen(grow),
+            GeometryRenderer::Area(_, renderer) => renderer.as_mut().widen(grow),
+            GeometryRenderer::Decoration(_, renderer) => renderer.as_mut().widen(grow),
+            GeometryRenderer::Visible(_, renderer)
+            | GeometryRenderer::Animated(_, renderer)
+            | GeometryRenderer::Effect(_, renderer) => (**renderer).as_mut().widen(grow),
+            GeometryRenderer::Group(group) => {
+                group.rasters.is_empty()
+                    && group
+                        .renderers
+                        .iter_mut()
+                        .all(|renderer| renderer.as_mut().widen(grow))
+            }
+            _ => false,
+        }
+    }
+    /// Whether this is a group, or wraps one, whose members are placed one by one.
+    pub(crate) fn contains_group(&self) -> bool {
+        match self {
+            GeometryRenderer::Group(_) => true,
+            GeometryRenderer::Visible(_, renderer)
+            | GeometryRenderer::Animated(_, renderer)
+            | GeometryRenderer::Effect(_, renderer) => (**renderer).as_ref().contains_group(),
+            _ => false,
+        }
+    }
+    /// Renderers placement numbers this one as, one for each member of a group within.
+    pub(crate) fn placement_slots(&self) -> usize {
+        match self {
+            GeometryRenderer::Group(group) => group
+                .renderers
+                .iter()
+                .map(|renderer| renderer.as_ref().placement_slots())
+                .sum(),
+            GeometryRenderer::Visible(_, renderer)
+            | GeometryRenderer::Animated(_, renderer)
+            | GeometryRenderer::Effect(_, renderer) => (**renderer).as_ref().placement_slots(),
+            _ => 1,
+        }
+    }
+    pub fn is_visible(&self, context: &RenderContext) -> bool {
+        match self {
+            GeometryRenderer::Visible(range, renderer) => {
+                range.as_ref().contains(context) && (**renderer).as_ref().is_visible(context)
+            }
+            GeometryRenderer::Animated(timeline, renderer) => (**renderer)
+                .as_ref()
+                .is_visible(&timeline.as_ref().context(context)),
+            GeometryRenderer::Effect(_, renderer) => (**renderer).as_ref().is_visible(context),
+            _ => true,
+        }
+    }
     pub fn draw(
         &mut self,
         scene: &mut Scene,
         transform: Affine,
-        rendered_geometrys: &mut Vec<RenderedGeometry>,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
+        render_rect: Option<Rect>,
+    ) -> Result<(), String> {
+        self.draw_placed(
+            scene,
+            transform,
+            context,
+            rendered_geometrys,
+            render_rect,
+            None,
+        )
+    }
+    /// Collects pixel-space boxes of labels and symbols, `None` for renderers that never collide.
+    pub fn placement_candidates(
+        &mut self,
+        renderer_index: usize,
+        transform: Affine,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
+        render_rect: Option<Rect>,
+    ) -> Result<Option<Vec<PlacementCandidate>>, String> {
+        let mut candidates = Vec::new();
+        match self {
+            GeometryRenderer::Point(filter, renderer) => {
+                let filter = filter.as_ref();
+                for (geom_index, rendered_geometry) in rendered_geometrys.iter_mut().enumerate() {
+                    if rendered_geometry.fit_filter(filter, context) {
+                        let props = rendered_geometry.props();
+                        renderer.convert(props, context)?;
+                        let renderer = renderer.as_ref();
+                        let true_render_rect = if renderer.must_show.inner_try_into()? {
+                            render_rect
+                        } else {
+                            None
+                        };
+                        if let Some(point) = rendered_geometry.center_point(true_render_rect) {
+                            candidates.push(PlacementCandidate {
+                                renderer_index,
+                                geom_index,
+                                bbox: renderer.bbox(transform, point)?,
+                                priority: renderer.priority.inner_try_into()?,
+                                allow_overlap: renderer.allow_overlap.inner_try_into()?,
+                            });
+                        }
+                    }
+                }
+            }
+            GeometryRenderer::Text(filter, renderer) => {
+                let filter = filter.as_ref();
+                for (geom_index, rendered_geometry) in rendered_geometrys.iter_mut().enumerate() {
+                    if rendered_geometry.fit_filter(filter, context) {
+                        let props = rendered_geometry.props();
+                        renderer.convert(props, context)?;
+                        let renderer = renderer.as_ref();
+                        if let Some(label) =
+                            renderer.layout_geometry(transform, rendered_geometry, render_rect)?
+                        {
+                            candidates.push(PlacementCandidate {
+                                renderer_index,
+                                geom_index,
+                                bbox: label.bbox,
+                                priority: renderer.priority.inner_try_into()?,
+                                allow_overlap: renderer.allow_overlap.inner_try_into()?,
+                            });
+                        }
+                    }
+                }
+            }
+            GeometryRenderer::Visible(range, renderer) => {
+                if !range.as_ref().contains(context) {
+                    return Ok(None);
+                }
+                return (**renderer).as_mut().placement_candidates(
+                    renderer_index,
+                    transform,
+                    context,
+                    rendered_geometrys,
+                    render_rect,
+                );
+            }
+            GeometryRenderer::Animated(timeline, renderer) => {
+                return (**renderer).as_mut().placement_candidates(
+                    renderer_index,
+                    transform,
+                    &timeline.as_ref().context(context),
+                    rendered_geometrys,
+                    render_rect,
+                );
+            }
+            GeometryRenderer::Effect(_, renderer) => {
+                return (**renderer).as_mut().placement_candidates(
+                    renderer_index,
+                    transform,
+                    context,
+                    rendered_geometrys,
+                    render_rect,
+                );
+            }
+            _ => return Ok(None),
+        }
+        Ok(Some(candidates))
+    }
+    /// Draws like [`GeometryRenderer::draw`], but when `placed` is given, labels and symbols are
+    /// only drawn for the geometry indices it contains.
+    pub fn draw_placed(
+        &mut self,
+        scene: &mut Scene,
+        transform: Affine,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
         render_rect: Option<Rect>,
+        placed: Option<&HashSet<usize>>,
     ) -> Result<(), String> {
+        let is_placed = |index: usize| placed.is_none_or(|placed| placed.contains(&index));
         match self {
             GeometryRenderer::None => {}
             GeometryRenderer::Point(filter, renderer) => {
                 let filter = filter.as_ref();
-                for rendered_geometry in rendered_geometrys {
-                    if rendered_geometry.fit_filter(filter) {
+                for (geom_index, rendered_geometry) in rendered_geometrys.iter_mut().enumerate() {
+                    if is_placed(geom_index) && rendered_geometry.fit_filter(filter, context) {
                         let props = rendered_geometry.props();
-                        renderer.convert(props)?;
+                        renderer.convert(props, context)?;
                         let renderer = renderer.as_ref();
                         let true_render_rect = if renderer.must_show.inner_try_into()? {
                             render_rect
@@ -105,14 +548,12 @@ impl GeometryRenderer {
             GeometryRenderer::Line(filter, renderer) => {
                 let filter = filter.as_ref();
                 for rendered_geometry in rendered_geometrys {
-                    if rendered_geometry.fit_filter(filter) {
+                    if rendered_geometry.fit_filter(filter, context) {
                         let props = rendered_geometry.props();
-                        renderer.convert(props)?;
+                        renderer.convert(props, context)?;
                         let renderer = renderer.as_mut();
-                        let lines = rendered_geometry.lines();
-                        if lines.is_some() {
-                            let lines = lines.unwrap();
-                            renderer.draw_multi(scene, transform, lines)?;
+                        if let Some(lines) = rendered_geometry.lines() {
+                            renderer.draw_multi(scene, transform, context, lines)?;
                         }
                     }
                 }
@@ -120,39 +561,197 @@ impl GeometryRenderer {
             GeometryRenderer::Area(filter, renderer) => {
                 let filter = filter.as_ref();
                 for rendered_geometry in rendered_geometrys {
-                    if rendered_geometry.fit_filter(filter) {
+                    if rendered_geometry.fit_filter(filter, context) {
                         let props = rendered_geometry.props();
-                        renderer.convert(props)?;
+                        renderer.convert(props, context)?;
                         let renderer = renderer.as_mut();
                         if let Some(areas) = rendered_geometry.areas() {
-                            renderer.draw_multi(scene, transform, areas)?;
+                            renderer.draw_multi(scene, transform, context, areas, render_rect)?;
                         }
                     }
                 }
             }
+            GeometryRenderer::Text(filter, renderer) => {
+                let filter = filter.as_ref();
+                for (geom_index, rendered_geometry) in rendered_geometrys.iter_mut().enumerate() {
+                    if is_placed(geom_index) && rendered_geometry.fit_filter(filter, context) {
+                        let props = rendered_geometry.props();
+                        renderer.convert(props, context)?;
+                        let renderer = renderer.as_ref();
+                        if let Some(label) =
+                            renderer.layout_geometry(transform, rendered_geometry, render_rect)?
+                        {
+                            renderer.draw_label(scene, &label)?;
+                        }
+                    }
+                }
+            }
+            GeometryRenderer::Decoration(filter, renderer) => {
+                let filter = filter.as_ref();
+                for rendered_geometry in rendered_geometrys {
+                    if rendered_geometry.fit_filter(filter, context) {
+                        let props = rendered_geometry.props().clone();
+                        renderer.convert(&props, context)?;
+                        let renderer = renderer.as_mut();
+                        if let Some(lines) = rendered_geometry.lines() {
+                            renderer.draw_multi(scene, transform, context, &props, lines)?;
+                        }
+                    }
+                }
+            }
+            GeometryRenderer::Heatmap(filter, renderer) => {
+                let filter = filter.as_ref();
+                let mut points = Vec::new();
+                for rendered_geometry in rendered_geometrys {
+                    if rendered_geometry.fit_filter(filter, context) {
+                        let props = rendered_geometry.props();
+                        renderer.convert(props, context)?;
+                        points.extend(renderer.as_ref().weighted_points(rendered_geometry)?);
+                    }
+                }
+                renderer
+                    .as_ref()
+                    .draw(scene, transform, &points, render_rect)?;
+            }
+            GeometryRenderer::Cluster(filter, renderer) => {
+                let filter = filter.as_ref();
+                let members: Vec<usize> = rendered_geometrys
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, rendered_geometry)| rendered_geometry.fit_filter(filter, context))
+                    .map(|(geom_index, _)| geom_index)
+                    .collect();
+                let renderer = &mut **renderer;
+                renderer.convert(&Default::default(), context)?;
+                renderer.as_mut().draw(
+                    scene,
+                    transform,
+                    context,
+                    rendered_geometrys,
+                    &members,
+                    render_rect,
+                )?;
+            }
+            GeometryRenderer::Graph(filter, renderer) => {
+                let filter = filter.as_ref();
+                let members: Vec<usize> = rendered_geometrys
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, rendered_geometry)| rendered_geometry.fit_filter(filter, context))
+                    .map(|(geom_index, _)| geom_index)
+                    .collect();
+                let renderer = &mut **renderer;
+                renderer.convert(&Default::default(), context)?;
+                renderer
+                    .as_mut()
+                    .draw(scene, transform, context, rendered_geometrys, &members)?;
+            }
+            GeometryRenderer::Trail(filter, renderer) => {
+                let filter = filter.as_ref();
+                let members: Vec<usize> = rendered_geometrys
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, rendered_geometry)| rendered_geometry.fit_filter(filter, context))
+                    .map(|(geom_index, _)| geom_index)
+                    .collect();
+                let renderer = &mut **renderer;
+                renderer.convert(&Default::default(), context)?;
+                renderer
+                    .as_mut()
+                    .draw(scene, transform, context, rendered_geometrys, &members)?;
+            }
+            GeometryRenderer::Proportional(filter, renderer) => {
+                let filter = filter.as_ref();
+                let members: Vec<usize> = rendered_geometrys
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, rendered_geometry)| rendered_geometry.fit_filter(filter, context))
+                    .map(|(geom_index, _)| geom_index)
+                    .collect();
+                (**renderer).as_mut().draw(
+                    scene,
+                    transform,
+                    context,
+                    rendered_geometrys,
+                    &members,
+                )?;
+            }
+            GeometryRenderer::Chart(filter, renderer) => {
+                let filter = filter.as_ref();
+                for rendered_geometry in rendered_geometrys {
+                    if rendered_geometry.fit_filter(filter, context) {
+                        let props = rendered_geometry.props().clone();
+                        renderer.convert(&props, context)?;
+                        if let Some(point) = rendered_geometry.center_point(None) {
+                            renderer.as_ref().draw(scene, transform, &props, point)?;
+                        }
+                    }
+                }
+            }
+            GeometryRenderer::Visible(range, renderer) => {
+                if range.as_ref().contains(context) {
+                    (**renderer).as_mut().draw_placed(
+                        scene,
+                        transform,
+                        context,
+                        rendered_geometrys,
+                        render_rect,
+                        placed,
+                    )?;
+                }
+            }
+            GeometryRenderer::Animated(timeline, renderer) => {
+                (**renderer).as_mut().draw_placed(
+                    scene,
+                    transform,
+                    &timeline.as_ref().context(context),
+                    rendered_geometrys,
+                    render_rect,
+                    placed,
+                )?;
+            }
+            GeometryRenderer::Effect(effect, renderer) => {
+                effect.convert(&Default::default(), context)?;
+                effect.as_ref().draw_with(
+                    scene,
+                    transform,
+                    (**renderer).as_mut(),
+                    |scene, transform, renderer| {
+                        renderer.draw_placed(
+                            scene,
+                            transform,
+                            context,
+                            rendered_geometrys,
+                            render_rect,
+                            placed,
+                        )
+                    },
+                )?;
+            }
+            GeometryRenderer::Group(group) => {
+                group.draw_with(
+                    scene,
+                    transform,
+                    context,
+                    rendered_geometrys,
+                    |scene, renderers, rendered_geometrys| {
+                        for renderer in renderers.iter_mut() {
+                            let renderer = renderer.as_mut();
+                            if renderer.is_visible(context) {
+                                renderer.draw(
+                                    scene,
+                                    transform,
+                                    context,
+                                    rendered_geometrys,
+                                    render_rect,
+                                )?;
+                            }
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
         };
         Ok(())
     }
 }
-
-#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
-pub enum RenderedGeometryFilter {
-    #[default]
-    None,
-    Layer(String),
-}
-
-impl MagicFetcher for RenderedGeometryFilter {
-    fn fetch(&mut self) -> Result<(), String> {
-        Ok(())
-    }
-}
-
-impl MagicConverter for RenderedGeometryFilter {
-    fn convert(
-        &mut self,
-        _: &std::collections::HashMap<String, crate::PropValue>,
-    ) -> Result<(), String> {
-        Ok(())
-    }
-}
@@ -1,21 +1,229 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, TAU},
+};
 
 use geo::Point;
 use vello::{
-    kurbo::{Affine, Circle},
+    kurbo::{Affine, BezPath, Circle, Point as KurboPoint, Rect, Shape, Stroke, Vec2},
     peniko::{Brush, color::palette},
 };
 
-use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue};
+use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, utils};
+
+use super::{Effect, Icon, load_icon};
+
+/// Which point of an icon sits on the geometry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum IconAnchor {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    /// Fractions of the icon width and height from its top left corner.
+    Fraction(f64, f64),
+}
+
+impl IconAnchor {
+    pub fn fraction(&self) -> (f64, f64) {
+        match self {
+            IconAnchor::Center => (0.5, 0.5),
+            IconAnchor::Top => (0.5, 0.0),
+            IconAnchor::Bottom => (0.5, 1.0),
+            IconAnchor::Left => (0.0, 0.5),
+            IconAnchor::Right => (1.0, 0.5),
+            IconAnchor::TopLeft => (0.0, 0.0),
+            IconAnchor::TopRight => (1.0, 0.0),
+            IconAnchor::BottomLeft => (0.0, 1.0),
+            IconAnchor::BottomRight => (1.0, 1.0),
+            IconAnchor::Fraction(x, y) => (*x, *y),
+        }
+    }
+}
+
+impl MagicFetcher for IconAnchor {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for IconAnchor {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum MarkerShape {
+    #[default]
+    Circle,
+    Square,
+    Triangle,
+    Diamond,
+    Cross,
+    /// Star with the given number of points.
+    Star(u32),
+    /// Regular polygon with the given number of sides.
+    Polygon(u32),
+    /// Arrowhead, e.g. for line decorations rotated to the line bearing.
+    Arrow,
+}
+
+impl MagicFetcher for MarkerShape {
+    fn fetch(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MagicConverter for MarkerShape {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl MarkerShape {
+    /// Outline of the shape around `center`, pointing up and within `radius` of it along each
+    /// axis. Triangles, diamonds, stars and polygons have their outer vertices at `radius`.
+    pub fn to_shape(&self, center: KurboPoint, radius: f64) -> BezPath {
+        let polygon = |vertices: Vec<(f64, f64)>| {
+            let mut path = BezPath::new();
+            for (index, (x, y)) in vertices.into_iter().enumerate() {
+                let vertex = center + Vec2::new(x, y);
+                if index == 0 {
+                    path.move_to(vertex);
+                } else {
+                    path.line_to(vertex);
+                }
+            }
+            path.close_path();
+            path
+        };
+        let regular = |sides: u32, inner: Option<f64>| {
+            let steps = if inner.is_some() { sides * 2 } else { sides };
+            let vertices = (0..steps)
+                .map(|index| {
+                    let angle = FRAC_PI_2 + TAU * index as f64 / steps as f64;
+                    let length = match inner {
+                        Some(inner) if index % 2 == 1 => radius * inner,
+                        _ => radius,
+                    };
+                    (length * angle.cos(), length * angle.sin())
+                })
+                .collect();
+            polygon(vertices)
+        };
+        match self {
+            MarkerShape::Circle => Circle::new(center, radius).to_path(radius * 1e-3),
+            MarkerShape::Square => {
+                let half = radius * FRAC_1_SQRT_2;
+                polygon(vec![
+                    (-half, -half),
+                    (half, -half),
+                    (half, half),
+                    (-half, half),
+                ])
+            }
+            MarkerShape::Triangle => regular(3, None),
+            MarkerShape::Diamond => regular(4, None),
+            MarkerShape::Cross => {
+                let arm = radius / 3f64;
+                polygon(vec![
+                    (-arm, radius),
+                    (arm, radius),
+                    (arm, arm),
+                    (radius, arm),
+                    (radius, -arm),
+                    (arm, -arm),
+                    (arm, -radius),
+                    (-arm, -radius),
+                    (-arm, -arm),
+                    (-radius, -arm),
+                    (-radius, arm),
+                    (-arm, arm),
+                ])
+            }
+            MarkerShape::Star(points) => regular((*points).max(2), Some(0.4)),
+            MarkerShape::Polygon(sides) => regular((*sides).max(3), None),
+            MarkerShape::Arrow => polygon(vec![
+                (0f64, radius),
+                (radius * 0.8, -radius),
+                (0f64, -radius * 0.4),
+                (-radius * 0.8, -radius),
+            ]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Outline {
+    pub stroke: MagicValue<Stroke>,
+    pub brush: MagicValue<Brush>,
+}
+
+impl std::default::Default for Outline {
+    fn default() -> Self {
+        Outline {
+            stroke: Stroke::new(0.02f64).into(),
+            brush: Brush::Solid(palette::css::BLACK).into(),
+        }
+    }
+}
+
+impl MagicFetcher for Outline {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.stroke.fetch()?;
+        self.brush.fetch()?;
+        Ok(())
+    }
+}
+
+impl MagicConverter for Outline {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.stroke.convert(props, context)?;
+        self.brush.convert(props, context)?;
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.stroke.prepare(layer)?;
+        self.brush.prepare(layer)?;
+        Ok(())
+    }
+}
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct PointRenderer {
-    #[serde(default)]
+    /// Circle radius, or half the longer side of an icon, in pixels when `need_scale` is set.
     pub radius: MagicValue<PropValue>,
-    #[serde(default)]
     pub brush: MagicValue<Brush>,
-    #[serde(default)]
+    pub shape: MagicValue<MarkerShape>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outline: Option<Outline>,
+    /// Path of an SVG or raster icon in the icon directory (see `set_icon_dir`), drawn instead
+    /// of the marker when set and loadable.
+    pub icon: MagicValue<PropValue>,
+    /// Clockwise rotation of the icon or shape on screen, in degrees.
+    pub rotation: MagicValue<PropValue>,
+    pub anchor: MagicValue<IconAnchor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tint: Option<MagicValue<Brush>>,
     pub must_show: MagicValue<PropValue>,
+    /// Higher priorities claim space first when symbols collide.
+    pub priority: MagicValue<PropValue>,
+    pub allow_overlap: MagicValue<PropValue>,
+    /// Pixels the marker stroke grows by, or the reach of the copies drawn around an icon.
+    #[serde(skip)]
+    pub(crate) widen: f64,
 }
 
 impl std::default::Default for PointRenderer {
@@ -23,7 +231,16 @@ impl std::default::Default for PointRenderer {
         PointRenderer {
             radius: MagicValue::wrap(0.1f64),
             brush: Brush::Solid(palette::css::LIGHT_YELLOW).into(),
+            shape: MarkerShape::Circle.into(),
+            outline: None,
+            icon: MagicValue::wrap(""),
+            rotation: MagicValue::wrap(0f64),
+            anchor: IconAnchor::Center.into(),
+            tint: None,
             must_show: MagicValue::wrap(false),
+            priority: MagicValue::wrap(0f64),
+            allow_overlap: MagicValue::wrap(true),
+            widen: 0f64,
         }
     }
 }
@@ -32,34 +249,254 @@ impl MagicFetcher for PointRenderer {
     fn fetch(&mut self) -> Result<(), String> {
         self.radius.fetch()?;
         self.brush.fetch()?;
+        self.shape.fetch()?;
+        if let Some(outline) = self.outline.as_mut() {
+            outline.fetch()?;
+        }
+        self.icon.fetch()?;
+        self.rotation.fetch()?;
+        self.anchor.fetch()?;
+        if let Some(tint) = self.tint.as_mut() {
+            tint.fetch()?;
+        }
+        self.must_show.fetch()?;
+        self.priority.fetch()?;
+        self.allow_overlap.fetch()?;
         Ok(())
     }
 }
 
 impl MagicConverter for PointRenderer {
-    fn convert(&mut self, props: &HashMap<String, PropValue>) -> Result<(), String> {
-        self.brush.convert(props)?;
-        self.radius.convert(props)?;
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.brush.convert(props, context)?;
+        self.radius.convert(props, context)?;
+        self.shape.convert(props, context)?;
+        if let Some(outline) = self.outline.as_mut() {
+            outline.convert(props, context)?;
+        }
+        self.icon.convert(props, context)?;
+        self.rotation.convert(props, context)?;
+        self.anchor.convert(props, context)?;
+        if let Some(tint) = self.tint.as_mut() {
+            tint.convert(props, context)?;
+        }
+        self.must_show.convert(props, context)?;
+        self.priority.convert(props, context)?;
+        self.allow_overlap.convert(props, context)?;
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.brush.prepare(layer)?;
+        self.radius.prepare(layer)?;
+        self.shape.prepare(layer)?;
+        if let Some(outline) = self.outline.as_mut() {
+            outline.prepare(layer)?;
+        }
+        self.icon.prepare(layer)?;
+        self.rotation.prepare(layer)?;
+        self.anchor.prepare(layer)?;
+        if let Some(tint) = self.tint.as_mut() {
+            tint.prepare(layer)?;
+        }
+        self.must_show.prepare(layer)?;
+        self.priority.prepare(layer)?;
+        self.allow_overlap.prepare(layer)?;
         Ok(())
     }
 }
 
 impl PointRenderer {
+    fn icon_path(&self) -> Option<String> {
+        match self.icon.as_ref() {
+            PropValue::None => None,
+            icon => Some(icon.to_string()).filter(|path| !path.is_empty()),
+        }
+    }
+    /// The icon to draw, `None` falling back to the marker shape when it is unset or fails
+    /// to load, so one bad icon never aborts the render.
+    fn icon(&self) -> Option<Icon> {
+        let path = self.icon_path()?;
+        load_icon(&path)
+            .inspect_err(|e| log::warn!("Draw marker instead of icon: {}", e))
+            .ok()
+    }
+    /// Pixel-space transform placing an icon of `icon_size` on `point`, upright on screen.
+    fn icon_transform(
+        &self,
+        transform: Affine,
+        point: &Point,
+        icon_size: (f64, f64),
+    ) -> Result<Affine, String> {
+        let radius = self.radius.pixel_length(transform)?;
+        let rotation: f64 = self.rotation.inner_try_into()?;
+        let (width, height) = icon_size;
+        let scale = 2f64 * radius / width.max(height);
+        let (anchor_x, anchor_y) = self.anchor.as_ref().fraction();
+        let position = transform * KurboPoint::new(point.x(), point.y());
+        Ok(Affine::translate(position.to_vec2())
+            * Affine::rotate(rotation.to_radians())
+            * Affine::scale(scale)
+            * Affine::translate((-anchor_x * width, -anchor_y * height)))
+    }
+    /// Pixel-space box of the symbol, used for collision placement.
+    pub fn bbox(&self, transform: Affine, point: &Point) -> Result<Rect, String> {
+        if let Some(icon) = self.icon() {
+            let (width, height) = icon.size();
+            let icon_transform = self.icon_transform(transform, point, (width, height))?;
+            return Ok(icon_transform.transform_rect_bbox(Rect::new(0f64, 0f64, width, height)));
+        }
+        let (shape_transform, path) = self.shape_path(transform, point)?;
+        Ok(shape_transform.transform_rect_bbox(path.bounding_box()))
+    }
+    /// Map-space marker outline with the transform drawing it rotated on screen.
+    fn shape_path(&self, transform: Affine, point: &Point) -> Result<(Affine, BezPath), String> {
+        let radius = self.radius.map_length(transform)?;
+        let rotation: f64 = self.rotation.inner_try_into()?;
+        let center = KurboPoint::new(point.x(), point.y());
+        let path = self.shape.as_ref().to_shape(center, radius);
+        let shape_transform =
+            Affine::rotate_about(rotation.to_radians(), transform * center) * transform;
+        Ok((shape_transform, path))
+    }
+    /// Grows the symbol by `grow` pixels on each side. Icons can not be stroked wider, so they
+    /// are drawn again around themselves instead.
+    pub(crate) fn widen(&mut self, grow: f64) -> bool {
+        self.widen += grow;
+        true
+    }
     pub fn draw(
         &self,
         scene: &mut vello::Scene,
         transform: Affine,
         point: &Point,
     ) -> Result<(), String> {
+        if let Some(icon) = self.icon() {
+            let icon_transform = self.icon_transform(transform, point, icon.size())?;
+            // icons have no strokes to widen, so copies spread over the grown area grow them instead
+            for offset in Effect::copy_offsets(self.widen) {
+                icon.draw(
+                    scene,
+                    Affine::translate(offset) * icon_transform,
+                    self.tint.as_ref().map(|tint| tint.as_ref()),
+                );
+            }
+            return Ok(());
+        }
         let brush = self.brush.as_ref();
-        let radius = self.radius.inner_try_into()?;
+        let (shape_transform, path) = self.shape_path(transform, point)?;
         scene.fill(
             vello::peniko::Fill::NonZero,
-            transform,
+            shape_transform,
             brush,
             None,
-            &Circle::new((point.x(), point.y()), radius),
+            &path,
         );
+        let widen = 2f64 * self.widen / utils::transform_scale(transform);
+        match self.outline.as_ref() {
+            Some(outline) => {
+                let mut stroke = outline.stroke.map_stroke(transform);
+                stroke.width += widen;
+                scene.stroke(
+                    &stroke,
+                    shape_transform,
+                    outline.brush.as_ref(),
+                    None,
+                    &path,
+                );
+            }
+            None if widen > 0f64 => {
+                scene.stroke(&Stroke::new(widen), shape_transform, brush, None, &path);
+            }
+            None => {}
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use vello::kurbo::PathEl;
+
+    use super::*;
+
+    /// Vertices of a closed polygon path, relative to its center.
+    fn vertices(shape: MarkerShape) -> Vec<Vec2> {
+        let path = shape.to_shape(KurboPoint::new(10f64, 20f64), 2f64);
+        assert_eq!(path.elements().last(), Some(&PathEl::ClosePath));
+        path.elements()
+            .iter()
+            .filter_map(|element| match element {
+                PathEl::MoveTo(point) | PathEl::LineTo(point) => {
+                    Some(*point - KurboPoint::new(10f64, 20f64))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!((a - b).hypot() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn markers_point_up_within_their_radius() {
+        let circle = MarkerShape::Circle.to_shape(KurboPoint::new(10f64, 20f64), 2f64);
+        let bounds = circle.bounding_box();
+        assert!((bounds.width() - 4f64).abs() < 1e-2 && (bounds.height() - 4f64).abs() < 1e-2);
+        assert!((bounds.center() - KurboPoint::new(10f64, 20f64)).hypot() < 1e-9);
+        for shape in [
+            MarkerShape::Square,
+            MarkerShape::Triangle,
+            MarkerShape::Diamond,
+            MarkerShape::Cross,
+            MarkerShape::Star(5),
+            MarkerShape::Polygon(6),
+            MarkerShape::Arrow,
+        ] {
+            let vertices = vertices(shape);
+            assert!(
+                vertices
+                    .iter()
+                    .all(|vertex| vertex.x.abs().max(vertex.y.abs()) <= 2f64 + 1e-9),
+                "{:?} leaves the square of its radius",
+                shape
+            );
+        }
+        // regular shapes have every outer vertex on the radius
+        for shape in [
+            MarkerShape::Triangle,
+            MarkerShape::Diamond,
+            MarkerShape::Polygon(6),
+        ] {
+            assert!(
+                vertices(shape)
+                    .iter()
+                    .all(|vertex| (vertex.hypot() - 2f64).abs() < 1e-9)
+            );
+        }
+        // map space has y up, so tips sit above the center
+        assert_near(vertices(MarkerShape::Triangle)[0], Vec2::new(0f64, 2f64));
+        assert_near(vertices(MarkerShape::Arrow)[0], Vec2::new(0f64, 2f64));
+        assert_near(
+            vertices(MarkerShape::Square)[0],
+            Vec2::new(-2f64, -2f64) * FRAC_1_SQRT_2,
+        );
+    }
+
+    #[test]
+    fn stars_and_polygons_count_their_vertices() {
+        let star = vertices(MarkerShape::Star(5));
+        assert_eq!(star.len(), 10);
+        assert!((star[1].hypot() - 0.8).abs() < 1e-9);
+        assert_eq!(vertices(MarkerShape::Polygon(6)).len(), 6);
+        assert_eq!(vertices(MarkerShape::Diamond).len(), 4);
+        assert_eq!(vertices(MarkerShape::Cross).len(), 12);
+        // too few points or sides are raised to the fewest that make a shape
+        assert_eq!(vertices(MarkerShape::Star(0)).len(), 4);
+        assert_eq!(vertices(MarkerShape::Polygon(1)).len(), 3);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use vello::{Scene, kurbo::Affine};
+
+use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, RenderedGeometry};
+
+use super::PointRenderer;
+
+/// Draws `symbol` at center points with an area proportional to `value`, so its radius is
+/// `radius` times the square root of `value / reference`. Larger symbols are drawn first, and
+/// features with a zero value get none.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ProportionalRenderer {
+    /// Usually taken from a numeric prop, negative values are sized by their magnitude.
+    pub value: MagicValue<PropValue>,
+    /// Value drawn with exactly `radius`.
+    pub reference: MagicValue<PropValue>,
+    /// In pixels when `need_scale` is set, map units otherwise.
+    pub radius: MagicValue<PropValue>,
+    /// Smallest radius drawn, in the same unit as `radius`.
+    pub min_radius: MagicValue<PropValue>,
+    pub symbol: MagicValue<PointRenderer>,
+}
+
+impl std::default::Default for ProportionalRenderer {
+    fn default() -> Self {
+        ProportionalRenderer {
+            value: MagicValue::wrap(1f64),
+            reference: MagicValue::wrap(1f64),
+            radius: MagicValue::wrap(10f64).with_need_scale(true),
+            min_radius: MagicValue::wrap(1f64).with_need_scale(true),
+            symbol: PointRenderer::default().into(),
+        }
+    }
+}
+
+impl MagicFetcher for ProportionalRenderer {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.value.fetch()?;
+        self.reference.fetch()?;
+        self.radius.fetch()?;
+        self.min_radius.fetch()?;
+        self.symbol.fetch()?;
+        Ok(())
+    }
+}
+
+impl MagicConverter for ProportionalRenderer {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.value.convert(props, context)?;
+        self.reference.convert(props, context)?;
+        self.radius.convert(props, context)?;
+        self.min_radius.convert(props, context)?;
+        self.symbol.convert(props, context)?;
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.value.prepare(layer)?;
+        self.reference.prepare(layer)?;
+        self.radius.prepare(layer)?;
+        self.min_radius.prepare(layer)?;
+        self.symbol.prepare(layer)?;
+        Ok(())
+    }
+}
+
+impl ProportionalRenderer {
+    /// Symbol radius in map units for the converted `value`, at least `min_radius`. `None`
+    /// for zero or non-finite values and a zero `reference`, which draw no symbol.
+    pub fn map_radius(&self, transform: Affine) -> Result<Option<f64>, String> {
+        let value: f64 = self.value.inner_try_into()?;
+        let reference: f64 = self.reference.inner_try_into()?;
+        if value == 0f64 || reference == 0f64 || !value.is_finite() {
+            return Ok(None);
+        }
+        let radius = self.radius.map_length(transform)? * (value / reference).abs().sqrt();
+        Ok(Some(radius.max(self.min_radius.map_length(transform)?)))
+    }
+    pub fn draw(
+        &mut self,
+        scene: &mut Scene,
+        transform: Affine,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
+        geom_indices: &[usize],
+    ) -> Result<(), String> {
+        let mut symbols = Vec::new();
+        for geom_index in geom_indices {
+            let rendered_geometry = &mut rendered_geometrys[*geom_index];
+            self.convert(rendered_geometry.props(), context)?;
+            let Some(radius) = self.map_radius(transform)? else {
+                continue;
+            };
+            if let Some(point) = rendered_geometry.center_point(None) {
+                let mut symbol = self.symbol.as_ref().clone();
+                symbol.radius = MagicValue::wrap(radius);
+                symbols.push((radius, *point, symbol));
+            }
+        }
+        symbols.sort_by(|(a, _, _), (b, _, _)| b.total_cmp(a));
+        for (_, point, symbol) in symbols {
+            symbol.draw(scene, transform, &point)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn radius(value: f64, reference: f64) -> Option<f64> {
+        ProportionalRenderer {
+            value: MagicValue::wrap(value),
+            reference: MagicValue::wrap(reference),
+            radius: MagicValue::wrap(10f64),
+            min_radius: MagicValue::wrap(1f64),
+            ..Default::default()
+        }
+        .map_radius(Affine::IDENTITY)
+        .unwrap()
+    }
+
+    #[test]
+    fn areas_follow_the_value() {
+        assert_eq!(radius(1f64, 1f64), Some(10f64));
+        assert_eq!(radius(4f64, 1f64), Some(20f64));
+        assert_eq!(radius(25f64, 100f64), Some(5f64));
+        // four times the value, four times the area
+        let (small, large) = (radius(3f64, 2f64).unwrap(), radius(12f64, 2f64).unwrap());
+        assert!((large * large / (small * small) - 4f64).abs() < 1e-9);
+        assert_eq!(radius(-4f64, 1f64), Some(20f64));
+    }
+
+    #[test]
+    fn tiny_values_are_clamped_and_zero_ones_skipped() {
+        assert_eq!(radius(1e-6, 1f64), Some(1f64));
+        assert_eq!(radius(0f64, 1f64), None);
+        assert_eq!(radius(1f64, 0f64), None);
+        assert_eq!(radius(f64::NAN, 1f64), None);
+    }
+}
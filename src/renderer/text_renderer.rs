@@ -0,0 +1,555 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock, RwLock},
+};
+
+use geo::{LineString, MultiLineString, Point, Rect as GeoRect};
+use skrifa::{
+    FontRef, MetadataProvider,
+    instance::{LocationRef, Size},
+};
+use vello::{
+    Glyph,
+    kurbo::{self, Affine, Join, Rect, Stroke},
+    peniko::{Blob, Brush, Fill, Font, color::palette},
+};
+
+use crate::{
+    GeometryKind, MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext,
+    RenderedGeometry, utils,
+};
+
+/// Labels are laid out in pixel space, so `size` and `halo_width` are pixels.
+///
+/// Glyphs are placed one per character from the font's cmap and advance widths, with no
+/// kerning, ligatures or complex-script shaping, so only simple left-to-right scripts like
+/// Latin, Greek or Cyrillic lay out correctly.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TextRenderer {
+    /// Template like `"{name} ({ref})"`, filled from props.
+    pub text: MagicValue<PropValue>,
+    /// Path of a TrueType/OpenType font file.
+    pub font: MagicValue<PropValue>,
+    pub size: MagicValue<PropValue>,
+    pub brush: MagicValue<Brush>,
+    pub halo_brush: MagicValue<Brush>,
+    pub halo_width: MagicValue<PropValue>,
+    /// Clockwise rotation of labels at points, in degrees.
+    pub rotation: MagicValue<PropValue>,
+    pub must_show: MagicValue<PropValue>,
+    /// Higher priorities claim space first when labels collide.
+    pub priority: MagicValue<PropValue>,
+    pub allow_overlap: MagicValue<PropValue>,
+    #[serde(skip)]
+    label: String,
+}
+
+impl std::default::Default for TextRenderer {
+    fn default() -> Self {
+        TextRenderer {
+            text: MagicValue::wrap("{name}"),
+            font: MagicValue::wrap(""),
+            size: MagicValue::wrap(12f64),
+            brush: Brush::Solid(palette::css::BLACK).into(),
+            halo_brush: Brush::Solid(palette::css::WHITE).into(),
+            halo_width: MagicValue::wrap(1f64),
+            rotation: MagicValue::wrap(0f64),
+            must_show: MagicValue::wrap(false),
+            priority: MagicValue::wrap(0f64),
+            allow_overlap: MagicValue::wrap(false),
+            label: String::new(),
+        }
+    }
+}
+
+impl MagicFetcher for TextRenderer {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.text.fetch()?;
+        self.font.fetch()?;
+        self.size.fetch()?;
+        self.brush.fetch()?;
+        self.halo_brush.fetch()?;
+        self.halo_width.fetch()?;
+        self.rotation.fetch()?;
+        self.must_show.fetch()?;
+        self.priority.fetch()?;
+        self.allow_overlap.fetch()?;
+        Ok(())
+    }
+}
+
+impl MagicConverter for TextRenderer {
+    fn convert(
+        &mut self,
+        props: &HashMap<String, PropValue>,
+        context: &RenderContext,
+    ) -> Result<(), String> {
+        self.text.convert(props, context)?;
+        self.font.convert(props, context)?;
+        self.size.convert(props, context)?;
+        self.brush.convert(props, context)?;
+        self.halo_brush.convert(props, context)?;
+        self.halo_width.convert(props, context)?;
+        self.rotation.convert(props, context)?;
+        self.must_show.convert(props, context)?;
+        self.priority.convert(props, context)?;
+        self.allow_overlap.convert(props, context)?;
+        self.label = utils::format_template(&self.text.to_string(), props);
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.text.prepare(layer)?;
+        self.font.prepare(layer)?;
+        self.size.prepare(layer)?;
+        self.brush.prepare(layer)?;
+        self.halo_brush.prepare(layer)?;
+        self.halo_width.prepare(layer)?;
+        self.rotation.prepare(layer)?;
+        self.must_show.prepare(layer)?;
+        self.priority.prepare(layer)?;
+        self.allow_overlap.prepare(layer)?;
+        Ok(())
+    }
+}
+
+/// Glyphs of one label with their pixel-space transforms.
+#[derive(Debug, Clone)]
+pub struct TextLabel {
+    pub font: Font,
+    pub size: f32,
+    pub glyphs: Vec<(Affine, Glyph)>,
+    pub bbox: Rect,
+}
+
+/// Parsed fonts kept in memory, the least recently used dropped first.
+const FONT_CACHE_SIZE: usize = 16;
+
+/// Cached fonts by resolved path, most recently used last.
+type FontCache = Vec<(PathBuf, Font)>;
+
+fn font_cache() -> &'static Mutex<FontCache> {
+    static FONT_CACHE: OnceLock<Mutex<FontCache>> = OnceLock::new();
+    FONT_CACHE.get_or_init(Default::default)
+}
+
+fn font_dir() -> &'static RwLock<PathBuf> {
+    static FONT_DIR: OnceLock<RwLock<PathBuf>> = OnceLock::new();
+    FONT_DIR.get_or_init(|| RwLock::new(PathBuf::from(".")))
+}
+
+/// Sets the directory font paths are resolved against, the working directory by default.
+pub fn set_font_dir(dir: impl Into<PathBuf>) {
+    if let Ok(mut font_dir) = font_dir().write() {
+        *font_dir = dir.into();
+    }
+}
+
+/// Resolves `path` inside the font directory, rejecting paths leading out of it like icons.
+pub fn resolve_font_path(path: &str) -> Result<PathBuf, String> {
+    let dir = font_dir()
+        .read()
+        .map_err(|e| format!("Font directory poisoned: {}", e))?
+        .clone();
+    utils::resolve_in_dir(&dir, path, "font")
+}
+
+/// Loads a font from the font directory.
+pub fn load_font(path: &str) -> Result<Font, String> {
+    if path.is_empty() {
+        return Err("No font set for TextRenderer".to_string());
+    }
+    let resolved = resolve_font_path(path)?;
+    let lock = || {
+        font_cache()
+            .lock()
+            .map_err(|e| format!("Font cache poisoned: {}", e))
+    };
+    {
+        let mut cache = lock()?;
+        if let Some(index) = cache.iter().position(|(cached, _)| *cached == resolved) {
+            let entry = cache.remove(index);
+            let font = entry.1.clone();
+            cache.push(entry);
+            return Ok(font);
+        }
+    }
+    // read without the lock, so labels in other fonts render meanwhile
+    let data = std::fs::read(&resolved)
+        .map_err(|e| format!("Read font from File:{} error: {}", path, e))?;
+    FontRef::from_index(&data, 0)
+        .map_err(|e| format!("Parse font from File:{} error: {}", path, e))?;
+    let font = Font::new(Blob::new(Arc::new(data)), 0);
+    let mut cache = lock()?;
+    cache.retain(|(cached, _)| *cached != resolved);
+    cache.push((resolved, font.clone()));
+    if cache.len() > FONT_CACHE_SIZE {
+        cache.remove(0);
+    }
+    Ok(font)
+}
+
+struct ShapedLine {
+    glyphs: Vec<(Glyph, f32)>,
+    width: f32,
+}
+
+struct ShapedText {
+    lines: Vec<ShapedLine>,
+    ascent: f32,
+    descent: f32,
+    line_height: f32,
+}
+
+impl TextRenderer {
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+    fn shape(&self, font: &Font, size: f32, text: &str) -> Result<ShapedText, String> {
+        let font_ref = FontRef::from_index(font.data.as_ref(), font.index)
+            .map_err(|e| format!("Parse font error: {}", e))?;
+        let charmap = font_ref.charmap();
+        let metrics = font_ref.metrics(Size::new(size), LocationRef::default());
+        let glyph_metrics = font_ref.glyph_metrics(Size::new(size), LocationRef::default());
+        let lines = text
+            .lines()
+            .map(|line| {
+                let mut width = 0f32;
+                let glyphs = line
+                    .chars()
+                    .map(|ch| {
+                        let id = charmap.map(ch).unwrap_or_default();
+                        let advance = glyph_metrics.advance_width(id).unwrap_or_default();
+                        let glyph = Glyph {
+                            id: id.to_u32(),
+                            x: width,
+                            y: 0f32,
+                        };
+                        width += advance;
+                        (glyph, advance)
+                    })
+                    .collect();
+                ShapedLine { glyphs, width }
+            })
+            .collect();
+        Ok(ShapedText {
+            lines,
+            ascent: metrics.ascent,
+            descent: metrics.descent,
+            line_height: metrics.ascent - metrics.descent + metrics.leading,
+        })
+    }
+    /// Lays the label out along lines for lineal geometries, otherwise at the center point.
+    pub fn layout_geometry(
+        &self,
+        transform: Affine,
+        rendered_geometry: &mut RenderedGeometry,
+        render_rect: Option<GeoRect>,
+    ) -> Result<Option<TextLabel>, String> {
+        if GeometryKind::Lineal.fit(rendered_geometry.geom()) {
+            match rendered_geometry.lines() {
+                Some(lines) => self.layout_along_multi(transform, lines),
+                None => Ok(None),
+            }
+        } else {
+            let true_render_rect = if self.must_show.inner_try_into()? {
+                render_rect
+            } else {
+                None
+            };
+            match rendered_geometry.center_point(true_render_rect) {
+                Some(point) => self.layout_at(transform, point),
+                None => Ok(None),
+            }
+        }
+    }
+    /// Lays the label out centred on `point`.
+    pub fn layout_at(&self, transform: Affine, point: &Point) -> Result<Option<TextLabel>, String> {
+        if self.label.is_empty() {
+            return Ok(None);
+        }
+        let font = load_font(&self.font.to_string())?;
+        let size: f32 = self.size.inner_try_into()?;
+        let shaped = self.shape(&font, size, &self.label)?;
+        let anchor = transform * kurbo::Point::new(point.x(), point.y());
+        let rotation: f64 = self.rotation.inner_try_into()?;
+        let rotate = Affine::rotate_about(rotation.to_radians(), anchor);
+        let height = shaped.line_height * shaped.lines.len() as f32;
+        let top = anchor.y as f32 - height / 2f32;
+        let mut glyphs = Vec::new();
+        let mut bbox: Option<Rect> = None;
+        for (index, line) in shaped.lines.iter().enumerate() {
+            let left = anchor.x as f32 - line.width / 2f32;
+            let baseline = top + shaped.line_height * index as f32 + shaped.ascent;
+            let line_box = Rect::new(
+                left as f64,
+                (baseline - shaped.ascent) as f64,
+                (left + line.width) as f64,
+                (baseline - shaped.descent) as f64,
+            );
+            let line_box = rotate.transform_rect_bbox(line_box);
+            bbox = Some(bbox.map_or(line_box, |b| b.union(line_box)));
+            let transform = rotate * Affine::translate((left as f64, baseline as f64));
+            glyphs.extend(line.glyphs.iter().map(|(glyph, _)| (transform, *glyph)));
+        }
+        Ok(bbox.map(|bbox| TextLabel {
+            font,
+            size,
+            glyphs,
+            bbox,
+        }))
+    }
+    /// Lays the label out along the longest line, or returns `None` if no line is long enough.
+    pub fn layout_along_multi(
+        &self,
+        transform: Affine,
+        lines: &MultiLineString,
+    ) -> Result<Option<TextLabel>, String> {
+        let longest = lines
+            .iter()
+            .map(|line| (utils::pixel_path(transform, line), line))
+            .max_by(|(a, _), (b, _)| utils::path_length(a).total_cmp(&utils::path_length(b)));
+        match longest {
+            Some((_, line)) => self.layout_along(transform, line),
+            None => Ok(None),
+        }
+    }
+    /// Lays the label out along `line`, each glyph rotated to the local direction.
+    pub fn layout_along(
+        &self,
+        transform: Affine,
+        line: &LineString,
+    ) -> Result<Option<TextLabel>, String> {
+        let text = self.label.replace('\n', " ");
+        if text.is_empty() {
+            return Ok(None);
+        }
+        let mut path = utils::pixel_path(transform, line);
+        let length = utils::path_length(&path);
+        let font = load_font(&self.font.to_string())?;
+        let size: f32 = self.size.inner_try_into()?;
+        let shaped = self.shape(&font, size, &text)?;
+        let Some(shaped_line) = shaped.lines.first() else {
+            return Ok(None);
+        };
+        let width = shaped_line.width as f64;
+        if width > length {
+            return Ok(None);
+        }
+        // keep labels upright by reading left to right
+        if let (Some(first), Some(last)) = (path.first(), path.last())
+            && last.x < first.x
+        {
+            path.reverse();
+        }
+        let start = (length - width) / 2f64;
+        let baseline_shift = ((shaped.ascent + shaped.descent) / 2f32) as f64;
+        let mut glyphs = Vec::new();
+        let mut bbox: Option<Rect> = None;
+        for (glyph, advance) in shaped_line.glyphs.iter() {
+            let half_advance = *advance as f64 / 2f64;
+            let distance = start + glyph.x as f64 + half_advance;
+            let Some((position, angle)) = utils::point_along_path(&path, distance) else {
+                continue;
+            };
+            let glyph_transform = Affine::translate(position.to_vec2())
+                * Affine::rotate(angle)
+                * Affine::translate((-half_advance, baseline_shift));
+            let glyph_box = glyph_transform.transform_rect_bbox(Rect::new(
+                0f64,
+                -shaped.ascent as f64,
+                *advance as f64,
+                -shaped.descent as f64,
+            ));
+            bbox = Some(bbox.map_or(glyph_box, |b| b.union(glyph_box)));
+            glyphs.push((glyph_transform, Glyph { x: 0f32, ..*glyph }));
+        }
+        Ok(bbox.map(|bbox| TextLabel {
+            font,
+            size,
+            glyphs,
+            bbox,
+        }))
+    }
+    /// Draws every halo before any fill, so halos never cut into neighbouring glyphs.
+    pub fn draw_label(&self, scene: &mut vello::Scene, label: &TextLabel) -> Result<(), String> {
+        let halo_width: f64 = self.halo_width.inner_try_into()?;
+        // glyphs sharing a transform, like a line of a point label, are drawn as one run
+        let mut runs: Vec<(Affine, Vec<Glyph>)> = Vec::new();
+        for (transform, glyph) in label.glyphs.iter() {
+            match runs.last_mut() {
+                Some((run_transform, glyphs)) if run_transform == transform => glyphs.push(*glyph),
+                _ => runs.push((*transform, vec![*glyph])),
+            }
+        }
+        if halo_width > 0f64 {
+            let halo_stroke = Stroke::new(halo_width * 2f64).with_join(Join::Round);
+            for (transform, glyphs) in runs.iter() {
+                scene
+                    .draw_glyphs(&label.font)
+                    .font_size(label.size)
+                    .transform(*transform)
+                    .brush(self.halo_brush.as_ref())
+                    .draw(&halo_stroke, glyphs.iter().copied());
+            }
+        }
+        for (transform, glyphs) in runs.iter() {
+            scene
+                .draw_glyphs(&label.font)
+                .font_size(label.size)
+                .transform(*transform)
+                .brush(self.brush.as_ref())
+                .draw(Fill::NonZero, glyphs.iter().copied());
+        }
+        Ok(())
+    }
+    pub fn draw(
+        &self,
+        scene: &mut vello::Scene,
+        transform: Affine,
+        point: &Point,
+    ) -> Result<(), String> {
+        if let Some(label) = self.layout_at(transform, point)? {
+            self.draw_label(scene, &label)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FONT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/test/Tuffy.ttf");
+
+    fn point_label(text: &str) -> (TextRenderer, TextLabel) {
+        let mut renderer = TextRenderer {
+            text: MagicValue::wrap(text),
+            font: MagicValue::wrap(TEST_FONT),
+            halo_width: MagicValue::wrap(2f64),
+            ..Default::default()
+        };
+        renderer
+            .convert(&HashMap::new(), &RenderContext::default())
+            .unwrap();
+        let label = renderer
+            .layout_at(Affine::IDENTITY, &Point::new(32f64, 32f64))
+            .unwrap()
+            .unwrap();
+        (renderer, label)
+    }
+
+    fn advance(ch: char, size: f32) -> f32 {
+        let data = std::fs::read(TEST_FONT).unwrap();
+        let font = FontRef::new(&data).unwrap();
+        let id = font.charmap().map(ch).unwrap();
+        font.glyph_metrics(Size::new(size), LocationRef::default())
+            .advance_width(id)
+            .unwrap()
+    }
+
+    #[test]
+    fn point_labels_are_as_wide_as_their_advances() {
+        let (_, narrow) = point_label("iii");
+        let (_, wide) = point_label("WWW");
+        let narrow_width = 3f32 * advance('i', 12f32);
+        let wide_width = 3f32 * advance('W', 12f32);
+        assert!(wide_width > narrow_width * 2f32);
+        assert!((narrow.bbox.width() - narrow_width as f64).abs() < 1e-3);
+        assert!((wide.bbox.width() - wide_width as f64).abs() < 1e-3);
+        // centred on the anchor
+        assert!((wide.bbox.center().x - 32f64).abs() < 1e-3);
+        let xs: Vec<f32> = wide.glyphs.iter().map(|(_, glyph)| glyph.x).collect();
+        assert_eq!(xs, [0f32, advance('W', 12f32), 2f32 * advance('W', 12f32)]);
+    }
+
+    #[test]
+    fn multi_line_labels_stack_lines() {
+        let (_, single) = point_label("Main");
+        let (_, double) = point_label("Main\nStreet");
+        assert!((double.bbox.height() - 2f64 * single.bbox.height()).abs() < 1e-3);
+        let street: f32 = "Street".chars().map(|ch| advance(ch, 12f32)).sum();
+        assert!((double.bbox.width() - street as f64).abs() < 1e-3);
+    }
+
+    #[test]
+    fn labels_longer_than_the_line_are_dropped() {
+        let (renderer, _) = point_label("Main Street");
+        let width: f32 = "Main Street".chars().map(|ch| advance(ch, 12f32)).sum();
+        let short = LineString::from(vec![(0f64, 0f64), (width as f64 - 1f64, 0f64)]);
+        assert!(
+            renderer
+                .layout_along(Affine::IDENTITY, &short)
+                .unwrap()
+                .is_none()
+        );
+        let long = LineString::from(vec![(0f64, 0f64), (width as f64 + 1f64, 0f64)]);
+        let label = renderer
+            .layout_along(Affine::IDENTITY, &long)
+            .unwrap()
+            .unwrap();
+        assert_eq!(label.glyphs.len(), "Main Street".len());
+        assert!((label.bbox.width() - width as f64).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fills_are_drawn_over_all_halos() {
+        let (renderer, label) = point_label("AAA");
+        let mut scene = vello::Scene::new();
+        renderer.draw_label(&mut scene, &label).unwrap();
+        let runs = &scene.encoding().resources.glyph_runs;
+        assert_eq!(runs.len(), 2);
+        assert!(
+            matches!(&runs[0].style, vello::peniko::Style::Stroke(stroke) if stroke.width == 4f64)
+        );
+        assert!(matches!(
+            runs[1].style,
+            vello::peniko::Style::Fill(Fill::NonZero)
+        ));
+        assert!(runs.iter().all(|run| run.glyphs.len() == 3));
+    }
+
+    #[test]
+    fn halos_along_lines_come_first() {
+        let (renderer, _) = point_label("AAA");
+        let line = LineString::from(vec![(0f64, 0f64), (100f64, 40f64), (200f64, 0f64)]);
+        let label = renderer
+            .layout_along(Affine::IDENTITY, &line)
+            .unwrap()
+            .unwrap();
+        let mut scene = vello::Scene::new();
+        renderer.draw_label(&mut scene, &label).unwrap();
+        let is_halo: Vec<bool> = scene
+            .encoding()
+            .resources
+            .glyph_runs
+            .iter()
+            .map(|run| matches!(run.style, vello::peniko::Style::Stroke(_)))
+            .collect();
+        assert_eq!(is_halo, [true, true, true, false, false, false]);
+    }
+
+    #[test]
+    fn no_halo_without_width() {
+        let (mut renderer, label) = point_label("AA");
+        renderer.halo_width = MagicValue::wrap(0f64);
+        let mut scene = vello::Scene::new();
+        renderer.draw_label(&mut scene, &label).unwrap();
+        let runs = &scene.encoding().resources.glyph_runs;
+        assert_eq!(runs.len(), 1);
+        assert!(matches!(
+            runs[0].style,
+            vello::peniko::Style::Fill(Fill::NonZero)
+        ));
+    }
+
+    #[test]
+    fn fonts_outside_the_directory_are_rejected() {
+        assert!(load_font("assets/test/Tuffy.ttf").is_ok());
+        assert!(load_font(TEST_FONT).is_ok());
+        let err = load_font("/etc/hostname").err().unwrap();
+        assert!(err.contains("outside the font directory"), "{}", err);
+        assert!(load_font("..").is_err());
+    }
+}
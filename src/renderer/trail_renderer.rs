@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashMap};
+
+use geo::{Coord, LineString, Point};
+use vello::{Scene, kurbo::Affine};
+
+use crate::{MagicConverter, MagicFetcher, MagicValue, PropValue, RenderContext, RenderedGeometry};
+
+use super::{LineRenderer, PointRenderer};
+
+/// Draws tracks of moving objects up to the render time. Fixes are geometries sharing an
+/// `id_prop`, or a feature id, ordered by their time, joined with `line` and ended by `head`.
+/// The trail and head use the props of the latest fix. Objects stay at their last fix after
+/// it unless `max_age` is set.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TrailRenderer {
+    pub id_prop: String,
+    /// Prop the fix time is read from, the start of the feature time range when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_prop: Option<String>,
+    /// Seconds of trail kept behind the head, the whole trail when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tail: Option<f64>,
+    /// Seconds an object is still drawn after its last fix, for as long as the data lasts when
+    /// not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<f64>,
+    /// Opacity of the oldest end of the trail, rising to full at the head.
+    pub fade: MagicValue<PropValue>,
+    pub line: MagicValue<LineRenderer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head: Option<MagicValue<PointRenderer>>,
+    /// Moves the head between fixes instead of holding it at the last one.
+    pub interpolate: bool,
+    /// Turns the head along the trail on screen, replacing its rotation.
+    pub rotate_head: bool,
+}
+
+impl std::default::Default for TrailRenderer {
+    fn default() -> Self {
+        TrailRenderer {
+            id_prop: "id".to_string(),
+            time_prop: None,
+            tail: None,
+            max_age: None,
+            fade: MagicValue::wrap(0f64),
+            line: LineRenderer::default().into(),
+            head: Some(PointRenderer::default().into()),
+            interpolate: true,
+            rotate_head: false,
+        }
+    }
+}
+
+impl MagicFetcher for TrailRenderer {
+    fn fetch(&mut self) -> Result<(), String> {
+        self.fade.fetch()?;
+        self.line.fetch()?;
+        if let Some(head) = self.head.as_mut() {
+            head.fetch()?;
+        }
+        Ok(())
+    }
+}
+
+impl MagicConverter for TrailRenderer {
+    fn convert(&mut self, _: &HashMap<String, PropValue>, _: &RenderContext) -> Result<(), String> {
+        // the trail and head are converted with the props of their latest fix in `draw`
+        Ok(())
+    }
+    fn prepare(&mut self, layer: &[&HashMap<String, PropValue>]) -> Result<(), String> {
+        self.fade.prepare(layer)?;
+        self.line.prepare(layer)?;
+        if let Some(head) = self.head.as_mut() {
+            head.prepare(layer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Position of an object at a time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrailFix {
+    pub time: f64,
+    pub point: Point,
+}
+
+impl TrailFix {
+    fn lerp(&self, other: &TrailFix, time: f64) -> TrailFix {
+        let span = other.time - self.time;
+        let t = if span > 0f64 {
+            ((time - self.time) / span).clamp(0f64, 1f64)
+        } else {
+            1f64
+        };
+        TrailFix {
+            time,
+            point: Point::new(
+                self.point.x() + (other.point.x() - self.point.x()) * t,
+                self.point.y() + (other.point.y() - self.point.y()) * t,
+            ),
+        }
+    }
+}
+
+impl TrailRenderer {
+    fn fix_time(&self, rendered_geometry: &RenderedGeometry) -> Option<f64> {
+        match &self.time_prop {
+            Some(time_prop) => rendered_geometry.props().get(time_prop)?.as_timestamp(),
+            None => rendered_geometry.time_range().map(|(start, _)| start),
+        }
+    }
+    fn object_id(&self, rendered_geometry: &RenderedGeometry) -> Option<String> {
+        rendered_geometry
+            .props()
+            .get(&self.id_prop)
+            .or(rendered_geometry.id())
+            .map(|id| id.to_string())
+    }
+    /// Visible part of a trail at `time` from fixes sorted by time, ending at the head.
+    pub fn visible_fixes(&self, fixes: &[TrailFix], time: f64) -> Vec<TrailFix> {
+        let past = fixes.partition_point(|fix| fix.time <= time);
+        if past == 0
+            || self
+                .max_age
+                .is_some_and(|max_age| time > fixes[fixes.len() - 1].time + max_age)
+        {
+            return Vec::new();
+        }
+        let mut visible = fixes[..past].to_vec();
+        if self.interpolate
+            && fixes[past - 1].time < time
+            && let Some(next) = fixes.get(past)
+        {
+            visible.push(fixes[past - 1].lerp(next, time));
+        }
+        if let Some(tail) = self.tail {
+            let start = time - tail;
+            let first = visible.partition_point(|fix| fix.time < start);
+            if first == visible.len() {
+                // the object stopped before the tail, only its head stays
+                return visible.split_off(visible.len() - 1);
+            }
+            if first > 0 {
+                let clipped = visible[first - 1].lerp(&visible[first], start);
+                visible.drain(..first);
+                if visible[0].time > start {
+                    visible.insert(0, clipped);
+                }
+            }
+        }
+        visible
+    }
+    pub fn draw(
+        &mut self,
+        scene: &mut Scene,
+        transform: Affine,
+        context: &RenderContext,
+        rendered_geometrys: &mut [RenderedGeometry],
+        geom_indices: &[usize],
+    ) -> Result<(), String> {
+        let mut objects: BTreeMap<String, Vec<(TrailFix, usize)>> = BTreeMap::new();
+        for geom_index in geom_indices {
+            let rendered_geometry = &mut rendered_geometrys[*geom_index];
+            if let Some(id) = self.object_id(rendered_geometry)
+                && let Some(time) = self.fix_time(rendered_geometry)
+                && let Some(point) = rendered_geometry.center_point(None)
+            {
+                let fix = TrailFix {
+                    time,
+                    point: *point,
+                };
+                objects.entry(id).or_default().push((fix, *geom_index));
+            }
+        }
+        let mut heads = Vec::new();
+        for (_, mut fixes) in objects {
+            fixes.sort_by(|(a, _), (b, _)| a.time.total_cmp(&b.time));
+            let latest = fixes.partition_point(|(fix, _)| fix.time <= context.time);
+            if latest == 0 {
+                continue;
+            }
+            let props = rendered_geometrys[fixes[latest - 1].1].props();
+            let fixes: Vec<TrailFix> = fixes.into_iter().map(|(fix, _)| fix).collect();
+            let visible = self.visible_fixes(&fixes, context.time);
+            let Some(head) = visible.last().copied() else {
+                continue;
+            };
+            self.fade.convert(props, context)?;
+            self.line.convert(props, context)?;
+            self.draw_trail(scene, transform, context, &visible)?;
+            if let Some(head_renderer) = self.head.as_mut() {
+                head_renderer.convert(props, context)?;
+                let mut head_renderer = head_renderer.as_ref().clone();
+                if self.rotate_head
+                    && let Some(direction) = visible
+                        .iter()
+                        .rev()
+                        .map(|fix| {
+                            screen_point(transform, head.point) - screen_point(transform, fix.point)
+                        })
+                        .find(|direction| direction.hypot2() > 0f64)
+                {
+                    head_renderer.rotation =
+                        MagicValue::wrap(direction.x.atan2(-direction.y).to_degrees());
+                }
+                heads.push((head_renderer, head.point));
+            }
+        }
+        // heads go over every trail
+        for (head_renderer, point) in heads {
+            head_renderer.draw(scene, transform, &point)?;
+        }
+        Ok(())
+    }
+    fn draw_trail(
+        &self,
+        scene: &mut Scene,
+        transform: Affine,
+        context: &RenderContext,
+        visible: &[TrailFix],
+    ) -> Result<(), String> {
+        if visible.len() < 2 {
+            return Ok(());
+        }
+        let to_coord = |fix: &TrailFix| Coord {
+            x: fix.point.x(),
+            y: fix.point.y(),
+        };
+        let mut line = self.line.as_ref().clone();
+        let fade: f64 = self.fade.inner_try_into()?;
+        let head_time = visible[visible.len() - 1].time;
+        let span = head_time - visible[0].time;
+        let trail: LineString = visible.iter().map(to_coord).collect();
+        if fade >= 1f64 || span <= 0f64 {
+            return line.draw(scene, transform, context, &trail);
+        }
+        let fade = fade.max(0f64);
+        let times: Vec<f64> = visible.iter().map(|fix| fix.time).collect();
+        line.draw_faded(scene, transform, context, &trail, &times, |time| {
+            fade + (1f64 - fade) * (1f64 - (head_time - time) / span)
+        })
+    }
+}
+
+/// Pixel position of a map point.
+fn screen_point(transform: Affine, point: Point) -> vello::kurbo::Point {
+    transform * vello::kurbo::Point::new(point.x(), point.y())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixes(times: &[f64]) -> Vec<TrailFix> {
+        times
+            .iter()
+            .map(|time| TrailFix {
+                time: *time,
+                point: Point::new(*time, 0f64),
+            })
+            .collect()
+    }
+
+    fn times(visible: &[TrailFix]) -> Vec<f64> {
+        visible.iter().map(|fix| fix.time).collect()
+    }
+
+    #[test]
+    fn nothing_before_the_first_fix() {
+        let trail = TrailRenderer::default();
+        assert!(
+            trail
+                .visible_fixes(&fixes(&[10f64, 20f64]), 5f64)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn head_moves_between_fixes() {
+        let trail = TrailRenderer::default();
+        let visible = trail.visible_fixes(&fixes(&[0f64, 10f64, 20f64]), 15f64);
+        assert_eq!(times(&visible), [0f64, 10f64, 15f64]);
+        assert_eq!(visible[2].point, Point::new(15f64, 0f64));
+    }
+
+    #[test]
+    fn head_holds_without_interpolation() {
+        let trail = TrailRenderer {
+            interpolate: false,
+            ..Default::default()
+        };
+        let visible = trail.visible_fixes(&fixes(&[0f64, 10f64, 20f64]), 15f64);
+        assert_eq!(times(&visible), [0f64, 10f64]);
+    }
+
+    #[test]
+    fn head_on_a_fix_is_not_repeated() {
+        let trail = TrailRenderer::default();
+        let visible = trail.visible_fixes(&fixes(&[0f64, 10f64, 20f64]), 10f64);
+        assert_eq!(times(&visible), [0f64, 10f64]);
+    }
+
+    #[test]
+    fn tail_is_clipped_between_fixes() {
+        let trail = TrailRenderer {
+            tail: Some(5f64),
+            ..Default::default()
+        };
+        let visible = trail.visible_fixes(&fixes(&[0f64, 10f64, 20f64]), 20f64);
+        assert_eq!(times(&visible), [15f64, 20f64]);
+        assert_eq!(visible[0].point, Point::new(15f64, 0f64));
+    }
+
+    #[test]
+    fn tail_on_a_fix_keeps_it() {
+        let trail = TrailRenderer {
+            tail: Some(10f64),
+            ..Default::default()
+        };
+        let visible = trail.visible_fixes(&fixes(&[0f64, 10f64, 20f64]), 20f64);
+        assert_eq!(times(&visible), [10f64, 20f64]);
+    }
+
+    #[test]
+    fn stopped_object_keeps_only_its_head() {
+        let trail = TrailRenderer {
+            tail: Some(5f64),
+            ..Default::default()
+        };
+        let visible = trail.visible_fixes(&fixes(&[0f64, 10f64]), 30f64);
+        assert_eq!(times(&visible), [10f64]);
+        assert_eq!(visible[0].point, Point::new(10f64, 0f64));
+    }
+
+    #[test]
+    fn objects_are_dropped_after_their_max_age() {
+        let trail = TrailRenderer {
+            tail: Some(5f64),
+            max_age: Some(15f64),
+            ..Default::default()
+        };
+        let track = fixes(&[0f64, 10f64]);
+        assert_eq!(times(&trail.visible_fixes(&track, 25f64)), [10f64]);
+        assert!(trail.visible_fixes(&track, 26f64).is_empty());
+        // a gap between fixes longer than the age keeps the object
+        let gap = fixes(&[0f64, 100f64]);
+        assert_eq!(times(&trail.visible_fixes(&gap, 50f64)), [45f64, 50f64]);
+    }
+
+    #[test]
+    fn whole_trail_without_tail() {
+        let trail = TrailRenderer::default();
+        let visible = trail.visible_fixes(&fixes(&[0f64, 10f64, 20f64]), 100f64);
+        assert_eq!(times(&visible), [0f64, 10f64, 20f64]);
+    }
+}
@@ -1,9 +1,9 @@
 use geello::{
-    MagicFetcher, MagicValue, RenderOption, RenderRegion, RenderedGeometry,
+    MagicFetcher, MagicValue, PropValue, RenderOption, RenderRegion, RenderedGeometry,
     utils::transform_4326_to_3857_point,
 };
 use geo::BoundingRect;
-use geojson::GeoJson;
+use geojson::{Feature, GeoJson, JsonObject, JsonValue, feature};
 use rocket::{
     Build, Rocket,
     fairing::AdHoc,
@@ -36,6 +36,8 @@ pub async fn rocket() -> Rocket<Build> {
     let mut rocket = rocket::custom(figment);
     rocket = rocket.attach(AdHoc::config::<Config>());
     let config: Config = rocket.figment().extract().expect("read config errors.");
+    geello::set_icon_dir(&config.icon_path);
+    geello::set_font_dir(&config.font_path);
     // rocket = rocket.mount("/data", FileServer::from(config.data_path));
     let mut context = vello::util::RenderContext::new();
     match context.device(None).await {
@@ -171,15 +173,17 @@ async fn web_map(path: PathBuf, config: &State<Config>) -> Result<NamedFile, Str
         .map_err(|e| format!("open index.html error: {}", e.to_string()))
 }
 
-#[get("/ws/anim?<param..>")]
+/// Replays from `time` with `speed` seconds of data time per second, 1 by default.
+#[get("/ws/anim?<speed>&<param..>")]
 async fn anim_real_time_websocket<'a>(
     ws: rocket_ws::WebSocket,
+    speed: Option<f64>,
     param: WebMapServiceQueryParam,
     device: &'a State<Device>,
     queue: &'a State<Queue>,
     config: &'a State<Config>,
     data_cache: &State<Arc<RwLock<DataCache>>>,
-) -> rocket_ws::Stream!['a] {
+) -> Result<rocket_ws::Stream!['a], String> {
     let WebMapServiceQueryParam {
         layers,
         styles,
@@ -187,13 +191,12 @@ async fn anim_real_time_websocket<'a>(
         height,
         format,
         bbox,
+        time,
     } = param;
-    let geojson = get_data_from_cache(config, &layers, data_cache, None)
-        .await
-        .expect("read data error.");
-    let mut render_option = get_style_from_cache(config, &styles, data_cache, None)
-        .await
-        .expect("read style error.");
+    let start_time = convert_time(time)?.unwrap_or_default();
+    let speed = speed.unwrap_or(1f64);
+    let geojson = get_data_from_cache(config, &layers, data_cache, None).await?;
+    let mut render_option = get_style_from_cache(config, &styles, data_cache, None).await?;
     render_option.pixel_option.width = width;
     render_option.pixel_option.height = height;
     render_option.region = convert_bbox(bbox, render_option.need_proj_geom);
@@ -216,33 +219,15 @@ async fn anim_real_time_websocket<'a>(
     let size = width * height * 4;
     let mut buffer = Vec::with_capacity(size as usize);
     let time_instant = Instant::now();
-    rocket_ws::Stream! { ws =>
+    Ok(ws.stream(move |ws| rocket::async_stream::try_stream! {
         for await message in ws {
             let message = message?;
             if message.to_string() == "exit" {
                 break;
             }
-            let i = time_instant.elapsed().as_secs() % 10;
-            render_option
-                .renderers
-                .iter_mut()
-                .for_each(|type_renderer| {
-                    let inner = type_renderer.as_mut();
-                    match inner {
-                        geello::GeometryRenderer::Point(_,point_renderer) => {
-                            let point_renderer = point_renderer.as_mut();
-                            if i == 0 {
-                                point_renderer.radius = MagicValue::wrap(0.1);
-                            }else{
-                                let i = i as f64;
-                                let old:f64 = point_renderer.radius.inner_try_into().unwrap();
-                                point_renderer.radius = MagicValue::wrap(old + 0.001 * i);
-                            }
-
-                        }
-                        _ => {}
-                    }
-                });
+            let elapsed = time_instant.elapsed().as_secs_f64();
+            render_option.time = start_time + elapsed * speed;
+            render_option.animation_time = elapsed;
             let image = render_wms_on_texture(&geojson, device, queue,&mut renderer,&texture,  &mut render_option).await.expect("render errors.");
 
             let mut cursor = Cursor::new(&mut buffer);
@@ -251,7 +236,7 @@ async fn anim_real_time_websocket<'a>(
                 .map_err(|e| format!("encode image faild: {}", e.to_string())).expect("encode errors.");
             yield cursor.into_inner().clone().into();
         }
-    }
+    }))
 }
 
 #[get("/wms?<param..>")]
@@ -269,12 +254,16 @@ async fn wms_real_time(
         height,
         format,
         bbox,
+        time,
     } = param;
     let geojson = get_data_from_cache(config, &layers, data_cache, None).await?;
     let mut render_option = get_style_from_cache(config, &styles, data_cache, None).await?;
     render_option.pixel_option.width = width;
     render_option.pixel_option.height = height;
     render_option.region = convert_bbox(bbox, render_option.need_proj_geom);
+    if let Some(time) = convert_time(time)? {
+        render_option.time = time;
+    }
     let image = render_wms(&geojson, device, queue, config, &mut render_option).await?;
     let image_format = convert_format(format);
     let size = image.width() * image.height() * 4;
@@ -595,6 +584,10 @@ struct StyleCache {
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct Config {
     data_path: PathBuf,
+    /// Icons named by styles and feature props must be inside this directory.
+    icon_path: PathBuf,
+    /// Fonts named by styles and feature props must be inside this directory.
+    font_path: PathBuf,
     wmts_texture_count: u32,
     shader_init_threads: Option<NonZero<usize>>,
     cache_path: PathBuf,
@@ -605,6 +598,8 @@ impl Default for Config {
     fn default() -> Config {
         Config {
             data_path: "assets".into(),
+            icon_path: "assets".into(),
+            font_path: "assets".into(),
             wmts_texture_count: 100,
             shader_init_threads: None,
             cache_path: "cache".into(),
@@ -628,6 +623,8 @@ struct WebMapServiceQueryParam {
     format: Option<String>,
     #[field(name = uncase("bbox"))]
     bbox: Option<String>,
+    #[field(name = uncase("time"))]
+    time: Option<String>,
 }
 
 #[derive(Debug, FromForm)]
@@ -660,6 +657,17 @@ fn convert_format(format_str: Option<String>) -> ImageFormat {
     }
 }
 
+/// Parses the WMS `TIME` parameter, an epoch seconds or RFC 3339 instant.
+fn convert_time(time_str: Option<String>) -> Result<Option<f64>, String> {
+    time_str
+        .map(|time_str| {
+            PropValue::String(time_str.clone())
+                .as_timestamp()
+                .ok_or(format!("invalid time: {}", time_str))
+        })
+        .transpose()
+}
+
 fn convert_bbox(bbox_str: Option<String>, need_proj: bool) -> RenderRegion {
     if bbox_str.is_none() {
         return RenderRegion::All;
@@ -994,7 +1002,7 @@ async fn render_geojson_to_buffer(
 }
 
 fn get_rendered_geometry(
-    geom_vec: Vec<(String, Vec<geo_types::Geometry>)>,
+    geom_vec: Vec<(String, Vec<GeomToRender>)>,
     option: &RenderOption,
 ) -> Vec<RenderedGeometry> {
     let proj = if option.need_proj_geom {
@@ -1004,18 +1012,23 @@ fn get_rendered_geometry(
     };
     let mut rendered_geom = Vec::new();
     for (layer, geom) in geom_vec {
-        for g in geom {
-            let rg =
-                RenderedGeometry::new(Some(layer.clone()), Default::default(), g.clone(), &proj);
+        for GeomToRender { geom, props, id } in geom {
+            let rg = RenderedGeometry::new(Some(layer.clone()), props, geom, &proj).with_id(id);
             rendered_geom.push(rg);
         }
     }
     rendered_geom
 }
 
+struct GeomToRender {
+    geom: geo_types::Geometry,
+    props: HashMap<String, PropValue>,
+    id: Option<PropValue>,
+}
+
 fn get_geom_from_geojson_vec(
     geojson: &Vec<(String, GeoJson)>,
-) -> Result<Vec<(String, Vec<geo_types::Geometry>)>, String> {
+) -> Result<Vec<(String, Vec<GeomToRender>)>, String> {
     let mut geom_to_render_vec = Vec::new();
     for (layer, geo) in geojson {
         let gg = get_geom_from_geojson(geo)?;
@@ -1024,22 +1037,42 @@ fn get_geom_from_geojson_vec(
     Ok(geom_to_render_vec)
 }
 
-fn get_geom_from_geojson(geojson: &GeoJson) -> Result<Vec<geo_types::Geometry>, String> {
+fn get_geom_from_feature(
+    feature: &Feature,
+    geom: &geojson::Geometry,
+) -> Result<GeomToRender, String> {
+    let geom = geo_types::Geometry::<f64>::try_from(geom)
+        .map_err(|e| format!("convert geometry error: {}", e))?;
+    let props = feature
+        .properties
+        .as_ref()
+        .map(convert_json_object)
+        .unwrap_or_default();
+    let id = feature.id.as_ref().map(|id| match id {
+        feature::Id::String(v) => PropValue::String(v.clone()),
+        feature::Id::Number(v) => convert_json_number(v),
+    });
+    Ok(GeomToRender { geom, props, id })
+}
+
+fn get_geom_from_geojson(geojson: &GeoJson) -> Result<Vec<GeomToRender>, String> {
     let mut geom_to_render_vec = Vec::new();
     match geojson {
         GeoJson::Geometry(geometry) => {
             let geom = geo_types::Geometry::<f64>::try_from(geometry)
-                .map_err(|e| format!("convert geometry error: {}", e.to_string()))?;
-            geom_to_render_vec.push(geom);
+                .map_err(|e| format!("convert geometry error: {}", e))?;
+            geom_to_render_vec.push(GeomToRender {
+                geom,
+                props: HashMap::new(),
+                id: None,
+            });
         }
         GeoJson::Feature(feature) => {
             let geom = feature
                 .geometry
                 .as_ref()
-                .ok_or_else(|| format!("only feature has no geometry"))?;
-            let geom = geo_types::Geometry::<f64>::try_from(geom)
-                .map_err(|e| format!("convert geometry error: {}", e.to_string()))?;
-            geom_to_render_vec.push(geom);
+                .ok_or_else(|| "only feature has no geometry".to_string())?;
+            geom_to_render_vec.push(get_geom_from_feature(feature, geom)?);
         }
         GeoJson::FeatureCollection(feature_collection) => {
             for (index, feature) in feature_collection.features.iter().enumerate() {
@@ -1047,11 +1080,120 @@ fn get_geom_from_geojson(geojson: &GeoJson) -> Result<Vec<geo_types::Geometry>,
                     .geometry
                     .as_ref()
                     .ok_or_else(|| format!("feature (index:{index}) has no geometry"))?;
-                let geom = geo_types::Geometry::<f64>::try_from(geom)
-                    .map_err(|e| format!("convert geometry error: {}", e.to_string()))?;
-                geom_to_render_vec.push(geom);
+                geom_to_render_vec.push(get_geom_from_feature(feature, geom)?);
             }
         }
     };
     Ok(geom_to_render_vec)
 }
+
+fn convert_json_object(object: &JsonObject) -> HashMap<String, PropValue> {
+    object
+        .iter()
+        .map(|(key, value)| (key.clone(), convert_json_value(value)))
+        .collect()
+}
+
+fn convert_json_number(number: &serde_json::Number) -> PropValue {
+    if let Some(v) = number.as_i64() {
+        PropValue::Int64(v)
+    } else {
+        PropValue::Float64(number.as_f64().unwrap_or(f64::NAN))
+    }
+}
+
+fn convert_json_value(value: &JsonValue) -> PropValue {
+    match value {
+        JsonValue::Null => PropValue::None,
+        JsonValue::Bool(v) => PropValue::Boolean(*v),
+        JsonValue::Number(v) => convert_json_number(v),
+        JsonValue::String(v) => PropValue::String(v.clone()),
+        JsonValue::Array(values) => {
+            PropValue::Array(values.iter().map(convert_json_value).collect())
+        }
+        JsonValue::Object(object) => PropValue::Object(convert_json_object(object)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(json: &str) -> GeomToRender {
+        let GeoJson::Feature(feature) = json.parse::<GeoJson>().unwrap() else {
+            panic!("not a feature");
+        };
+        get_geom_from_feature(&feature, feature.geometry.as_ref().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn nested_props_convert_recursively() {
+        let geom = feature(
+            r#"{"type":"Feature","geometry":{"type":"Point","coordinates":[1,2]},
+            "properties":{"tags":[1,[2.5,"a"],{"k":null}],"meta":{"b":true,"a":{"c":3}}}}"#,
+        );
+        assert_eq!(
+            geom.props["tags"],
+            PropValue::Array(vec![
+                PropValue::Int64(1),
+                PropValue::Array(vec![
+                    PropValue::Float64(2.5),
+                    PropValue::String("a".to_string())
+                ]),
+                PropValue::Object(HashMap::from([("k".to_string(), PropValue::None)])),
+            ])
+        );
+        let PropValue::Object(meta) = &geom.props["meta"] else {
+            panic!("meta is not an object");
+        };
+        assert_eq!(meta["b"], PropValue::Boolean(true));
+        assert_eq!(
+            meta["a"],
+            PropValue::Object(HashMap::from([("c".to_string(), PropValue::Int64(3))]))
+        );
+    }
+
+    #[test]
+    fn object_display_sorts_keys() {
+        let geom = feature(
+            r#"{"type":"Feature","geometry":{"type":"Point","coordinates":[1,2]},
+            "properties":{"meta":{"z":1,"b":"x","m":[true],"a":null}}}"#,
+        );
+        assert_eq!(
+            geom.props["meta"].to_string(),
+            r#"{"a": None, "b": "x", "m": [true], "z": 1}"#
+        );
+    }
+
+    #[test]
+    fn float_and_string_ids_are_kept() {
+        let float = feature(
+            r#"{"type":"Feature","id":1.5,"geometry":{"type":"Point","coordinates":[1,2]},
+            "properties":null}"#,
+        );
+        assert!(matches!(float.id, Some(PropValue::Float64(v)) if v == 1.5));
+        let int = feature(
+            r#"{"type":"Feature","id":7,"geometry":{"type":"Point","coordinates":[1,2]},
+            "properties":null}"#,
+        );
+        assert!(matches!(int.id, Some(PropValue::Int64(7))));
+        let string = feature(
+            r#"{"type":"Feature","id":"a","geometry":{"type":"Point","coordinates":[1,2]},
+            "properties":null}"#,
+        );
+        assert!(matches!(string.id, Some(PropValue::String(ref v)) if v == "a"));
+        assert!(float.props.is_empty());
+    }
+
+    #[test]
+    fn times_parse_as_epoch_seconds_or_rfc3339() {
+        assert_eq!(convert_time(None), Ok(None));
+        assert_eq!(convert_time(Some("1.5".into())), Ok(Some(1.5)));
+        assert_eq!(
+            convert_time(Some("1970-01-02T00:00:00+01:00".into())),
+            Ok(Some(82800f64))
+        );
+        assert!(convert_time(Some("tomorrow".into())).is_err());
+        assert!(convert_time(Some("NaN".into())).is_err());
+    }
+}
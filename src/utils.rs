@@ -1,14 +1,172 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
 use crate::render_option::TileProj;
-use geo::Rect;
-use geo::{Coord, Geometry, MapCoordsInPlace};
+use crate::{MagicConverter, PropValue};
+use geo::{Coord, Geometry, LineString, MapCoordsInPlace, Rect};
+use vello::kurbo::{self, Affine};
+use vello::peniko::Brush;
 const EARTH_RADIUS: f64 = 6378137.0;
 const PI: f64 = std::f64::consts::PI;
-const EPSG3857_XY_MAX: f64 = EARTH_RADIUS * PI;
+pub const EPSG3857_XY_MAX: f64 = EARTH_RADIUS * PI;
 
 pub fn is_default<T: Default + PartialEq>(value: &T) -> bool {
     value == &T::default()
 }
 
+/// Replaces every `{name}` in `template` with the prop of that name, missing props become empty.
+pub fn format_template(template: &str, props: &HashMap<String, PropValue>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => {
+                if let Some(value) = props.get(&after[..end]) {
+                    result.push_str(&value.to_string());
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Average length scale of `transform`, e.g. pixels per map unit.
+pub fn transform_scale(transform: Affine) -> f64 {
+    transform.determinant().abs().sqrt()
+}
+
+pub fn pixel_path(transform: Affine, line: &LineString) -> Vec<kurbo::Point> {
+    line.coords()
+        .map(|coord| transform * kurbo::Point::new(coord.x, coord.y))
+        .collect()
+}
+
+pub fn path_length(path: &[kurbo::Point]) -> f64 {
+    path.windows(2).map(|pair| pair[0].distance(pair[1])).sum()
+}
+
+/// Longest miter, in offset distances, before a sharp corner of an offset line is clipped.
+const OFFSET_MITER_LIMIT: f64 = 4f64;
+
+/// Parallel copy of `line` shifted `distance` to its left, negative distances shift it right.
+pub fn offset_line(line: &LineString, distance: f64) -> LineString {
+    let mut coords: Vec<Coord> = line.coords().copied().collect();
+    coords.dedup();
+    if coords.len() < 2 || distance == 0f64 {
+        return line.clone();
+    }
+    let closed = line.is_closed();
+    let normals: Vec<Coord> = coords
+        .windows(2)
+        .map(|pair| {
+            let (dx, dy) = (pair[1].x - pair[0].x, pair[1].y - pair[0].y);
+            let length = dx.hypot(dy);
+            Coord {
+                x: -dy / length,
+                y: dx / length,
+            }
+        })
+        .collect();
+    let last = coords.len() - 1;
+    coords
+        .iter()
+        .enumerate()
+        .map(|(index, coord)| {
+            let before = match index {
+                0 if closed => normals.last(),
+                0 => None,
+                _ => normals.get(index - 1),
+            };
+            let after = match index {
+                _ if index == last && closed => normals.first(),
+                _ => normals.get(index),
+            };
+            let normal = match (before, after) {
+                (Some(a), Some(b)) => {
+                    let cos = 1f64 + a.x * b.x + a.y * b.y;
+                    let sum = *a + *b;
+                    if cos * OFFSET_MITER_LIMIT * OFFSET_MITER_LIMIT < 2f64 {
+                        let length = sum.x.hypot(sum.y);
+                        if length == 0f64 {
+                            *a
+                        } else {
+                            sum * (OFFSET_MITER_LIMIT / length)
+                        }
+                    } else {
+                        sum / cos
+                    }
+                }
+                (Some(normal), None) | (None, Some(normal)) => *normal,
+                (None, None) => Coord::zero(),
+            };
+            *coord + normal * distance
+        })
+        .collect()
+}
+
+/// Position and direction angle at `distance` along `path`.
+pub fn point_along_path(path: &[kurbo::Point], distance: f64) -> Option<(kurbo::Point, f64)> {
+    let mut walked = 0f64;
+    for pair in path.windows(2) {
+        let segment = pair[1] - pair[0];
+        let length = segment.hypot();
+        if length == 0f64 {
+            continue;
+        }
+        if walked + length >= distance {
+            let t = ((distance - walked) / length).max(0f64);
+            return Some((pair[0].lerp(pair[1], t), segment.atan2()));
+        }
+        walked += length;
+    }
+    None
+}
+
+/// Brush of a colour ramp at `t`, interpolated between the stops around it.
+pub fn ramp_brush(ramp: &[(f64, Brush)], t: f64) -> Option<Brush> {
+    let upper = ramp
+        .iter()
+        .position(|(stop, _)| *stop >= t)
+        .unwrap_or(ramp.len().saturating_sub(1));
+    match upper {
+        0 => ramp.first().map(|(_, brush)| brush.clone()),
+        upper => {
+            let (lower_stop, lower) = &ramp[upper - 1];
+            let (upper_stop, upper) = &ramp[upper];
+            let span = upper_stop - lower_stop;
+            let t = if span > 0f64 {
+                ((t - lower_stop) / span).clamp(0f64, 1f64)
+            } else {
+                1f64
+            };
+            lower.interpolate(upper, t)
+        }
+    }
+}
+
+/// RGBA lookup table of a colour ramp for values from 0 to 1, interpolated between stops.
+pub fn ramp_table(ramp: &[(f64, Brush)]) -> Vec<[u8; 4]> {
+    (0..256)
+        .map(|index| match ramp_brush(ramp, index as f64 / 255f64) {
+            Some(Brush::Solid(color)) => {
+                let rgba = color.to_rgba8();
+                [rgba.r, rgba.g, rgba.b, rgba.a]
+            }
+            _ => [0u8; 4],
+        })
+        .collect()
+}
+
 pub fn get_rect_from_xyz(x: u32, y: u32, z: u32, proj: &TileProj) -> Rect {
     match proj {
         TileProj::EPSG3857 => get_rect_from_xyz_3857(x, y, z),
@@ -74,9 +232,79 @@ pub fn transform_4326_to_3857_point(x: f64, y: f64) -> (f64, f64) {
     (x, y)
 }
 
+/// Moves a point from `from` into `to` coordinates.
+pub fn transform_point(x: f64, y: f64, from: &TileProj, to: &TileProj) -> (f64, f64) {
+    match (from, to) {
+        (TileProj::EPSG4326, TileProj::EPSG3857) => transform_4326_to_3857_point(x, y),
+        (TileProj::EPSG3857, TileProj::EPSG4326) => transform_3857_to_4326_point(x, y),
+        _ => (x, y),
+    }
+}
+
 pub fn transform_3857_to_4326_point(x: f64, y: f64) -> (f64, f64) {
     let x = x * 180f64 / EPSG3857_XY_MAX;
     let y = y * 180f64 / EPSG3857_XY_MAX;
     let y = ((y * (PI / 180f64)).exp().atan() * 360f64) / PI - 90f64;
     (x, y)
 }
+
+/// Resolves `path` against `dir`, rejecting paths that lead out of it, since file names may
+/// come from feature props. `kind` names the files in errors.
+pub fn resolve_in_dir(dir: &Path, path: &str, kind: &str) -> Result<PathBuf, String> {
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| format!("Read {} directory error: {}", kind, e))?;
+    let resolved = dir
+        .join(path)
+        .canonicalize()
+        .map_err(|e| format!("Read {} from File:{} error: {}", kind, path, e))?;
+    if !resolved.starts_with(&dir) {
+        return Err(format!("File:{} is outside the {} directory", path, kind));
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corner(line: &LineString, distance: f64) -> Coord {
+        offset_line(line, distance).0[1]
+    }
+
+    #[test]
+    fn offset_corners_are_mitered_up_to_the_limit() {
+        // a right angle keeps its miter, sqrt(2) offsets from the corner
+        let line = LineString::from(vec![(0f64, 0f64), (10f64, 0f64), (10f64, 10f64)]);
+        assert_eq!(corner(&line, 1f64), Coord { x: 9f64, y: 1f64 });
+        assert_eq!(corner(&line, -1f64), Coord { x: 11f64, y: -1f64 });
+        // turning back at the limit, the miter is exactly the limit long
+        let (sin, cos) = (15f64.sqrt() / 8f64, 7f64 / 8f64);
+        let at_limit = LineString::from(vec![(0f64, 0f64), (10f64, 0f64), (10f64 - cos, sin)]);
+        let miter = corner(&at_limit, 1f64) - Coord { x: 10f64, y: 0f64 };
+        assert!((miter.x.hypot(miter.y) - OFFSET_MITER_LIMIT).abs() < 1e-9);
+        // sharper corners are clipped to the limit along the bisector
+        let sharp = LineString::from(vec![(0f64, 0f64), (10f64, 0f64), (0f64, 0.1)]);
+        let miter = corner(&sharp, 2f64) - Coord { x: 10f64, y: 0f64 };
+        assert!((miter.x.hypot(miter.y) - 2f64 * OFFSET_MITER_LIMIT).abs() < 1e-9);
+        assert!(miter.x < 0f64);
+        // a full reversal keeps the normal of the first segment
+        let back = LineString::from(vec![(0f64, 0f64), (10f64, 0f64), (5f64, 0f64)]);
+        assert_eq!(corner(&back, 1f64), Coord { x: 10f64, y: 1f64 });
+    }
+
+    #[test]
+    fn offset_rings_stay_closed() {
+        let ring = LineString::from(vec![
+            (0f64, 0f64),
+            (4f64, 0f64),
+            (4f64, 4f64),
+            (0f64, 4f64),
+            (0f64, 0f64),
+        ]);
+        let inner = offset_line(&ring, 1f64);
+        assert!(inner.is_closed());
+        assert_eq!(inner.0[0], Coord { x: 1f64, y: 1f64 });
+        assert_eq!(inner.0[2], Coord { x: 3f64, y: 3f64 });
+    }
+}